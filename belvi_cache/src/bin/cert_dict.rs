@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Trains dictionaries for compressing certificates, and reports how well stored certificates
+//! are compressed.
+//!
+//! Usage: `cert_dict <data path> ratio [samples]` or `cert_dict <data path> train [samples]`.
+//! Both use a random sample of certificates from the certificate store (10000 by default). After
+//! training, the scanner and frontend must be restarted to use the new dictionary.
+use belvi_cache::compress::{self, Dictionaries};
+use std::collections::BTreeMap;
+
+const DEFAULT_SAMPLES: u32 = 10_000;
+
+/// Stored certificates with random leaf hashes from the database, as stored and decompressed.
+async fn sample(count: u32) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let config = belvi_config::get();
+    let leaf_hashes: Vec<Vec<u8>> = {
+        let db = belvi_db::connect_readonly();
+        let mut query = db
+            .prepare("SELECT leaf_hash FROM certs ORDER BY random() LIMIT ?")
+            .unwrap();
+        let rows = query.query_map([count], |row| row.get(0)).unwrap();
+        rows.collect::<Result<_, _>>().unwrap()
+    };
+    let store = belvi_cache::open_backend(config)
+        .await
+        .expect("failed to open certificate store");
+    let dicts = Dictionaries::load(compress::dict_dir(&config.data_path))
+        .expect("failed to load dictionaries");
+    let mut certs = Vec::new();
+    for leaf_hash in leaf_hashes {
+        // the certificate might not have been stored, if storing certificates was turned off
+        if let Some(blob) = store.get_cert(&leaf_hash).await.unwrap() {
+            let der = dicts.decompress(&blob).expect("invalid stored certificate");
+            certs.push((blob, der));
+        }
+    }
+    certs
+}
+
+fn ratio(stored: usize, der: usize) -> f64 {
+    stored as f64 / der as f64 * 100.0
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    env_logger::init();
+    let config = belvi_config::get();
+    let count = config.args.get(1).map_or(DEFAULT_SAMPLES, |count| {
+        count.parse().expect("invalid sample count")
+    });
+    let mut dicts = Dictionaries::load(compress::dict_dir(&config.data_path))
+        .expect("failed to load dictionaries");
+    match config.args.first().map(String::as_str) {
+        Some("ratio") => {
+            let certs = sample(count).await;
+            let der: usize = certs.iter().map(|(_, der)| der.len()).sum();
+            let stored: usize = certs.iter().map(|(blob, _)| blob.len()).sum();
+            let recompressed: usize = certs
+                .iter()
+                .map(|(_, der)| dicts.compress(der).unwrap().len())
+                .sum();
+            // how the certificates are stored, by their first byte
+            let mut formats: BTreeMap<u8, usize> = BTreeMap::new();
+            for (blob, _) in &certs {
+                *formats.entry(blob[0]).or_default() += 1;
+            }
+            println!("Sampled {} certificates: {} bytes of DER", certs.len(), der);
+            for (format, count) in formats {
+                println!("  {} stored with format {:#04x}", count, format);
+            }
+            println!(
+                "Stored size: {} bytes ({:.1}% of DER)",
+                stored,
+                ratio(stored, der)
+            );
+            println!(
+                "With dictionary {:?}: {} bytes ({:.1}% of DER)",
+                dicts.latest(),
+                recompressed,
+                ratio(recompressed, der)
+            );
+        }
+        Some("train") => {
+            let samples: Vec<Vec<u8>> = sample(count)
+                .await
+                .into_iter()
+                .map(|(_, der)| der)
+                .collect();
+            println!("Training dictionary from {} certificates", samples.len());
+            let dict =
+                compress::train(&samples, compress::DICT_SIZE).expect("failed to train dictionary");
+            let version = dicts.add(&dict).expect("failed to save dictionary");
+            println!(
+                "Saved dictionary version {} ({} bytes)",
+                version,
+                dict.len()
+            );
+        }
+        _ => {
+            eprintln!("Usage: cert_dict <data path> ratio|train [samples]");
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,306 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Compression of certificates with zstd. A single certificate is too small to compress well by
+//! itself, but most of it is shared with other certificates: issuer names, AIA and CRL URLs,
+//! policy OIDs. A dictionary trained on a sample of stored certificates captures those.
+//!
+//! The first byte of each stored blob says how it is stored:
+//! - `0x30`: uncompressed DER. Every certificate starts with this (the tag of a SEQUENCE), so
+//!   certificates that were stored before compression was added are read as they are.
+//! - `0x01`: zstd, without a dictionary.
+//! - `0x02`: zstd, with the dictionary whose version follows as a big-endian u32.
+//!
+//! Dictionaries are kept in the `cert_dicts` directory of the data path, as `<version>.dict`.
+//! New certificates are compressed with the newest one, but older ones must be kept for as long
+//! as anything compressed with them is stored.
+use crate::{CertStore, NewCert, StoreError};
+use async_trait::async_trait;
+use log::{debug, info};
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::Read,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+use zstd::{bulk::Compressor, dict::DecoderDictionary};
+
+const FORMAT_DER: u8 = 0x30;
+const FORMAT_ZSTD: u8 = 0x01;
+const FORMAT_ZSTD_DICT: u8 = 0x02;
+
+/// Compression level used for certificates. Higher levels barely help with a dictionary.
+const LEVEL: i32 = 3;
+/// Size of trained dictionaries. Larger dictionaries compress slightly better, but use more
+/// memory in every process that has them loaded.
+pub const DICT_SIZE: usize = 64 * 1024;
+
+pub fn dict_dir(data_path: &Path) -> PathBuf {
+    data_path.join("cert_dicts")
+}
+
+/// Trains a dictionary from a sample of certificates.
+pub fn train(samples: &[Vec<u8>], max_size: usize) -> Result<Vec<u8>, StoreError> {
+    Ok(zstd::dict::from_samples(samples, max_size)?)
+}
+
+/// All versions of the dictionary, and a compressor for the newest one.
+pub struct Dictionaries {
+    dir: PathBuf,
+    decoders: BTreeMap<u32, DecoderDictionary<'static>>,
+    compressor: Mutex<Compressor<'static>>,
+}
+
+impl fmt::Debug for Dictionaries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dictionaries")
+            .field("dir", &self.dir)
+            .field("versions", &self.decoders.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Dictionaries {
+    /// Loads the dictionaries in `dir`. It is fine for `dir` to not exist, in which case
+    /// certificates are compressed without a dictionary.
+    pub fn load(dir: PathBuf) -> Result<Self, StoreError> {
+        let mut dicts = BTreeMap::new();
+        if dir.exists() {
+            for entry in fs::read_dir(&dir)? {
+                let path = entry?.path();
+                let version = match path
+                    .file_name()
+                    .and_then(|name| name.to_str())
+                    .and_then(|name| name.strip_suffix(".dict"))
+                    .and_then(|version| version.parse::<u32>().ok())
+                {
+                    Some(version) => version,
+                    None => continue,
+                };
+                dicts.insert(version, fs::read(&path)?);
+            }
+        }
+        debug!("Loaded certificate dictionaries {:?}", dicts.keys());
+        let compressor = match dicts.values().next_back() {
+            Some(dict) => Compressor::with_dictionary(LEVEL, dict)?,
+            None => Compressor::new(LEVEL)?,
+        };
+        Ok(Self {
+            dir,
+            decoders: dicts
+                .iter()
+                .map(|(version, dict)| (*version, DecoderDictionary::copy(dict)))
+                .collect(),
+            compressor: Mutex::new(compressor),
+        })
+    }
+
+    /// Version of the dictionary used to compress new certificates.
+    pub fn latest(&self) -> Option<u32> {
+        self.decoders.keys().next_back().copied()
+    }
+
+    /// Saves a new version of the dictionary, and uses it for new certificates.
+    pub fn add(&mut self, dict: &[u8]) -> Result<u32, StoreError> {
+        let version = self.latest().unwrap_or(0) + 1;
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("{}.dict", version));
+        let temp = path.with_extension("dict.tmp");
+        fs::write(&temp, dict)?;
+        fs::rename(&temp, &path)?;
+        info!(
+            "Saved certificate dictionary version {} to {:?}",
+            version, path
+        );
+        self.decoders.insert(version, DecoderDictionary::copy(dict));
+        self.compressor = Mutex::new(Compressor::with_dictionary(LEVEL, dict)?);
+        Ok(version)
+    }
+
+    pub fn compress(&self, der: &[u8]) -> Result<Vec<u8>, StoreError> {
+        let compressed = self.compressor.lock().unwrap().compress(der)?;
+        let mut blob = Vec::with_capacity(compressed.len() + 5);
+        match self.latest() {
+            Some(version) => {
+                blob.push(FORMAT_ZSTD_DICT);
+                blob.extend_from_slice(&version.to_be_bytes());
+            }
+            None => blob.push(FORMAT_ZSTD),
+        }
+        blob.extend_from_slice(&compressed);
+        Ok(blob)
+    }
+
+    pub fn decompress(&self, blob: &[u8]) -> Result<Vec<u8>, StoreError> {
+        let mut der = Vec::new();
+        match blob.first() {
+            Some(&FORMAT_DER) => return Ok(blob.to_vec()),
+            Some(&FORMAT_ZSTD) => {
+                zstd::stream::Decoder::new(&blob[1..])?.read_to_end(&mut der)?;
+            }
+            Some(&FORMAT_ZSTD_DICT) if blob.len() >= 5 => {
+                let version = u32::from_be_bytes(blob[1..5].try_into().unwrap());
+                let dict = self
+                    .decoders
+                    .get(&version)
+                    .ok_or(StoreError::MissingDictionary(version))?;
+                zstd::stream::Decoder::with_prepared_dictionary(&blob[5..], dict)?
+                    .read_to_end(&mut der)?;
+            }
+            _ => return Err(StoreError::UnknownFormat),
+        }
+        Ok(der)
+    }
+}
+
+/// Compresses certificates before they are stored in another store. Certificates are always
+/// decompressed, so compression can be turned off without making stored certificates unreadable.
+#[derive(Debug)]
+pub struct CompressedStore {
+    inner: Arc<dyn CertStore>,
+    dicts: Dictionaries,
+    compress: bool,
+}
+
+impl CompressedStore {
+    pub fn new(inner: Arc<dyn CertStore>, dicts: Dictionaries, compress: bool) -> Self {
+        Self {
+            inner,
+            dicts,
+            compress,
+        }
+    }
+}
+
+#[async_trait]
+impl CertStore for CompressedStore {
+    async fn get_cert(&self, id: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        match self.inner.get_cert(id).await? {
+            Some(blob) => Ok(Some(self.dicts.decompress(&blob)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn new_cert(&self, cert: NewCert<'_>) -> Result<(), StoreError> {
+        if self.compress {
+            self.inner.new_cert(NewCert {
+                content: &self.dicts.compress(cert.content)?,
+                ..cert
+            })
+        } else {
+            self.inner.new_cert(cert)
+        }
+    }
+
+    async fn contains_certs(&self, ids: &[&[u8]]) -> Result<Vec<bool>, StoreError> {
+        self.inner.contains_certs(ids).await
+    }
+
+    async fn put_certs(&self, certs: &[NewCert<'_>]) -> Result<(), StoreError> {
+        if !self.compress {
+            return self.inner.put_certs(certs).await;
+        }
+        let blobs = certs
+            .iter()
+            .map(|cert| self.dicts.compress(cert.content))
+            .collect::<Result<Vec<_>, StoreError>>()?;
+        let compressed: Vec<NewCert> = certs
+            .iter()
+            .zip(&blobs)
+            .map(|(cert, blob)| NewCert {
+                content: blob,
+                ..*cert
+            })
+            .collect();
+        self.inner.put_certs(&compressed).await
+    }
+
+    async fn new_certs(&self, certs: &[NewCert<'_>]) -> Result<usize, StoreError> {
+        // check first, so that certificates that are already stored aren't compressed
+        let missing = crate::missing_certs(self, certs).await?;
+        self.put_certs(&missing).await?;
+        Ok(missing.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MemoryStore;
+
+    /// Things that look a bit like certificates, with a lot in common.
+    fn samples() -> Vec<Vec<u8>> {
+        (0..2000)
+            .map(|i| {
+                let mut cert = vec![FORMAT_DER, 0x82];
+                cert.extend_from_slice(format!("CN=Example Issuing CA {}, O=Example Trust Services, C=US; not before 2022-{:02}-01; subject CN=host{}.example.com; http://ocsp.example.com/ http://crl.example.com/ca{}.crl 2.23.140.1.2.1 1.3.6.1.4.1.44947.1.1.1", i % 4, i % 12 + 1, i * 7919, i % 4).as_bytes());
+                cert
+            })
+            .collect()
+    }
+
+    #[test]
+    fn versioned_dictionaries() {
+        let dir = crate::test_dir("dicts").join("cert_dicts");
+        let samples = samples();
+        let mut dicts = Dictionaries::load(dir.clone()).unwrap();
+        assert_eq!(dicts.latest(), None);
+
+        // no dictionary
+        let plain = dicts.compress(&samples[0]).unwrap();
+        assert_eq!(plain[0], FORMAT_ZSTD);
+        assert_eq!(dicts.decompress(&plain).unwrap(), samples[0]);
+        // uncompressed certificates are read as they are
+        assert_eq!(dicts.decompress(&samples[0]).unwrap(), samples[0]);
+
+        let dict = train(&samples, 4096).unwrap();
+        assert_eq!(dicts.add(&dict).unwrap(), 1);
+        let v1 = dicts.compress(&samples[1]).unwrap();
+        assert_eq!(&v1[..5], &[FORMAT_ZSTD_DICT, 0, 0, 0, 1]);
+        assert!(v1.len() < plain.len());
+
+        let dict = train(&samples[1000..], 4096).unwrap();
+        assert_eq!(dicts.add(&dict).unwrap(), 2);
+        let v2 = dicts.compress(&samples[2]).unwrap();
+        assert_eq!(&v2[..5], &[FORMAT_ZSTD_DICT, 0, 0, 0, 2]);
+
+        // blobs compressed with all versions can be read after loading again
+        let dicts = Dictionaries::load(dir).unwrap();
+        assert_eq!(dicts.latest(), Some(2));
+        assert_eq!(dicts.decompress(&plain).unwrap(), samples[0]);
+        assert_eq!(dicts.decompress(&v1).unwrap(), samples[1]);
+        assert_eq!(dicts.decompress(&v2).unwrap(), samples[2]);
+
+        let mut missing = v2.clone();
+        missing[4] = 3;
+        assert!(matches!(
+            dicts.decompress(&missing),
+            Err(StoreError::MissingDictionary(3))
+        ));
+        assert!(matches!(
+            dicts.decompress(&[0x42]),
+            Err(StoreError::UnknownFormat)
+        ));
+    }
+
+    #[tokio::test]
+    async fn compressed_store() {
+        let inner = Arc::new(MemoryStore::default());
+        let dicts = Dictionaries::load(crate::test_dir("compressed").join("cert_dicts")).unwrap();
+        let store = CompressedStore::new(inner.clone(), dicts, true);
+        crate::check_store(&store).await;
+        let cert = &samples()[0];
+        store.new_cert(crate::test_cert(&[9; 16], cert)).unwrap();
+        assert_eq!(
+            inner.get_cert(&[9; 16]).await.unwrap().unwrap()[0],
+            FORMAT_ZSTD
+        );
+        assert_eq!(store.get_cert(&[9; 16]).await.unwrap().as_ref(), Some(cert));
+
+        // certificates stored before compression are still readable
+        inner.new_cert(crate::test_cert(&[10; 16], cert)).unwrap();
+        assert_eq!(
+            store.get_cert(&[10; 16]).await.unwrap().as_ref(),
+            Some(cert)
+        );
+    }
+}
@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: Apache-2.0
+use crate::{CertStore, NewCert, StoreError};
+use async_trait::async_trait;
+use log::{debug, trace};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// Stores each certificate in a file named after its leaf hash. Files are sharded into
+/// directories by the first two bytes of the hash, so that no directory gets too large:
+/// `ab/cd/abcd...`.
+#[derive(Debug)]
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    pub fn open(root: PathBuf) -> Result<Self, StoreError> {
+        if !root.exists() {
+            debug!("creating certificate directory {:?}", root);
+            fs::create_dir_all(&root)?;
+        }
+        Ok(Self { root })
+    }
+
+    fn path(&self, id: &[u8]) -> PathBuf {
+        let name = hex::encode(id);
+        self.root.join(&name[0..2]).join(&name[2..4]).join(name)
+    }
+}
+
+/// Writes a file so that readers never see it partially written.
+fn write_atomic(path: &Path, content: &[u8]) -> io::Result<()> {
+    let temp = path.with_extension(format!("tmp{}", std::process::id()));
+    fs::write(&temp, content)?;
+    fs::rename(&temp, path)
+}
+
+#[async_trait]
+impl CertStore for FsStore {
+    async fn get_cert(&self, id: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        // certificates are small, so reading them synchronously is fine
+        match fs::read(self.path(id)) {
+            Ok(content) => Ok(Some(content)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn new_cert(&self, cert: NewCert<'_>) -> Result<(), StoreError> {
+        let path = self.path(cert.id);
+        // the path depends only on the content, so an existing file doesn't need to be replaced
+        if path.exists() {
+            return Ok(());
+        }
+        trace!("adding cert to {:?}, {} bytes", path, cert.content.len());
+        fs::create_dir_all(path.parent().unwrap())?;
+        write_atomic(&path, cert.content)?;
+        Ok(())
+    }
+
+    async fn contains_certs(&self, ids: &[&[u8]]) -> Result<Vec<bool>, StoreError> {
+        Ok(ids.iter().map(|id| self.path(id).exists()).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn store() {
+        let dir = crate::test_dir("fs");
+        let store = FsStore::open(dir.join("certs")).unwrap();
+        crate::check_store(&store).await;
+        assert!(dir
+            .join("certs/07/07/07070707070707070707070707070707")
+            .is_file());
+    }
+}
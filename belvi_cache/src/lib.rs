@@ -1,45 +1,194 @@
 // SPDX-License-Identifier: Apache-2.0
-use log::trace;
-use redis_async::{client::paired, resp_array};
-use std::fmt;
+//! Storage for the bodies of certificates. The database only has metadata about certificates,
+//! so the certificates themselves are kept in a [`CertStore`], keyed by their leaf hash.
+//!
+//! Which store is used is set by `cert_store` in the configuration, and the scanner and frontend
+//! must use the same one. Certificates are compressed before they are stored (see [`compress`]).
+use async_trait::async_trait;
+use belvi_config::{CertStoreKind, Config};
+use std::{collections::HashSet, fmt, sync::Arc};
 
-pub struct Connection {
-    inner: paired::PairedConnection,
+pub mod compress;
+mod fs;
+mod memory;
+mod redis;
+pub mod retention;
+mod sqlite;
+pub mod stats;
+
+pub use self::{
+    compress::CompressedStore, fs::FsStore, memory::MemoryStore, redis::RedisStore,
+    sqlite::SqliteStore,
+};
+
+#[derive(Debug)]
+#[allow(dead_code)] // Debug trait is ignored for dead code analysis, but some fields are only here for better messages
+pub enum StoreError {
+    Io(std::io::Error),
+    Redis(redis_async::error::Error),
+    Sqlite(rusqlite::Error),
+    /// A stored certificate was compressed with a dictionary that doesn't exist.
+    MissingDictionary(u32),
+    /// A stored certificate doesn't start with a known format byte.
+    UnknownFormat,
 }
 
-impl fmt::Debug for Connection {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.debug_struct("Connection")
-            .field("inner", &"[redis connection]".to_string())
-            .finish()
+impl From<std::io::Error> for StoreError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
     }
 }
 
-const OBJECT_PREFIX: &[u8] = b"o:";
+impl From<redis_async::error::Error> for StoreError {
+    fn from(err: redis_async::error::Error) -> Self {
+        Self::Redis(err)
+    }
+}
 
-impl Connection {
-    pub async fn new() -> Self {
-        let client = paired::paired_connect("127.0.0.1:6379").await.unwrap();
-        Self { inner: client }
+impl From<rusqlite::Error> for StoreError {
+    fn from(err: rusqlite::Error) -> Self {
+        Self::Sqlite(err)
     }
+}
 
-    pub async fn get_cert(&mut self, id: &[u8]) -> Option<Vec<u8>> {
-        self.inner
-            .send(resp_array!["GET", [OBJECT_PREFIX, id].concat()])
-            .await
-            .unwrap()
+/// A certificate to store.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NewCert<'a> {
+    /// Leaf hash of the certificate.
+    pub id: &'a [u8],
+    pub content: &'a [u8],
+    /// When the certificate expires, as a Unix timestamp. Caches can drop it after this.
+    pub not_after: i64,
+}
+
+#[async_trait]
+pub trait CertStore: fmt::Debug + Send + Sync {
+    /// Gets the certificate with the leaf hash `id`, if it is stored.
+    async fn get_cert(&self, id: &[u8]) -> Result<Option<Vec<u8>>, StoreError>;
+
+    /// Stores a certificate. This can block, but shouldn't take long.
+    fn new_cert(&self, cert: NewCert<'_>) -> Result<(), StoreError>;
+
+    /// Checks which of the certificates are stored.
+    async fn contains_certs(&self, ids: &[&[u8]]) -> Result<Vec<bool>, StoreError> {
+        let mut found = Vec::with_capacity(ids.len());
+        for id in ids {
+            found.push(self.get_cert(id).await?.is_some());
+        }
+        Ok(found)
     }
 
-    pub fn new_cert(&mut self, id: &[u8], content: &[u8]) {
-        trace!("adding cert to Redis: {:?}, {} bytes", id, content.len());
-        self.inner
-            .send_and_forget(resp_array!["SET", [OBJECT_PREFIX, id].concat(), content]);
-        trace!("added cert to Redis: {:?}, {} bytes", id, content.len());
+    /// Stores several certificates, whether or not they are already stored. Stores should do
+    /// this in fewer round trips than storing each certificate separately.
+    async fn put_certs(&self, certs: &[NewCert<'_>]) -> Result<(), StoreError> {
+        for cert in certs {
+            self.new_cert(*cert)?;
+        }
+        Ok(())
     }
 
-    /// Lists the keys for all certificates in the database.
-    /// Should be used for testing only, this is not fast.
-    pub async fn cached_cert_key_list(&mut self) -> Vec<Vec<u8>> {
-        self.inner.send(resp_array!["KEYS", "*"]).await.unwrap()
+    /// Stores the certificates that aren't stored yet, and returns how many were stored. This is
+    /// used by the scanner for every batch of entries, which are mostly certificates that were
+    /// already seen in other logs.
+    async fn new_certs(&self, certs: &[NewCert<'_>]) -> Result<usize, StoreError> {
+        let missing = missing_certs(self, certs).await?;
+        self.put_certs(&missing).await?;
+        Ok(missing.len())
     }
 }
+
+/// The certificates that aren't in `store`. Duplicates are only included once.
+async fn missing_certs<'a, S: CertStore + ?Sized>(
+    store: &S,
+    certs: &[NewCert<'a>],
+) -> Result<Vec<NewCert<'a>>, StoreError> {
+    let ids: Vec<&[u8]> = certs.iter().map(|cert| cert.id).collect();
+    let found = store.contains_certs(&ids).await?;
+    let mut seen = HashSet::new();
+    Ok(certs
+        .iter()
+        .zip(found)
+        .filter(|(cert, found)| !found && seen.insert(cert.id))
+        .map(|(cert, _)| *cert)
+        .collect())
+}
+
+/// Opens the store set in the configuration, compressing certificates if `compress_certs` is set.
+pub async fn open(config: &Config) -> Result<Arc<dyn CertStore>, StoreError> {
+    let dicts = compress::Dictionaries::load(compress::dict_dir(&config.data_path))?;
+    Ok(Arc::new(CompressedStore::new(
+        open_backend(config).await?,
+        dicts,
+        config.compress_certs,
+    )))
+}
+
+/// Opens the store set in the configuration, without compression. Certificates from this store
+/// must be decompressed.
+pub async fn open_backend(config: &Config) -> Result<Arc<dyn CertStore>, StoreError> {
+    let retention = retention::Retention::from_config(config);
+    Ok(match config.cert_store {
+        CertStoreKind::Redis => Arc::new(RedisStore::connect(&config.redis_addr, retention).await?),
+        CertStoreKind::Filesystem => Arc::new(FsStore::open(config.data_path.join("certs"))?),
+        CertStoreKind::Sqlite => Arc::new(SqliteStore::open(config.data_path.join("certs.db"))?),
+        CertStoreKind::Memory => Arc::new(MemoryStore::new(retention)),
+    })
+}
+
+/// A certificate for tests, which expires in 2100.
+#[cfg(test)]
+fn test_cert<'a>(id: &'a [u8], content: &'a [u8]) -> NewCert<'a> {
+    NewCert {
+        id,
+        content,
+        not_after: 4_102_444_800,
+    }
+}
+
+/// Checks that a store returns what was stored in it.
+#[cfg(test)]
+async fn check_store(store: &dyn CertStore) {
+    let id = [7; 16];
+    assert!(store.get_cert(&id).await.unwrap().is_none());
+    store.new_cert(test_cert(&id, b"cert")).unwrap();
+    assert_eq!(store.get_cert(&id).await.unwrap(), Some(b"cert".to_vec()));
+    // storing the same certificate again is fine
+    store.new_cert(test_cert(&id, b"cert")).unwrap();
+    assert_eq!(store.get_cert(&id).await.unwrap(), Some(b"cert".to_vec()));
+    store.new_cert(test_cert(&[8; 16], b"other")).unwrap();
+    assert_eq!(store.get_cert(&id).await.unwrap(), Some(b"cert".to_vec()));
+    assert_eq!(
+        store.get_cert(&[8; 16]).await.unwrap(),
+        Some(b"other".to_vec())
+    );
+
+    let ids: Vec<[u8; 16]> = (10..15).map(|i| [i; 16]).collect();
+    let certs: Vec<NewCert> = ids
+        .iter()
+        .map(|id| test_cert(id, b"batched"))
+        .chain([test_cert(&id, b"changed"), test_cert(&ids[0], b"batched")])
+        .collect();
+    // only new certificates are stored, and only once
+    assert_eq!(store.new_certs(&certs).await.unwrap(), 5);
+    assert_eq!(store.new_certs(&certs).await.unwrap(), 0);
+    assert_eq!(store.get_cert(&id).await.unwrap(), Some(b"cert".to_vec()));
+    assert_eq!(
+        store
+            .contains_certs(&[&ids[4], &[20; 16], &id])
+            .await
+            .unwrap(),
+        vec![true, false, true]
+    );
+    assert_eq!(
+        store.get_cert(&ids[3]).await.unwrap(),
+        Some(b"batched".to_vec())
+    );
+}
+
+#[cfg(test)]
+fn test_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(format!("belvi-cache-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
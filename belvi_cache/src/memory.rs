@@ -0,0 +1,202 @@
+// SPDX-License-Identifier: Apache-2.0
+use crate::{
+    retention::{unix_now, Retention},
+    stats::STATS,
+    CertStore, NewCert, StoreError,
+};
+use async_trait::async_trait;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Mutex,
+};
+
+/// Stores certificates in memory. They are lost when the process exits, and aren't shared
+/// between the scanner and the frontend, so this is only useful for development and tests.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    retention: Retention,
+    inner: Mutex<Inner>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    content: Vec<u8>,
+    /// Unix time when the entry is dropped.
+    expires: Option<i64>,
+    /// Value of the clock when the entry was last stored or viewed.
+    used: u64,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    certs: HashMap<Vec<u8>, Entry>,
+    /// IDs of certificates by when they were last used, least recently used first.
+    lru: BTreeMap<u64, Vec<u8>>,
+    /// Incremented whenever an entry is used.
+    clock: u64,
+    /// Total size of stored certificates.
+    bytes: u64,
+}
+
+impl Inner {
+    fn remove(&mut self, id: &[u8]) {
+        if let Some(entry) = self.certs.remove(id) {
+            self.lru.remove(&entry.used);
+            self.bytes -= entry.content.len() as u64;
+        }
+    }
+
+    /// Gets an entry that hasn't expired.
+    fn get(&mut self, id: &[u8], now: i64) -> Option<&mut Entry> {
+        let expired = match self.certs.get(id) {
+            Some(entry) => matches!(entry.expires, Some(expires) if expires <= now),
+            None => return None,
+        };
+        if expired {
+            self.remove(id);
+            STATS.evict();
+            return None;
+        }
+        self.certs.get_mut(id)
+    }
+
+    /// Marks an entry as recently used.
+    fn touch(&mut self, id: &[u8], retention: &Retention, now: i64) {
+        self.clock += 1;
+        let clock = self.clock;
+        let entry = self.certs.get_mut(id).unwrap();
+        self.lru.remove(&entry.used);
+        entry.used = clock;
+        self.lru.insert(clock, id.to_vec());
+        // the expiry isn't moved past when the certificate expires
+        if let (Some(ttl), false) = (retention.ttl, retention.unexpired_only) {
+            entry.expires = Some(now + ttl.as_secs() as i64);
+        }
+    }
+
+    fn insert(&mut self, cert: NewCert<'_>, retention: &Retention, now: i64) {
+        let expires = match retention.keep_for(cert.not_after, now) {
+            Some(0) => return,
+            Some(secs) => Some(now + secs as i64),
+            None => None,
+        };
+        self.remove(cert.id);
+        self.clock += 1;
+        self.certs.insert(
+            cert.id.to_vec(),
+            Entry {
+                content: cert.content.to_vec(),
+                expires,
+                used: self.clock,
+            },
+        );
+        self.lru.insert(self.clock, cert.id.to_vec());
+        self.bytes += cert.content.len() as u64;
+        if let Some(max_bytes) = retention.max_bytes {
+            while self.bytes > max_bytes {
+                let id = self.lru.values().next().unwrap().clone();
+                self.remove(&id);
+                STATS.evict();
+            }
+        }
+    }
+}
+
+impl MemoryStore {
+    pub fn new(retention: Retention) -> Self {
+        Self {
+            retention,
+            inner: Mutex::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl CertStore for MemoryStore {
+    async fn get_cert(&self, id: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        let now = unix_now();
+        let mut inner = self.inner.lock().unwrap();
+        let content = match inner.get(id, now) {
+            Some(entry) => entry.content.clone(),
+            None => return Ok(None),
+        };
+        inner.touch(id, &self.retention, now);
+        Ok(Some(content))
+    }
+
+    fn new_cert(&self, cert: NewCert<'_>) -> Result<(), StoreError> {
+        self.inner
+            .lock()
+            .unwrap()
+            .insert(cert, &self.retention, unix_now());
+        Ok(())
+    }
+
+    async fn contains_certs(&self, ids: &[&[u8]]) -> Result<Vec<bool>, StoreError> {
+        let now = unix_now();
+        let mut inner = self.inner.lock().unwrap();
+        Ok(ids.iter().map(|id| inner.get(id, now).is_some()).collect())
+    }
+
+    async fn put_certs(&self, certs: &[NewCert<'_>]) -> Result<(), StoreError> {
+        let now = unix_now();
+        let mut inner = self.inner.lock().unwrap();
+        for cert in certs {
+            inner.insert(*cert, &self.retention, now);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn store() {
+        crate::check_store(&MemoryStore::default()).await;
+    }
+
+    #[test]
+    fn retention() {
+        let cert = |id: &'static [u8], not_after| NewCert {
+            id,
+            content: b"0123456789",
+            not_after,
+        };
+        let retention = Retention {
+            ttl: Some(Duration::from_secs(60)),
+            max_bytes: Some(30),
+            unexpired_only: false,
+        };
+        let mut inner = Inner::default();
+        for id in [b"a", b"b", b"c"] {
+            inner.insert(cert(id, 0), &retention, 1000);
+        }
+        // viewing a makes b the least recently used
+        assert!(inner.get(b"a", 1000).is_some());
+        inner.touch(b"a", &retention, 1010);
+        inner.insert(cert(b"d", 0), &retention, 1020);
+        assert_eq!(inner.bytes, 30);
+        assert!(inner.get(b"b", 1020).is_none());
+        assert!(inner.get(b"c", 1020).is_some());
+        // c expires 60 seconds after it was stored, a 60 seconds after it was viewed
+        assert!(inner.get(b"c", 1060).is_none());
+        assert!(inner.get(b"a", 1060).is_some());
+        assert!(inner.get(b"a", 1070).is_none());
+        assert_eq!(inner.certs.len(), 1);
+        assert_eq!(inner.lru.len(), 1);
+
+        let unexpired = Retention {
+            unexpired_only: true,
+            ..Retention::default()
+        };
+        let mut inner = Inner::default();
+        inner.insert(cert(b"old", 999), &unexpired, 1000);
+        inner.insert(cert(b"new", 2000), &unexpired, 1000);
+        assert!(inner.get(b"old", 1000).is_none());
+        assert!(inner.get(b"new", 1999).is_some());
+        assert!(inner.get(b"new", 2000).is_none());
+    }
+}
@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: Apache-2.0
+use crate::{
+    retention::{unix_now, Retention},
+    CertStore, NewCert, StoreError,
+};
+use async_trait::async_trait;
+use log::trace;
+use redis_async::{client::paired, resp::RespValue, resp_array};
+use std::fmt;
+
+/// Stores certificates in Redis. Redis keeps everything in memory, so this is fast, but
+/// certificates can be lost if Redis isn't configured to persist them.
+///
+/// Certificates are dropped by Redis: they are stored with an expiry if there is a TTL or only
+/// unexpired certificates are kept. The size of the cache is limited by the server's own
+/// `maxmemory` and `maxmemory-policy`, which are left for the operator to set, since the server
+/// might be shared.
+pub struct RedisStore {
+    inner: paired::PairedConnection,
+    retention: Retention,
+}
+
+impl fmt::Debug for RedisStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedisStore")
+            .field("inner", &"[redis connection]".to_string())
+            .finish()
+    }
+}
+
+const OBJECT_PREFIX: &[u8] = b"o:";
+/// Number of certificates set with one `MSET`, so that commands don't get too large.
+const MSET_SIZE: usize = 500;
+/// Number of keys that `SCAN` is asked to look at each time. Redis doesn't block other clients
+/// for long with this many.
+const SCAN_COUNT: u32 = 1000;
+
+fn key(id: &[u8]) -> Vec<u8> {
+    [OBJECT_PREFIX, id].concat()
+}
+
+impl RedisStore {
+    pub async fn connect(addr: &str, retention: Retention) -> Result<Self, StoreError> {
+        let client = paired::paired_connect(addr).await?;
+        Ok(Self {
+            inner: client,
+            retention,
+        })
+    }
+
+    /// The `SET` command for a certificate, or `None` if it shouldn't be stored.
+    fn set_command(&self, cert: NewCert<'_>, now: i64) -> Option<RespValue> {
+        match self.retention.keep_for(cert.not_after, now) {
+            Some(0) => None,
+            Some(secs) => Some(resp_array![
+                "SET",
+                key(cert.id),
+                cert.content,
+                "EX",
+                secs.to_string()
+            ]),
+            None => Some(resp_array!["SET", key(cert.id), cert.content]),
+        }
+    }
+
+    /// Iterates over the IDs of all stored certificates with `SCAN`, so Redis isn't blocked even
+    /// if there are millions. Certificates that are added or removed while scanning might be
+    /// missed, and IDs can be returned more than once.
+    pub fn scan_cert_ids(&self) -> CertIdScan<'_> {
+        CertIdScan {
+            store: self,
+            cursor: Some("0".to_string()),
+        }
+    }
+}
+
+/// See [`RedisStore::scan_cert_ids`].
+#[derive(Debug)]
+pub struct CertIdScan<'a> {
+    store: &'a RedisStore,
+    /// `None` once the scan is done.
+    cursor: Option<String>,
+}
+
+impl CertIdScan<'_> {
+    /// Gets the next batch of IDs, or `None` once all keys have been scanned. Batches can be
+    /// empty.
+    pub async fn next_batch(&mut self) -> Result<Option<Vec<Vec<u8>>>, StoreError> {
+        let cursor = match self.cursor.take() {
+            Some(cursor) => cursor,
+            None => return Ok(None),
+        };
+        let pattern = [OBJECT_PREFIX, b"*"].concat();
+        let (cursor, keys): (String, Vec<Vec<u8>>) = self
+            .store
+            .inner
+            .send(resp_array![
+                "SCAN",
+                cursor,
+                "MATCH",
+                pattern,
+                "COUNT",
+                SCAN_COUNT.to_string()
+            ])
+            .await?;
+        if cursor != "0" {
+            self.cursor = Some(cursor);
+        }
+        Ok(Some(
+            keys.into_iter()
+                .map(|key| key[OBJECT_PREFIX.len()..].to_vec())
+                .collect(),
+        ))
+    }
+}
+
+#[async_trait]
+impl CertStore for RedisStore {
+    async fn get_cert(&self, id: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        let cert: Option<Vec<u8>> = self.inner.send(resp_array!["GET", key(id)]).await?;
+        // keep recently viewed certificates for longer, unless that would keep them after they
+        // expire, which isn't known here
+        if let (Some(_), Some(ttl), false) =
+            (&cert, self.retention.ttl, self.retention.unexpired_only)
+        {
+            self.inner
+                .send_and_forget(resp_array!["EXPIRE", key(id), ttl.as_secs().to_string()]);
+        }
+        Ok(cert)
+    }
+
+    fn new_cert(&self, cert: NewCert<'_>) -> Result<(), StoreError> {
+        trace!(
+            "adding cert to Redis: {:?}, {} bytes",
+            cert.id,
+            cert.content.len()
+        );
+        // errors are logged by redis-async
+        if let Some(command) = self.set_command(cert, unix_now()) {
+            self.inner.send_and_forget(command);
+        }
+        Ok(())
+    }
+
+    async fn contains_certs(&self, ids: &[&[u8]]) -> Result<Vec<bool>, StoreError> {
+        // commands are sent when they are created, so this only waits for one round trip
+        let replies: Vec<_> = ids
+            .iter()
+            .map(|id| self.inner.send::<i64>(resp_array!["EXISTS", key(id)]))
+            .collect();
+        let mut found = Vec::with_capacity(ids.len());
+        for reply in replies {
+            found.push(reply.await? == 1);
+        }
+        Ok(found)
+    }
+
+    async fn put_certs(&self, certs: &[NewCert<'_>]) -> Result<(), StoreError> {
+        trace!("adding {} certs to Redis", certs.len());
+        let replies: Vec<_> = if self.retention.expires() {
+            // MSET can't set expiries, so each certificate is set separately
+            let now = unix_now();
+            certs
+                .iter()
+                .filter_map(|cert| self.set_command(*cert, now))
+                .map(|command| self.inner.send::<String>(command))
+                .collect()
+        } else {
+            certs
+                .chunks(MSET_SIZE)
+                .map(|chunk| {
+                    let mut command = vec![RespValue::from("MSET")];
+                    for cert in chunk {
+                        command.push(key(cert.id).into());
+                        command.push(cert.content.into());
+                    }
+                    self.inner.send::<String>(RespValue::Array(command))
+                })
+                .collect()
+        };
+        for reply in replies {
+            reply.await?;
+        }
+        Ok(())
+    }
+}
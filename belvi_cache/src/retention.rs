@@ -0,0 +1,82 @@
+// SPDX-License-Identifier: Apache-2.0
+//! How long caches keep certificates for. Certificates that are dropped from the cache can still
+//! be fetched from a log, so this keeps memory bounded at the cost of slower lookups of
+//! certificates that aren't viewed often.
+use belvi_config::Config;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Retention {
+    /// Certificates are dropped this long after they were last stored or viewed.
+    pub ttl: Option<Duration>,
+    /// Least recently viewed certificates are dropped to keep the cache below this size.
+    pub max_bytes: Option<u64>,
+    /// Whether certificates are dropped once they expire.
+    pub unexpired_only: bool,
+}
+
+impl Retention {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            ttl: config.cert_ttl.map(Duration::from_secs),
+            max_bytes: config.cert_cache_max_bytes,
+            unexpired_only: config.cert_unexpired_only,
+        }
+    }
+
+    /// Whether certificates are ever dropped because of their age.
+    pub fn expires(&self) -> bool {
+        self.ttl.is_some() || self.unexpired_only
+    }
+
+    /// How many seconds a certificate that expires at `not_after` should be kept for, if it is
+    /// stored at `now`. `None` means that it is kept until it has to be evicted, and `Some(0)`
+    /// means that it shouldn't be stored at all.
+    pub fn keep_for(&self, not_after: i64, now: i64) -> Option<u64> {
+        let ttl = self.ttl.map(|ttl| ttl.as_secs());
+        if !self.unexpired_only {
+            return ttl;
+        }
+        let until_expired = u64::try_from(not_after - now).unwrap_or(0);
+        Some(ttl.map_or(until_expired, |ttl| ttl.min(until_expired)))
+    }
+}
+
+pub(crate) fn unix_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is before 1970")
+        .as_secs() as i64
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keep_for() {
+        let forever = Retention::default();
+        assert!(!forever.expires());
+        assert_eq!(forever.keep_for(100, 200), None);
+
+        let ttl = Retention {
+            ttl: Some(Duration::from_secs(60)),
+            ..Retention::default()
+        };
+        assert_eq!(ttl.keep_for(100, 200), Some(60));
+
+        let unexpired = Retention {
+            unexpired_only: true,
+            ..Retention::default()
+        };
+        assert_eq!(unexpired.keep_for(300, 200), Some(100));
+        assert_eq!(unexpired.keep_for(100, 200), Some(0));
+
+        let both = Retention {
+            unexpired_only: true,
+            ..ttl
+        };
+        assert_eq!(both.keep_for(1000, 200), Some(60));
+        assert_eq!(both.keep_for(230, 200), Some(30));
+    }
+}
@@ -0,0 +1,99 @@
+// SPDX-License-Identifier: Apache-2.0
+use crate::{CertStore, NewCert, StoreError};
+use async_trait::async_trait;
+use rusqlite::{Connection, OptionalExtension};
+use std::{path::PathBuf, sync::Mutex};
+
+/// Stores certificates as blobs in a SQLite database. This is separate from the main database,
+/// so that it doesn't make queries on the main database slower.
+#[derive(Debug)]
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn open(path: PathBuf) -> Result<Self, StoreError> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    fn from_connection(conn: Connection) -> Result<Self, StoreError> {
+        conn.execute_batch(
+            "PRAGMA journal_mode = WAL;
+            PRAGMA synchronous = NORMAL;
+            CREATE TABLE IF NOT EXISTS certs (
+                leaf_hash BLOB PRIMARY KEY NOT NULL, -- SHA256 of leaf data
+                cert BLOB NOT NULL -- DER of the certificate or precertificate TBS
+            ) WITHOUT ROWID;",
+        )?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+#[async_trait]
+impl CertStore for SqliteStore {
+    async fn get_cert(&self, id: &[u8]) -> Result<Option<Vec<u8>>, StoreError> {
+        // lookups by primary key are fast, so this doesn't block for long
+        let conn = self.conn.lock().unwrap();
+        let cert = conn
+            .prepare_cached("SELECT cert FROM certs WHERE leaf_hash = ?")?
+            .query_row([id], |row| row.get(0))
+            .optional()?;
+        Ok(cert)
+    }
+
+    fn new_cert(&self, cert: NewCert<'_>) -> Result<(), StoreError> {
+        let conn = self.conn.lock().unwrap();
+        conn.prepare_cached("INSERT OR IGNORE INTO certs (leaf_hash, cert) VALUES (?, ?)")?
+            .execute([cert.id, cert.content])?;
+        Ok(())
+    }
+
+    async fn contains_certs(&self, ids: &[&[u8]]) -> Result<Vec<bool>, StoreError> {
+        let conn = self.conn.lock().unwrap();
+        let mut query = conn.prepare_cached("SELECT 1 FROM certs WHERE leaf_hash = ?")?;
+        let mut found = Vec::with_capacity(ids.len());
+        for id in ids {
+            found.push(query.exists([id])?);
+        }
+        Ok(found)
+    }
+
+    async fn put_certs(&self, certs: &[NewCert<'_>]) -> Result<(), StoreError> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        {
+            let mut insert =
+                tx.prepare_cached("INSERT OR IGNORE INTO certs (leaf_hash, cert) VALUES (?, ?)")?;
+            for cert in certs {
+                insert.execute([cert.id, cert.content])?;
+            }
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn store() {
+        let store = SqliteStore::from_connection(Connection::open_in_memory().unwrap()).unwrap();
+        crate::check_store(&store).await;
+
+        // certificates are kept when the database is opened again
+        let path = crate::test_dir("sqlite").join("certs.db");
+        SqliteStore::open(path.clone())
+            .unwrap()
+            .new_cert(crate::test_cert(&[1; 16], b"cert"))
+            .unwrap();
+        let store = SqliteStore::open(path).unwrap();
+        assert_eq!(
+            store.get_cert(&[1; 16]).await.unwrap(),
+            Some(b"cert".to_vec())
+        );
+    }
+}
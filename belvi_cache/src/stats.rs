@@ -0,0 +1,57 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Counters of how the certificate cache is used by this process, shown by the frontend.
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Debug)]
+pub struct CacheStats {
+    /// Certificates that were found in the store.
+    hits: AtomicU64,
+    /// Certificates that weren't in the store.
+    misses: AtomicU64,
+    /// Certificates that weren't in the store, and were fetched from a log again.
+    refetches: AtomicU64,
+    /// Certificates dropped by the memory store. Redis doesn't report what it drops.
+    evictions: AtomicU64,
+}
+
+pub static STATS: CacheStats = CacheStats {
+    hits: AtomicU64::new(0),
+    misses: AtomicU64::new(0),
+    refetches: AtomicU64::new(0),
+    evictions: AtomicU64::new(0),
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct StatsSnapshot {
+    pub hits: u64,
+    pub misses: u64,
+    pub refetches: u64,
+    pub evictions: u64,
+}
+
+impl CacheStats {
+    pub fn hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn refetch(&self) {
+        self.refetches.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn evict(&self) {
+        self.evictions.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            refetches: self.refetches.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+}
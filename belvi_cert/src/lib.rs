@@ -1,10 +1,104 @@
 // SPDX-License-Identifier: Apache-2.0
 use bcder::{
     decode::{self, Constructed, Content},
-    Tag,
+    encode::Values,
+    Oid, Tag,
 };
 use log::warn;
-use x509_certificate::rfc5280::TbsCertificate;
+use std::net::IpAddr;
+use x509_certificate::{rfc3280::Name, rfc5280::TbsCertificate};
+
+pub mod sct;
+
+/// 1.3.6.1.4.1.11129.2.4.2, the SignedCertificateTimestampList extension
+pub const SCT_LIST_OID: &[u8] = &[43, 6, 1, 4, 1, 214, 121, 2, 4, 2];
+/// 1.3.6.1.4.1.11129.2.4.3, the extension that makes a precertificate unusable
+const POISON_OID: &[u8] = &[43, 6, 1, 4, 1, 214, 121, 2, 4, 3];
+
+/// DER of a TBS certificate without the poison and SCT list extensions. This is the same for a
+/// final certificate and the TBS of its precert entry, so it can be used to match them.
+pub fn precert_tbs_der(cert: &TbsCertificate) -> Vec<u8> {
+    let mut cert = cert.clone();
+    if let Some(exts) = &mut cert.extensions {
+        exts.retain(|ext| ext.id.as_ref() != SCT_LIST_OID && ext.id.as_ref() != POISON_OID);
+    }
+    let mut der = Vec::new();
+    cert.encode_ref()
+        .write_encoded(bcder::Mode::Der, &mut der)
+        .expect("writing to a Vec can't fail");
+    der
+}
+
+/// 1.3.6.1.4.1.311.20.2.3, Microsoft's user principal name
+const UPN_OID: &[u8] = &[43, 6, 1, 4, 1, 130, 55, 20, 2, 3];
+/// 1.3.6.1.5.5.7.8.9, an internationalized email address (RFC 8398)
+const SMTP_UTF8_MAILBOX_OID: &[u8] = &[43, 6, 1, 5, 5, 7, 8, 9];
+/// 1.3.6.1.5.5.7.8.5, a Jabber ID (RFC 6120)
+const XMPP_ADDR_OID: &[u8] = &[43, 6, 1, 5, 5, 7, 8, 5];
+
+/// The kind of a subjectAltName that isn't an email address, DNS name, URI or IP address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NameKind {
+    /// otherName with a user principal name
+    Upn,
+    /// otherName with an internationalized email address
+    SmtpUtf8Mailbox,
+    /// otherName with a Jabber ID
+    XmppAddr,
+    DirectoryName,
+    RegisteredId,
+}
+
+impl NameKind {
+    pub const ALL: [NameKind; 5] = [
+        NameKind::Upn,
+        NameKind::SmtpUtf8Mailbox,
+        NameKind::XmppAddr,
+        NameKind::DirectoryName,
+        NameKind::RegisteredId,
+    ];
+
+    /// Number used to store the kind in the database.
+    pub fn num(self) -> u8 {
+        match self {
+            NameKind::Upn => 1,
+            NameKind::SmtpUtf8Mailbox => 2,
+            NameKind::XmppAddr => 3,
+            NameKind::DirectoryName => 4,
+            NameKind::RegisteredId => 5,
+        }
+    }
+
+    pub fn from_num(num: u8) -> Option<Self> {
+        Self::ALL.iter().copied().find(|kind| kind.num() == num)
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            NameKind::Upn => "User principal name",
+            NameKind::SmtpUtf8Mailbox => "SMTP UTF-8 mailbox",
+            NameKind::XmppAddr => "XMPP address",
+            NameKind::DirectoryName => "Directory name",
+            NameKind::RegisteredId => "Registered ID",
+        }
+    }
+}
+
+/// A subjectAltName of a kind that is tagged with a [`NameKind`], in textual form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TaggedName {
+    pub kind: NameKind,
+    pub value: String,
+}
+
+/// A name in the subjectAltName extension that we know how to decode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum GeneralName {
+    /// An email address, DNS name or URI.
+    Text(Vec<u8>),
+    Ip(IpAddr),
+    Tagged(TaggedName),
+}
 
 pub fn get_cert_domains(cert: &TbsCertificate) -> Vec<Vec<u8>> {
     let mut domains = Vec::new();
@@ -14,54 +108,95 @@ pub fn get_cert_domains(cert: &TbsCertificate) -> Vec<Vec<u8>> {
             if attr.typ.as_ref() == [85, 4, 3] {
                 let next_dom =
                     Constructed::decode((**attr.value).clone(), bcder::Mode::Ber, take_tagged_ber);
-                if let Ok(dom) = next_dom {
+                if let Ok(Some(GeneralName::Text(dom))) = next_dom {
                     domains.push(dom);
                 }
             }
         }
     }
+    for name in get_alt_names(cert) {
+        if let GeneralName::Text(dom) = name {
+            if !domains.contains(&dom) {
+                domains.push(dom);
+            }
+        }
+    }
+    domains
+}
+
+/// IP addresses in the subjectAltName extension.
+pub fn get_cert_ips(cert: &TbsCertificate) -> Vec<IpAddr> {
+    let mut ips = Vec::new();
+    for name in get_alt_names(cert) {
+        if let GeneralName::Ip(ip) = name {
+            if !ips.contains(&ip) {
+                ips.push(ip);
+            }
+        }
+    }
+    ips
+}
+
+/// Other names in the subjectAltName extension, such as those in S/MIME and client
+/// authentication certificates.
+pub fn get_cert_tagged_names(cert: &TbsCertificate) -> Vec<TaggedName> {
+    let mut names = Vec::new();
+    for name in get_alt_names(cert) {
+        if let GeneralName::Tagged(name) = name {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+    }
+    names
+}
+
+fn get_alt_names(cert: &TbsCertificate) -> Vec<GeneralName> {
+    let mut names = Vec::new();
     if let Some(exts) = &cert.extensions {
         for ext in &**exts {
             // 2.5.29.17 is OID for subjectAltName
             if ext.id.as_ref() == [85, 29, 17] {
-                let doms = Constructed::decode(ext.value.to_bytes(), bcder::Mode::Ber, |cons| {
-                    cons.take_sequence(|subcons| {
-                        let mut doms = Vec::new();
-                        loop {
-                            match take_tagged_ber(subcons) {
-                                Ok(dom) => {
-                                    if !domains.contains(&dom) && !doms.contains(&dom) {
-                                        doms.push(dom);
-                                    }
-                                }
-                                Err(decode::Error::Malformed) => break,
-                                Err(decode::Error::Unimplemented) => {}
-                            }
-                        }
-                        Ok(doms)
-                    })
-                });
-                if let Ok(doms) = doms {
-                    for dom in doms {
-                        domains.push(dom);
-                    }
+                let ext_names = decode_alt_names(ext.value.to_bytes());
+                if let Ok(ext_names) = ext_names {
+                    names.extend(ext_names);
                 } else {
                     warn!("Cert has invalid subjectAltNames extension");
                 }
             }
         }
     }
-    domains
+    names
+}
+
+fn decode_alt_names(value: bytes::Bytes) -> Result<Vec<GeneralName>, decode::Error> {
+    Constructed::decode(value, bcder::Mode::Ber, |cons| {
+        cons.take_sequence(|subcons| {
+            let mut names = Vec::new();
+            loop {
+                match take_tagged_ber(subcons) {
+                    Ok(Some(name)) => names.push(name),
+                    Ok(None) => {}
+                    Err(_) => break,
+                }
+            }
+            Ok(names)
+        })
+    })
 }
 
-fn take_tagged_ber(cons: &mut Constructed<bytes::Bytes>) -> Result<Vec<u8>, bcder::decode::Error> {
+/// Decodes a GeneralName, or returns `None` if it is of a kind that isn't supported. The value is
+/// always consumed, since returning an error from `take_value` stops decoding the rest of the
+/// names.
+fn take_tagged_ber(
+    cons: &mut Constructed<bytes::Bytes>,
+) -> Result<Option<GeneralName>, bcder::decode::Error> {
     cons.take_value(|tag, content| {
         match content {
             Content::Primitive(prim) => {
                 let bytes = prim.take_all()?;
                 // tag can be from 0-8: https://datatracker.ietf.org/doc/html/rfc5280#page-128
                 // in practice, almost always a DNS name
-                // TODO: support IP addresses, tagged with CTX_7
                 if
                 // email
                 tag == Tag::CTX_1 ||
@@ -70,16 +205,102 @@ fn take_tagged_ber(cons: &mut Constructed<bytes::Bytes>) -> Result<Vec<u8>, bcde
                     // URI
                     tag == Tag::CTX_6
                 {
-                    Ok(ber_to_string(bytes))
+                    Ok(Some(GeneralName::Text(ber_to_string(bytes))))
+                } else if tag == Tag::ctx(8) {
+                    // registered ID, as the contents of an OID
+                    Ok(Some(GeneralName::Tagged(TaggedName {
+                        kind: NameKind::RegisteredId,
+                        value: Oid(bytes).to_string(),
+                    })))
+                } else if tag == Tag::ctx(7) {
+                    // IP address, as 4 or 16 bytes in network byte order
+                    if let Ok(octets) = <[u8; 4]>::try_from(bytes.as_ref()) {
+                        Ok(Some(GeneralName::Ip(IpAddr::from(octets))))
+                    } else if let Ok(octets) = <[u8; 16]>::try_from(bytes.as_ref()) {
+                        Ok(Some(GeneralName::Ip(IpAddr::from(octets))))
+                    } else {
+                        Ok(None)
+                    }
+                } else {
+                    Ok(None)
+                }
+            }
+            Content::Constructed(cons) => {
+                // the contents are captured before decoding them so that a malformed name is
+                // skipped instead of ending the list
+                if tag == Tag::CTX_0 {
+                    Ok(cons.capture_all()?.decode(take_other_name).unwrap_or(None))
+                } else if tag == Tag::CTX_4 {
+                    // directoryName is a CHOICE, so it is explicitly tagged
+                    let name = cons.capture_all()?.decode(Name::take_from);
+                    Ok(name.ok().map(|name| {
+                        GeneralName::Tagged(TaggedName {
+                            kind: NameKind::DirectoryName,
+                            value: format_name(&name),
+                        })
+                    }))
                 } else {
-                    Err(decode::Error::Unimplemented)
+                    // x400Address and ediPartyName
+                    cons.skip_all()?;
+                    Ok(None)
                 }
             }
-            _ => Err(decode::Error::Malformed),
         }
     })
 }
 
+/// Decodes the contents of an otherName, which is a type ID and an explicitly tagged value.
+fn take_other_name(
+    cons: &mut Constructed<bytes::Bytes>,
+) -> Result<Option<GeneralName>, bcder::decode::Error> {
+    let type_id = Oid::take_from(cons)?;
+    let kind = match type_id.as_ref() {
+        UPN_OID => NameKind::Upn,
+        SMTP_UTF8_MAILBOX_OID => NameKind::SmtpUtf8Mailbox,
+        XMPP_ADDR_OID => NameKind::XmppAddr,
+        _ => {
+            cons.skip_all()?;
+            return Ok(None);
+        }
+    };
+    // all of these are UTF8Strings
+    let value = cons.take_constructed_if(Tag::CTX_0, bcder::Utf8String::take_from)?;
+    Ok(Some(GeneralName::Tagged(TaggedName {
+        kind,
+        value: String::from_utf8_lossy(&value.to_bytes()).to_string(),
+    })))
+}
+
+/// Formats a name like OpenSSL does, such as `CN=Example, O=Example Inc, C=CA`. Attributes without
+/// a short name are written with their OID.
+fn format_name(name: &Name) -> String {
+    let mut fields = Vec::new();
+    for rdn in &***name {
+        for attr in &**rdn {
+            let typ = match attr.typ.as_ref() {
+                [85, 4, 3] => "CN".to_string(),
+                [85, 4, 5] => "serialNumber".to_string(),
+                [85, 4, 6] => "C".to_string(),
+                [85, 4, 7] => "L".to_string(),
+                [85, 4, 8] => "ST".to_string(),
+                [85, 4, 10] => "O".to_string(),
+                [85, 4, 11] => "OU".to_string(),
+                [42, 134, 72, 134, 247, 13, 1, 9, 1] => "emailAddress".to_string(),
+                _ => attr.typ.to_string(),
+            };
+            let value = attr.to_string().unwrap_or_else(|_| {
+                // RFC 4514 writes values that aren't strings as hex of the DER
+                attr.value
+                    .as_slice()
+                    .iter()
+                    .fold("#".to_string(), |a, b| a + &format!("{:02x}", b))
+            });
+            fields.push(format!("{}={}", typ, value));
+        }
+    }
+    fields.join(", ")
+}
+
 fn ber_to_string(bytes: bytes::Bytes) -> Vec<u8> {
     let str_decode = Constructed::decode(bytes.clone(), bcder::Mode::Ber, |cons| {
         if let Ok(str) = bcder::Utf8String::take_from(cons) {
@@ -149,7 +370,188 @@ mod test {
         expected.push(b"test1.http-01.production.haplorrhini.com".to_vec());
         expected.push(b"test2.http-01.production.haplorrhini.com".to_vec());
         expected.push(b"test3.http-01.production.haplorrhini.com".to_vec());
-        // TODO: ip address
         assert_eq!(domains, expected);
     }
+
+    #[test]
+    fn haplorrhini_ips() {
+        let ips = get_cert_ips(
+            &x509_certificate::certificate::X509Certificate::from_der(include_bytes!(
+                "../../test_certs/haplorrhini.der"
+            ))
+            .unwrap()
+            .as_ref()
+            .tbs_certificate,
+        );
+        let ips: Vec<String> = ips.iter().map(IpAddr::to_string).collect();
+        assert_eq!(ips, vec!["34.117.169.92", "2600:1901:0:631b::"]);
+    }
+
+    #[test]
+    fn ttw_ips() {
+        let ips = get_cert_ips(
+            &x509_certificate::certificate::X509Certificate::from_der(include_bytes!(
+                "../../test_certs/ttw.der"
+            ))
+            .unwrap()
+            .as_ref()
+            .tbs_certificate,
+        );
+        assert!(ips.is_empty());
+    }
+
+    /// DER of a value shorter than 256 bytes.
+    fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+        let len = content.len() as u8;
+        let header = if len < 128 {
+            vec![tag, len]
+        } else {
+            vec![tag, 0x81, len]
+        };
+        [&header[..], content].concat()
+    }
+
+    #[test]
+    fn tagged_names() {
+        let other_name = |oid: &[u8], value: &str| {
+            tlv(
+                0xa0,
+                &[tlv(0x06, oid), tlv(0xa0, &tlv(0x0c, value.as_bytes()))].concat(),
+            )
+        };
+        let attr = |oid: &[u8], value: &str| {
+            tlv(
+                0x31,
+                &tlv(
+                    0x30,
+                    &[tlv(0x06, oid), tlv(0x0c, value.as_bytes())].concat(),
+                ),
+            )
+        };
+        let alt_names = tlv(
+            0x30,
+            &[
+                tlv(0x82, b"example.com"),
+                other_name(UPN_OID, "user@corp.example"),
+                other_name(SMTP_UTF8_MAILBOX_OID, "用户@例子.广告"),
+                // unknown otherNames are skipped
+                other_name(&[42, 3, 4], "unknown"),
+                other_name(XMPP_ADDR_OID, "juliet@im.example.com"),
+                tlv(
+                    0xa4,
+                    &tlv(
+                        0x30,
+                        &[
+                            attr(&[85, 4, 3], "Juliet"),
+                            attr(&[85, 4, 10], "Capulet"),
+                            attr(&[85, 4, 99], "other"),
+                        ]
+                        .concat(),
+                    ),
+                ),
+                // ediPartyName is skipped
+                tlv(0xa5, &tlv(0xa1, &tlv(0x0c, b"party"))),
+                // 1.3.6.1.4.1.311.21.8
+                tlv(0x88, &[43, 6, 1, 4, 1, 130, 55, 21, 8]),
+                tlv(0x81, b"juliet@example.com"),
+            ]
+            .concat(),
+        );
+        let names = decode_alt_names(alt_names.into()).unwrap();
+        let tagged = |kind, value: &str| {
+            GeneralName::Tagged(TaggedName {
+                kind,
+                value: value.to_string(),
+            })
+        };
+        assert_eq!(
+            names,
+            vec![
+                GeneralName::Text(b"example.com".to_vec()),
+                tagged(NameKind::Upn, "user@corp.example"),
+                tagged(NameKind::SmtpUtf8Mailbox, "用户@例子.广告"),
+                tagged(NameKind::XmppAddr, "juliet@im.example.com"),
+                tagged(
+                    NameKind::DirectoryName,
+                    "CN=Juliet, O=Capulet, 2.5.4.99=other"
+                ),
+                tagged(NameKind::RegisteredId, "1.3.6.1.4.1.311.21.8"),
+                GeneralName::Text(b"juliet@example.com".to_vec()),
+            ]
+        );
+        for kind in NameKind::ALL {
+            assert_eq!(NameKind::from_num(kind.num()), Some(kind));
+        }
+    }
+
+    #[test]
+    fn malformed_names_skipped() {
+        let mut cert = x509_certificate::certificate::X509Certificate::from_der(include_bytes!(
+            "../../test_certs/ttw.der"
+        ))
+        .unwrap()
+        .as_ref()
+        .tbs_certificate
+        .clone();
+        let alt_names = tlv(
+            0x30,
+            &[
+                tlv(0x82, b"smitop.com"),
+                // a directoryName with an RDN that isn't a set of attributes
+                tlv(0xa4, &tlv(0x30, &tlv(0x31, &tlv(0x04, b"x")))),
+                // an otherName with no value
+                tlv(0xa0, &tlv(0x06, UPN_OID)),
+                tlv(0x82, b"*.smitop.com"),
+            ]
+            .concat(),
+        );
+        for ext in cert.extensions.as_mut().unwrap().iter_mut() {
+            if ext.id.as_ref() == [85, 29, 17] {
+                ext.value = bcder::OctetString::new(alt_names.clone().into());
+            }
+        }
+        let domains = get_cert_domains(&cert);
+        assert_eq!(
+            domains,
+            vec![b"smitop.com".to_vec(), b"*.smitop.com".to_vec()]
+        );
+        assert!(get_cert_tagged_names(&cert).is_empty());
+    }
+
+    #[test]
+    fn precert_tbs_matches() {
+        let cert = x509_certificate::certificate::X509Certificate::from_der(include_bytes!(
+            "../../test_certs/ttw.der"
+        ))
+        .unwrap();
+        let tbs = &cert.as_ref().tbs_certificate;
+        let has_ext = |tbs: &TbsCertificate, oid: &[u8]| {
+            tbs.extensions
+                .as_ref()
+                .unwrap()
+                .iter()
+                .any(|ext| ext.id.as_ref() == oid)
+        };
+        assert!(has_ext(tbs, SCT_LIST_OID));
+        let stripped = precert_tbs_der(tbs);
+
+        // the TBS of the precert entry has neither extension
+        let precert = Constructed::decode(stripped.as_ref(), bcder::Mode::Der, |cons| {
+            TbsCertificate::take_from(cons)
+        })
+        .unwrap();
+        assert!(!has_ext(&precert, SCT_LIST_OID));
+        assert_eq!(precert_tbs_der(&precert), stripped);
+
+        // a precertificate has the poison extension instead of SCTs
+        let mut poisoned = precert.clone();
+        let mut poison = poisoned.extensions.as_ref().unwrap()[0].clone();
+        poison.id = bcder::Oid(bytes::Bytes::from_static(POISON_OID));
+        poisoned.extensions.as_mut().unwrap().push(poison);
+        assert_eq!(precert_tbs_der(&poisoned), stripped);
+
+        let mut other = precert;
+        other.extensions.as_mut().unwrap().remove(0);
+        assert_ne!(precert_tbs_der(&other), stripped);
+    }
 }
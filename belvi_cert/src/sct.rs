@@ -0,0 +1,175 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Signed certificate timestamps embedded in final certificates, as described in
+//! [RFC 6962 section 3.3](https://www.rfc-editor.org/rfc/rfc6962#section-3.3).
+use crate::SCT_LIST_OID;
+use bcder::{decode::Constructed, OctetString};
+use x509_certificate::rfc5280::TbsCertificate;
+
+/// A promise by a log to incorporate a certificate within its maximum merge delay.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sct {
+    pub version: u8,
+    /// SHA-256 hash of the log's public key.
+    pub log_id: [u8; 32],
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub extensions: Vec<u8>,
+    /// TLS `HashAlgorithm` of the signature.
+    pub hash_algorithm: u8,
+    /// TLS `SignatureAlgorithm` of the signature.
+    pub signature_algorithm: u8,
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug)]
+#[allow(dead_code)] // Debug trait is ignored for dead code analysis, but some fields are only here for better messages
+pub enum SctError {
+    /// The extension isn't an OCTET STRING.
+    Asn1(bcder::decode::Error),
+    TooShort,
+    TrailingData,
+    UnknownVersion(u8),
+}
+
+impl Sct {
+    /// Name of the signature algorithm, such as `ECDSA with SHA-256`.
+    pub fn algorithm_name(&self) -> String {
+        let hash = match self.hash_algorithm {
+            1 => "MD5".to_string(),
+            2 => "SHA-1".to_string(),
+            3 => "SHA-224".to_string(),
+            4 => "SHA-256".to_string(),
+            5 => "SHA-384".to_string(),
+            6 => "SHA-512".to_string(),
+            other => format!("hash {}", other),
+        };
+        let signature = match self.signature_algorithm {
+            1 => "RSA".to_string(),
+            2 => "DSA".to_string(),
+            3 => "ECDSA".to_string(),
+            other => format!("signature {}", other),
+        };
+        format!("{} with {}", signature, hash)
+    }
+}
+
+/// Reads `len` bytes from the start of `v`.
+fn take<'a>(v: &mut &'a [u8], len: usize) -> Result<&'a [u8], SctError> {
+    if v.len() < len {
+        return Err(SctError::TooShort);
+    }
+    let (taken, rest) = v.split_at(len);
+    *v = rest;
+    Ok(taken)
+}
+
+/// Reads data with a 2 byte length from the start of `v`.
+fn take_u16_prefixed<'a>(v: &mut &'a [u8]) -> Result<&'a [u8], SctError> {
+    let len = take(v, 2)?;
+    take(v, u16::from_be_bytes([len[0], len[1]]) as usize)
+}
+
+fn parse_sct(mut v: &[u8]) -> Result<Sct, SctError> {
+    let version = take(&mut v, 1)?[0];
+    // v1 is the only version, and later versions might be laid out differently
+    if version != 0 {
+        return Err(SctError::UnknownVersion(version));
+    }
+    let log_id = take(&mut v, 32)?.try_into().unwrap();
+    let timestamp = u64::from_be_bytes(take(&mut v, 8)?.try_into().unwrap());
+    let extensions = take_u16_prefixed(&mut v)?.to_vec();
+    let algorithms = take(&mut v, 2)?;
+    let signature = take_u16_prefixed(&mut v)?.to_vec();
+    if !v.is_empty() {
+        return Err(SctError::TrailingData);
+    }
+    Ok(Sct {
+        version,
+        log_id,
+        timestamp,
+        extensions,
+        hash_algorithm: algorithms[0],
+        signature_algorithm: algorithms[1],
+        signature,
+    })
+}
+
+/// Parses the value of a SignedCertificateTimestampList extension.
+pub fn parse_sct_list(ext_value: bytes::Bytes) -> Result<Vec<Sct>, SctError> {
+    let list = Constructed::decode(ext_value, bcder::Mode::Der, OctetString::take_from)
+        .map_err(SctError::Asn1)?
+        .to_bytes();
+    let mut v = &list[..];
+    let mut scts_bytes = take_u16_prefixed(&mut v)?;
+    if !v.is_empty() {
+        return Err(SctError::TrailingData);
+    }
+    let mut scts = Vec::new();
+    while !scts_bytes.is_empty() {
+        scts.push(parse_sct(take_u16_prefixed(&mut scts_bytes)?)?);
+    }
+    Ok(scts)
+}
+
+/// Gets the SCTs embedded in a certificate. Precerts don't have any.
+pub fn get_cert_scts(cert: &TbsCertificate) -> Result<Vec<Sct>, SctError> {
+    let ext = cert
+        .extensions
+        .as_ref()
+        .and_then(|exts| exts.iter().find(|ext| ext.id.as_ref() == SCT_LIST_OID));
+    match ext {
+        Some(ext) => parse_sct_list(ext.value.to_bytes()),
+        None => Ok(Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ttw_scts() {
+        let cert = x509_certificate::certificate::X509Certificate::from_der(include_bytes!(
+            "../../test_certs/ttw.der"
+        ))
+        .unwrap();
+        let scts = get_cert_scts(&cert.as_ref().tbs_certificate).unwrap();
+        assert!(!scts.is_empty());
+        for sct in scts {
+            assert_eq!(sct.version, 0);
+            // between 2018 and 2030
+            assert!((1_514_764_800_000..1_893_456_000_000).contains(&sct.timestamp));
+            assert!(!sct.signature.is_empty());
+            assert_eq!(sct.algorithm_name(), "ECDSA with SHA-256");
+        }
+    }
+
+    #[test]
+    fn invalid_scts() {
+        let sct = [
+            &[0][..],
+            &[7; 32],
+            &1000u64.to_be_bytes(),
+            &[0, 0],
+            &[4, 3],
+            &[0, 2, 9, 9],
+        ]
+        .concat();
+        let parsed = parse_sct(&sct).unwrap();
+        assert_eq!(parsed.log_id, [7; 32]);
+        assert_eq!(parsed.timestamp, 1000);
+        assert_eq!(parsed.signature, [9, 9]);
+        assert!(matches!(
+            parse_sct(&sct[..sct.len() - 1]),
+            Err(SctError::TooShort)
+        ));
+        assert!(matches!(
+            parse_sct(&[&sct[..], &[0]].concat()),
+            Err(SctError::TrailingData)
+        ));
+        assert!(matches!(
+            parse_sct(&[&[1][..], &sct[1..]].concat()),
+            Err(SctError::UnknownVersion(1))
+        ));
+    }
+}
@@ -0,0 +1,810 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Configuration shared by all Belvi binaries.
+//!
+//! Each setting is taken from the first of these that sets it:
+//! 1. a command line flag, such as `--data-path`
+//! 2. an environment variable, such as `BELVI_DATA_PATH`
+//! 3. the TOML file given by `--config` or `BELVI_CONFIG` (see `belvi.example.toml`)
+//! 4. the default
+//!
+//! Settings that are lists, such as `extra_log_lists`, are arrays in the TOML file. In
+//! environment variables and flags, paths are separated like in `PATH`, and log IDs are separated
+//! with commas.
+//!
+//! For compatibility with older versions, if the data path isn't set in any of these ways, the
+//! first positional argument is used as the data path, and setting `BELVI_NO_CACHE` to anything
+//! turns off `cache_certs`.
+use serde::{Deserialize, Serialize};
+use std::{
+    convert::Infallible,
+    ffi::{OsStr, OsString},
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+#[derive(Debug)]
+#[allow(dead_code)] // Debug trait is ignored for dead code analysis, but some fields are only here for better messages
+pub enum ConfigError {
+    Io {
+        path: PathBuf,
+        err: std::io::Error,
+    },
+    Toml {
+        path: PathBuf,
+        err: toml::de::Error,
+    },
+    Args(pico_args::Error),
+    UnknownFlag(String),
+    /// An environment variable has an invalid value.
+    BadEnv(&'static str),
+    Invalid {
+        setting: &'static str,
+        reason: &'static str,
+    },
+}
+
+/// Where the bodies of certificates are stored.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CertStoreKind {
+    /// A Redis server at `redis_addr`.
+    Redis,
+    /// Files in the `certs` directory of the data path.
+    Filesystem,
+    /// A SQLite database, `certs.db` in the data path.
+    Sqlite,
+    /// Memory of the process, which is lost when it exits. Only useful for development.
+    Memory,
+}
+
+impl FromStr for CertStoreKind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "redis" => Ok(Self::Redis),
+            "filesystem" => Ok(Self::Filesystem),
+            "sqlite" => Ok(Self::Sqlite),
+            "memory" => Ok(Self::Memory),
+            _ => Err("unknown certificate store"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Config {
+    /// Directory with the database and other data.
+    pub data_path: PathBuf,
+    /// Address of the Redis server used as a cache of certificates.
+    pub redis_addr: String,
+    /// Where the bodies of certificates are stored.
+    pub cert_store: CertStoreKind,
+    /// Whether to compress certificates before storing them. Compressed certificates are always
+    /// readable, even if this is turned off.
+    pub compress_certs: bool,
+    /// Seconds that cached certificates are kept for after they were last stored or viewed.
+    /// Only for the Redis and memory stores, since the others are meant to keep certificates.
+    pub cert_ttl: Option<u64>,
+    /// Least recently viewed certificates are dropped to keep the cache below this size. Only
+    /// for the memory store. Redis limits its own size with `maxmemory`, which has to be set on
+    /// the server.
+    pub cert_cache_max_bytes: Option<u64>,
+    /// Whether cached certificates are dropped once they expire. Only for the Redis and memory
+    /// stores.
+    pub cert_unexpired_only: bool,
+    /// Address the frontend listens on.
+    pub bind_addr: SocketAddr,
+    /// Contact address sent to logs with every request, so that log operators can reach us.
+    pub contact: String,
+    /// Whether the scanner stores the certificates it fetches. If not, certificates are fetched
+    /// from logs when they are viewed.
+    pub cache_certs: bool,
+    /// Whether the scanner fetches from test logs, which aren't trusted by browsers.
+    pub include_test_logs: bool,
+    /// How many batches of entries the scanner fetches from each log at once.
+    pub batches_per_log: usize,
+    /// IDs of logs that the scanner fetches from the first entry, instead of only fetching new
+    /// entries. `all` backfills every log.
+    pub backfill_logs: Vec<String>,
+    /// `log_list.json` file to use instead of the bundled log list.
+    pub log_list: Option<PathBuf>,
+    /// Detached signature of `log_list`. Defaults to its path with a `.sig` extension.
+    pub log_list_sig: Option<PathBuf>,
+    /// Public key (PEM or DER) that `log_list` must be signed with. If unset, the signature isn't
+    /// checked.
+    pub log_list_key: Option<PathBuf>,
+    /// More log lists, which aren't signed, for private or test logs that aren't in the main list.
+    pub extra_log_lists: Vec<PathBuf>,
+    /// Seconds between reloads of the log lists.
+    pub log_list_reload: u64,
+    /// JSON file with limits on the rate of requests to logs (see `belvi_log_list::rate_limit`).
+    pub rate_limits: Option<PathBuf>,
+    /// Positional arguments that aren't options, for tools that take their own arguments.
+    #[serde(skip)]
+    pub args: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            data_path: PathBuf::new(),
+            redis_addr: "127.0.0.1:6379".to_string(),
+            cert_store: CertStoreKind::Redis,
+            compress_certs: true,
+            cert_ttl: None,
+            cert_cache_max_bytes: None,
+            cert_unexpired_only: false,
+            bind_addr: ([0, 0, 0, 0], 47371).into(),
+            contact: "belvi@smitop.com".to_string(),
+            cache_certs: true,
+            include_test_logs: false,
+            batches_per_log: 4,
+            backfill_logs: Vec::new(),
+            log_list: None,
+            log_list_sig: None,
+            log_list_key: None,
+            extra_log_lists: Vec::new(),
+            log_list_reload: 60 * 60,
+            rate_limits: None,
+            args: Vec::new(),
+        }
+    }
+}
+
+/// Settings from one source, which override the settings from lower sources.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Overrides {
+    data_path: Option<PathBuf>,
+    redis_addr: Option<String>,
+    cert_store: Option<CertStoreKind>,
+    compress_certs: Option<bool>,
+    cert_ttl: Option<u64>,
+    cert_cache_max_bytes: Option<u64>,
+    cert_unexpired_only: Option<bool>,
+    bind_addr: Option<SocketAddr>,
+    contact: Option<String>,
+    cache_certs: Option<bool>,
+    include_test_logs: Option<bool>,
+    batches_per_log: Option<usize>,
+    backfill_logs: Option<Vec<String>>,
+    log_list: Option<PathBuf>,
+    log_list_sig: Option<PathBuf>,
+    log_list_key: Option<PathBuf>,
+    extra_log_lists: Option<Vec<PathBuf>>,
+    log_list_reload: Option<u64>,
+    rate_limits: Option<PathBuf>,
+}
+
+/// Splits a comma-separated list of log IDs.
+fn split_log_ids(ids: &str) -> Vec<String> {
+    ids.split(',')
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn path(path: &OsStr) -> Result<PathBuf, Infallible> {
+    Ok(PathBuf::from(path))
+}
+
+fn paths(paths: &OsStr) -> Result<Vec<PathBuf>, Infallible> {
+    Ok(std::env::split_paths(paths).collect())
+}
+
+impl Overrides {
+    fn from_env(env: &dyn Fn(&str) -> Option<OsString>) -> Result<Self, ConfigError> {
+        let string = |name: &'static str| {
+            env(name)
+                .map(|value| value.into_string().map_err(|_| ConfigError::BadEnv(name)))
+                .transpose()
+        };
+        Ok(Self {
+            data_path: env("BELVI_DATA_PATH").map(PathBuf::from),
+            redis_addr: string("BELVI_REDIS_ADDR")?,
+            cert_store: string("BELVI_CERT_STORE")?
+                .map(|kind| kind.parse())
+                .transpose()
+                .map_err(|_| ConfigError::BadEnv("BELVI_CERT_STORE"))?,
+            compress_certs: string("BELVI_COMPRESS_CERTS")?
+                .map(|compress| compress.parse())
+                .transpose()
+                .map_err(|_| ConfigError::BadEnv("BELVI_COMPRESS_CERTS"))?,
+            cert_ttl: string("BELVI_CERT_TTL")?
+                .map(|ttl| ttl.parse())
+                .transpose()
+                .map_err(|_| ConfigError::BadEnv("BELVI_CERT_TTL"))?,
+            cert_cache_max_bytes: string("BELVI_CERT_CACHE_MAX_BYTES")?
+                .map(|bytes| bytes.parse())
+                .transpose()
+                .map_err(|_| ConfigError::BadEnv("BELVI_CERT_CACHE_MAX_BYTES"))?,
+            cert_unexpired_only: string("BELVI_CERT_UNEXPIRED_ONLY")?
+                .map(|unexpired| unexpired.parse())
+                .transpose()
+                .map_err(|_| ConfigError::BadEnv("BELVI_CERT_UNEXPIRED_ONLY"))?,
+            bind_addr: string("BELVI_BIND_ADDR")?
+                .map(|addr| addr.parse())
+                .transpose()
+                .map_err(|_| ConfigError::BadEnv("BELVI_BIND_ADDR"))?,
+            contact: string("BELVI_CONTACT")?,
+            cache_certs: match string("BELVI_CACHE_CERTS")? {
+                Some(cache) => Some(
+                    cache
+                        .parse()
+                        .map_err(|_| ConfigError::BadEnv("BELVI_CACHE_CERTS"))?,
+                ),
+                None => env("BELVI_NO_CACHE").map(|_| false),
+            },
+            include_test_logs: string("BELVI_INCLUDE_TEST_LOGS")?
+                .map(|include| include.parse())
+                .transpose()
+                .map_err(|_| ConfigError::BadEnv("BELVI_INCLUDE_TEST_LOGS"))?,
+            batches_per_log: string("BELVI_BATCHES_PER_LOG")?
+                .map(|count| count.parse())
+                .transpose()
+                .map_err(|_| ConfigError::BadEnv("BELVI_BATCHES_PER_LOG"))?,
+            backfill_logs: string("BELVI_BACKFILL_LOGS")?.map(|ids| split_log_ids(&ids)),
+            log_list: env("BELVI_LOG_LIST").map(PathBuf::from),
+            log_list_sig: env("BELVI_LOG_LIST_SIG").map(PathBuf::from),
+            log_list_key: env("BELVI_LOG_LIST_KEY").map(PathBuf::from),
+            extra_log_lists: env("BELVI_EXTRA_LOG_LISTS")
+                .map(|list| std::env::split_paths(&list).collect()),
+            log_list_reload: string("BELVI_LOG_LIST_RELOAD")?
+                .map(|secs| secs.parse())
+                .transpose()
+                .map_err(|_| ConfigError::BadEnv("BELVI_LOG_LIST_RELOAD"))?,
+            rate_limits: env("BELVI_RATE_LIMITS").map(PathBuf::from),
+        })
+    }
+
+    fn from_args(args: &mut pico_args::Arguments) -> Result<Self, ConfigError> {
+        Ok(Self {
+            data_path: args
+                .opt_value_from_os_str("--data-path", path)
+                .map_err(ConfigError::Args)?,
+            redis_addr: args
+                .opt_value_from_str("--redis-addr")
+                .map_err(ConfigError::Args)?,
+            cert_store: args
+                .opt_value_from_str("--cert-store")
+                .map_err(ConfigError::Args)?,
+            compress_certs: args
+                .opt_value_from_str("--compress-certs")
+                .map_err(ConfigError::Args)?,
+            cert_ttl: args
+                .opt_value_from_str("--cert-ttl")
+                .map_err(ConfigError::Args)?,
+            cert_cache_max_bytes: args
+                .opt_value_from_str("--cert-cache-max-bytes")
+                .map_err(ConfigError::Args)?,
+            cert_unexpired_only: args
+                .opt_value_from_str("--cert-unexpired-only")
+                .map_err(ConfigError::Args)?,
+            bind_addr: args
+                .opt_value_from_str("--bind-addr")
+                .map_err(ConfigError::Args)?,
+            contact: args
+                .opt_value_from_str("--contact")
+                .map_err(ConfigError::Args)?,
+            cache_certs: args
+                .opt_value_from_str("--cache-certs")
+                .map_err(ConfigError::Args)?,
+            include_test_logs: args
+                .opt_value_from_str("--include-test-logs")
+                .map_err(ConfigError::Args)?,
+            batches_per_log: args
+                .opt_value_from_str("--batches-per-log")
+                .map_err(ConfigError::Args)?,
+            backfill_logs: args
+                .opt_value_from_fn("--backfill-logs", |ids| {
+                    Ok::<_, Infallible>(split_log_ids(ids))
+                })
+                .map_err(ConfigError::Args)?,
+            log_list: args
+                .opt_value_from_os_str("--log-list", path)
+                .map_err(ConfigError::Args)?,
+            log_list_sig: args
+                .opt_value_from_os_str("--log-list-sig", path)
+                .map_err(ConfigError::Args)?,
+            log_list_key: args
+                .opt_value_from_os_str("--log-list-key", path)
+                .map_err(ConfigError::Args)?,
+            extra_log_lists: args
+                .opt_value_from_os_str("--extra-log-lists", paths)
+                .map_err(ConfigError::Args)?,
+            log_list_reload: args
+                .opt_value_from_str("--log-list-reload")
+                .map_err(ConfigError::Args)?,
+            rate_limits: args
+                .opt_value_from_os_str("--rate-limits", path)
+                .map_err(ConfigError::Args)?,
+        })
+    }
+
+    fn from_file(path: &Path) -> Result<Self, ConfigError> {
+        let data = fs::read_to_string(path).map_err(|err| ConfigError::Io {
+            path: path.to_path_buf(),
+            err,
+        })?;
+        toml::from_str(&data).map_err(|err| ConfigError::Toml {
+            path: path.to_path_buf(),
+            err,
+        })
+    }
+
+    fn apply(self, config: &mut Config) {
+        if let Some(data_path) = self.data_path {
+            config.data_path = data_path;
+        }
+        if let Some(redis_addr) = self.redis_addr {
+            config.redis_addr = redis_addr;
+        }
+        if let Some(cert_store) = self.cert_store {
+            config.cert_store = cert_store;
+        }
+        if let Some(compress_certs) = self.compress_certs {
+            config.compress_certs = compress_certs;
+        }
+        if let Some(cert_ttl) = self.cert_ttl {
+            config.cert_ttl = Some(cert_ttl);
+        }
+        if let Some(cert_cache_max_bytes) = self.cert_cache_max_bytes {
+            config.cert_cache_max_bytes = Some(cert_cache_max_bytes);
+        }
+        if let Some(cert_unexpired_only) = self.cert_unexpired_only {
+            config.cert_unexpired_only = cert_unexpired_only;
+        }
+        if let Some(bind_addr) = self.bind_addr {
+            config.bind_addr = bind_addr;
+        }
+        if let Some(contact) = self.contact {
+            config.contact = contact;
+        }
+        if let Some(cache_certs) = self.cache_certs {
+            config.cache_certs = cache_certs;
+        }
+        if let Some(include_test_logs) = self.include_test_logs {
+            config.include_test_logs = include_test_logs;
+        }
+        if let Some(batches_per_log) = self.batches_per_log {
+            config.batches_per_log = batches_per_log;
+        }
+        if let Some(backfill_logs) = self.backfill_logs {
+            config.backfill_logs = backfill_logs;
+        }
+        if let Some(log_list) = self.log_list {
+            config.log_list = Some(log_list);
+        }
+        if let Some(log_list_sig) = self.log_list_sig {
+            config.log_list_sig = Some(log_list_sig);
+        }
+        if let Some(log_list_key) = self.log_list_key {
+            config.log_list_key = Some(log_list_key);
+        }
+        if let Some(extra_log_lists) = self.extra_log_lists {
+            config.extra_log_lists = extra_log_lists;
+        }
+        if let Some(log_list_reload) = self.log_list_reload {
+            config.log_list_reload = log_list_reload;
+        }
+        if let Some(rate_limits) = self.rate_limits {
+            config.rate_limits = Some(rate_limits);
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the command line, environment variables, and config file.
+    pub fn load() -> Result<Self, ConfigError> {
+        Self::load_from(std::env::args_os().skip(1).collect(), &|name| {
+            std::env::var_os(name)
+        })
+    }
+
+    fn load_from(
+        args: Vec<OsString>,
+        env: &dyn Fn(&str) -> Option<OsString>,
+    ) -> Result<Self, ConfigError> {
+        let mut args = pico_args::Arguments::from_vec(args);
+        let config_path = args
+            .opt_value_from_os_str("--config", path)
+            .map_err(ConfigError::Args)?
+            .or_else(|| env("BELVI_CONFIG").map(PathBuf::from));
+        let cli = Overrides::from_args(&mut args)?;
+        let mut positional = Vec::new();
+        for arg in args.finish() {
+            let arg = arg
+                .into_string()
+                .map_err(|arg| ConfigError::UnknownFlag(arg.to_string_lossy().into_owned()))?;
+            if arg.starts_with("--") {
+                return Err(ConfigError::UnknownFlag(arg));
+            }
+            positional.push(arg);
+        }
+
+        let mut config = Self::default();
+        if let Some(path) = &config_path {
+            Overrides::from_file(path)?.apply(&mut config);
+        }
+        Overrides::from_env(env)?.apply(&mut config);
+        cli.apply(&mut config);
+        if config.data_path.as_os_str().is_empty() && !positional.is_empty() {
+            config.data_path = positional.remove(0).into();
+        }
+        config.args = positional;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |setting, reason| Err(ConfigError::Invalid { setting, reason });
+        if self.data_path.as_os_str().is_empty() {
+            return invalid("data_path", "isn't set");
+        }
+        if !self.data_path.is_dir() {
+            return invalid("data_path", "isn't a directory");
+        }
+        match self.redis_addr.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+            _ => return invalid("redis_addr", "must be host:port"),
+        }
+        let retention = self.cert_ttl.is_some()
+            || self.cert_cache_max_bytes.is_some()
+            || self.cert_unexpired_only;
+        if retention
+            && !matches!(
+                self.cert_store,
+                CertStoreKind::Redis | CertStoreKind::Memory
+            )
+        {
+            return invalid(
+                "cert_store",
+                "certificates can only be dropped from the redis and memory stores",
+            );
+        }
+        if self.cert_cache_max_bytes.is_some() && self.cert_store == CertStoreKind::Redis {
+            return invalid(
+                "cert_cache_max_bytes",
+                "set maxmemory and maxmemory-policy on the Redis server instead",
+            );
+        }
+        if self.cert_ttl == Some(0) {
+            return invalid("cert_ttl", "must be more than 0");
+        }
+        if self.contact.is_empty()
+            || !self
+                .contact
+                .chars()
+                .all(|c| c.is_ascii_graphic() || c == ' ')
+        {
+            return invalid("contact", "must be printable ASCII");
+        }
+        if self.batches_per_log == 0 {
+            return invalid("batches_per_log", "must be more than 0");
+        }
+        if self.log_list.is_none() {
+            if self.log_list_sig.is_some() {
+                return invalid("log_list_sig", "can only be set with log_list");
+            }
+            if self.log_list_key.is_some() {
+                return invalid("log_list_key", "can only be set with log_list");
+            }
+        }
+        let files = [
+            ("log_list", &self.log_list),
+            ("log_list_key", &self.log_list_key),
+            ("rate_limits", &self.rate_limits),
+        ];
+        for (setting, file) in files {
+            if matches!(file, Some(file) if !file.is_file()) {
+                return invalid(setting, "isn't a file");
+            }
+        }
+        if !self.extra_log_lists.iter().all(|file| file.is_file()) {
+            return invalid("extra_log_lists", "isn't a file");
+        }
+        if self.log_list_reload == 0 {
+            return invalid("log_list_reload", "must be more than 0");
+        }
+        Ok(())
+    }
+}
+
+lazy_static::lazy_static! {
+    static ref CONFIG: Config = match Config::load() {
+        Ok(config) => config,
+        Err(err) => {
+            eprintln!("Invalid configuration: {:?}", err);
+            std::process::exit(2);
+        }
+    };
+}
+
+/// The configuration of this process. It is loaded when this is first called, and the process
+/// exits if the configuration is invalid.
+pub fn get() -> &'static Config {
+    &CONFIG
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn load(args: &[&str], env: &[(&str, &str)]) -> Result<Config, ConfigError> {
+        let env: HashMap<String, OsString> =
+            env.iter().map(|(k, v)| (k.to_string(), v.into())).collect();
+        Config::load_from(args.iter().map(OsString::from).collect(), &|name| {
+            env.get(name).cloned()
+        })
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("belvi-config-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn precedence() {
+        let dir = test_dir("precedence");
+        let data = dir.to_str().unwrap();
+        let file = dir.join("belvi.toml");
+        fs::write(
+            &file,
+            format!(
+                "data_path = \"{}\"\nredis_addr = \"redis.internal:6379\"\ncontact = \"file@example.com\"\nbind_addr = \"127.0.0.1:8000\"\n",
+                data
+            ),
+        )
+        .unwrap();
+        let file = file.to_str().unwrap();
+
+        let config = load(&["--config", file], &[]).unwrap();
+        assert_eq!(config.redis_addr, "redis.internal:6379");
+        assert_eq!(config.bind_addr, "127.0.0.1:8000".parse().unwrap());
+        assert_eq!(config.data_path, dir);
+
+        let config = load(
+            &["--config", file, "--contact", "cli@example.com"],
+            &[
+                ("BELVI_CONTACT", "env@example.com"),
+                ("BELVI_REDIS_ADDR", "localhost:6380"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.contact, "cli@example.com");
+        assert_eq!(config.redis_addr, "localhost:6380");
+
+        let config = load(
+            &["--config", file, "--cert-store", "filesystem"],
+            &[("BELVI_CERT_STORE", "sqlite")],
+        )
+        .unwrap();
+        assert_eq!(config.cert_store, CertStoreKind::Filesystem);
+        let config = load(&["--config", file], &[("BELVI_CERT_STORE", "sqlite")]).unwrap();
+        assert_eq!(config.cert_store, CertStoreKind::Sqlite);
+        assert!(config.compress_certs);
+        let config = load(&["--config", file], &[("BELVI_COMPRESS_CERTS", "false")]).unwrap();
+        assert!(!config.compress_certs);
+        assert!(matches!(
+            load(&["--config", file], &[("BELVI_CERT_STORE", "s3")]),
+            Err(ConfigError::BadEnv("BELVI_CERT_STORE"))
+        ));
+
+        // the config file can also be given in the environment
+        let config = load(&[], &[("BELVI_CONFIG", file)]).unwrap();
+        assert_eq!(config.contact, "file@example.com");
+
+        fs::write(dir.join("bad.toml"), "data_dir = \"/\"\n").unwrap();
+        assert!(matches!(
+            load(&["--config", dir.join("bad.toml").to_str().unwrap()], &[]),
+            Err(ConfigError::Toml { .. })
+        ));
+    }
+
+    #[test]
+    fn scanner_settings() {
+        let dir = test_dir("scanner");
+        let data = dir.to_str().unwrap();
+        for name in [
+            "list.json",
+            "extra1.json",
+            "extra2.json",
+            "key.pem",
+            "limits.json",
+        ] {
+            fs::write(dir.join(name), "").unwrap();
+        }
+        let file = dir.join("belvi.toml");
+        fs::write(
+            &file,
+            format!(
+                "data_path = \"{0}\"\nbatches_per_log = 2\nbackfill_logs = [\"all\"]\nlog_list = \"{0}/list.json\"\nextra_log_lists = [\"{0}/extra1.json\"]\n",
+                data
+            ),
+        )
+        .unwrap();
+        let file = file.to_str().unwrap();
+
+        let config = load(&["--config", file], &[]).unwrap();
+        assert_eq!(config.batches_per_log, 2);
+        assert_eq!(config.backfill_logs, vec!["all".to_string()]);
+        assert_eq!(config.log_list, Some(dir.join("list.json")));
+        assert_eq!(config.extra_log_lists, vec![dir.join("extra1.json")]);
+        assert!(config.cache_certs);
+        assert!(!config.include_test_logs);
+
+        let extra =
+            std::env::join_paths([dir.join("extra1.json"), dir.join("extra2.json")]).unwrap();
+        let limits = dir.join("limits.json");
+        let config = load(
+            &[
+                "--config",
+                file,
+                "--batches-per-log",
+                "8",
+                "--rate-limits",
+                limits.to_str().unwrap(),
+            ],
+            &[
+                ("BELVI_BATCHES_PER_LOG", "16"),
+                ("BELVI_BACKFILL_LOGS", "a,,b"),
+                ("BELVI_EXTRA_LOG_LISTS", extra.to_str().unwrap()),
+                ("BELVI_INCLUDE_TEST_LOGS", "true"),
+                ("BELVI_NO_CACHE", "1"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(config.batches_per_log, 8);
+        assert_eq!(config.backfill_logs, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(
+            config.extra_log_lists,
+            vec![dir.join("extra1.json"), dir.join("extra2.json")]
+        );
+        assert_eq!(config.rate_limits, Some(limits));
+        assert!(config.include_test_logs);
+        assert!(!config.cache_certs);
+        let config = load(
+            &[data, "--cache-certs", "true"],
+            &[("BELVI_CACHE_CERTS", "false")],
+        )
+        .unwrap();
+        assert!(config.cache_certs);
+
+        assert!(matches!(
+            load(&["--config", file, "--batches-per-log", "0"], &[]),
+            Err(ConfigError::Invalid {
+                setting: "batches_per_log",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(&["--config", file], &[("BELVI_BATCHES_PER_LOG", "many")]),
+            Err(ConfigError::BadEnv("BELVI_BATCHES_PER_LOG"))
+        ));
+        assert!(matches!(
+            load(&["--config", file], &[("BELVI_LOG_LIST_RELOAD", "0")]),
+            Err(ConfigError::Invalid {
+                setting: "log_list_reload",
+                ..
+            })
+        ));
+        let key = dir.join("key.pem");
+        assert!(matches!(
+            load(&[data, "--log-list-key", key.to_str().unwrap()], &[]),
+            Err(ConfigError::Invalid {
+                setting: "log_list_key",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(&["--config", file], &[("BELVI_LOG_LIST", data)]),
+            Err(ConfigError::Invalid {
+                setting: "log_list",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(
+                &["--config", file, "--extra-log-lists", "missing.json"],
+                &[]
+            ),
+            Err(ConfigError::Invalid {
+                setting: "extra_log_lists",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn positional_data_path() {
+        let dir = test_dir("positional");
+        let data = dir.to_str().unwrap();
+        let config = load(&[data, "status"], &[]).unwrap();
+        assert_eq!(config.data_path, dir);
+        assert_eq!(config.args, vec!["status".to_string()]);
+        assert_eq!(
+            config,
+            Config {
+                data_path: dir.clone(),
+                args: config.args.clone(),
+                ..Config::default()
+            }
+        );
+
+        // if the data path is set, all positional arguments are left for the tool
+        let config = load(&["42"], &[("BELVI_DATA_PATH", data)]).unwrap();
+        assert_eq!(config.args, vec!["42".to_string()]);
+    }
+
+    #[test]
+    fn invalid() {
+        let dir = test_dir("invalid");
+        let data = dir.to_str().unwrap();
+        assert!(matches!(
+            load(&[], &[]),
+            Err(ConfigError::Invalid {
+                setting: "data_path",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(&[dir.join("missing").to_str().unwrap()], &[]),
+            Err(ConfigError::Invalid {
+                setting: "data_path",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(&[data, "--redis-addr", "localhost"], &[]),
+            Err(ConfigError::Invalid {
+                setting: "redis_addr",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(&[data, "--contact", "me\n"], &[]),
+            Err(ConfigError::Invalid {
+                setting: "contact",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(&[data], &[("BELVI_BIND_ADDR", "everywhere")]),
+            Err(ConfigError::BadEnv("BELVI_BIND_ADDR"))
+        ));
+        assert!(matches!(
+            load(&[data, "--cert-store", "sqlite", "--cert-ttl", "60"], &[]),
+            Err(ConfigError::Invalid {
+                setting: "cert_store",
+                ..
+            })
+        ));
+        assert!(matches!(
+            load(&[data, "--cert-cache-max-bytes", "1024"], &[]),
+            Err(ConfigError::Invalid {
+                setting: "cert_cache_max_bytes",
+                ..
+            })
+        ));
+        load(
+            &[
+                data,
+                "--cert-store",
+                "memory",
+                "--cert-cache-max-bytes",
+                "1024",
+            ],
+            &[],
+        )
+        .unwrap();
+        assert!(matches!(
+            load(&[data, "--verbose"], &[]),
+            Err(ConfigError::UnknownFlag(_))
+        ));
+    }
+}
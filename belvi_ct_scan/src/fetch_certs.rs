@@ -1,175 +1,216 @@
 // SPDX-License-Identifier: Apache-2.0
-use crate::{Ctx, FetchState, LogId};
-use bcder::decode::Constructed;
-use belvi_log_list::{log_data::LogEntry, Log};
+use crate::{writer::Batch, Ctx, FetchState, LogId};
+use belvi_log_list::{
+    fetcher::{FetchError, Fetcher},
+    log_data::{GetEntriesItem, LogSth},
+    Log,
+};
+use chrono::Utc;
 use log::{debug, info, trace, warn};
-use std::sync::Mutex;
-use x509_certificate::asn1time::Time;
 
 pub mod batcher;
+mod inclusion;
 
-fn time_to_unix(time: Time) -> i64 {
-    match time {
-        Time::UtcTime(time) => *time,
-        Time::GeneralTime(time) => time.into(),
+#[derive(Debug)]
+#[allow(dead_code)] // fields are only read through Debug
+pub enum BatchError {
+    Fetch(FetchError),
+    /// The log didn't return any entries.
+    EmptyResponse,
+    /// The log returned more entries than were requested.
+    TooManyEntries {
+        start: u64,
+        end: u64,
+        received: u64,
+    },
+    /// The entries aren't in the log's tree.
+    NotIncluded(inclusion::InclusionError),
+}
+
+/// Fetches the inclusive range `start..=end` from a log, and checks that the entries are in the
+/// tree of `sth`. Logs can return fewer entries than requested, so requests are made until the
+/// whole range is fetched.
+pub async fn fetch_batch(
+    fetcher: &Fetcher,
+    log: &Log,
+    sth: &LogSth,
+    start: u64,
+    end: u64,
+) -> Result<Vec<GetEntriesItem>, BatchError> {
+    assert!(start <= end);
+    let mut entries = Vec::new();
+    while start + (entries.len() as u64) <= end {
+        let next = start + entries.len() as u64;
+        let mut fetched = if log.is_tiled() {
+            fetcher
+                .fetch_tiled_entries(log, next, end, sth.tree_size)
+                .await
+        } else {
+            fetcher.fetch_entries(log, next, end).await
+        }
+        .map_err(|err| {
+            warn!(
+                "Failed to fetch certs for \"{}\" (range: {}-{}): {:?}",
+                log.description, next, end, err
+            );
+            BatchError::Fetch(err)
+        })?;
+        if fetched.is_empty() {
+            return Err(BatchError::EmptyResponse);
+        }
+        if next + fetched.len() as u64 - 1 > end {
+            return Err(BatchError::TooManyEntries {
+                start: next,
+                end,
+                received: fetched.len() as u64,
+            });
+        }
+        trace!(
+            "Got {} entries at {} from \"{}\"",
+            fetched.len(),
+            next,
+            log.description
+        );
+        entries.append(&mut fetched);
     }
-    .timestamp()
+    inclusion::check_batch(fetcher, log, sth, start, &entries)
+        .await
+        .map_err(BatchError::NotIncluded)?;
+    Ok(entries)
 }
 
-impl<'ctx> FetchState {
-    pub async fn fetch_next_batch(
-        self_mutex: &Mutex<Self>,
-        ctx: &Mutex<Ctx>,
+impl FetchState {
+    /// Fetches up to [`Ctx::batches_per_log`] batches from a log at once. Returns `None` if there
+    /// was nothing to fetch.
+    pub async fn fetch_batches(
+        &self,
+        ctx: &Ctx,
         log: &Log,
-    ) -> Option<u64> {
-        info!("Fetching batch of certs from \"{}\"", log.description);
+    ) -> Option<(
+        Vec<(u64, u64)>,
+        Vec<Result<Vec<GetEntriesItem>, BatchError>>,
+    )> {
         let id = LogId(log.log_id.clone());
-        let inner_ctx = ctx.lock().unwrap();
-        let next_batch = {
-            self_mutex
-                .lock()
-                .unwrap()
-                .next_batch(&inner_ctx, id.clone())
-        };
-        trace!("Desired range is {:?}", next_batch);
-        if let Some((start, end)) = next_batch {
-            assert!(start <= end);
-            let fetcher = inner_ctx.fetcher.clone();
-            let entries_future = fetcher.fetch_entries(log, start, end);
-            drop(inner_ctx);
-            match entries_future.await {
-                Ok(entries) => {
-                    assert!(
-                        !entries.is_empty(),
-                        "CT log sent empty response to get-entries"
-                    );
-                    let new_end = start + entries.len() as u64 - 1; // update requested end to actual end
-                    assert!(
-                        new_end <= end,
-                        "CT log sent more certs than requested: asked for {}-{} ({} entries), got end of {} ({} entries)",
-                        start,
-                        end,
-                        end - start + 1, // add 1 since inclusive of bounds
-                        new_end,
-                        entries.len(),
-                    );
-                    let end = new_end;
-                    let mut inner_ctx = ctx.lock().unwrap();
-                    let transient_entry = inner_ctx.log_transient.entry(id.clone()).or_default();
-                    transient_entry.fetches += 1;
-                    transient_entry.highest_page_size = transient_entry
-                        .highest_page_size
-                        .max(entries.len().try_into().expect(">64 bit?"));
-                    let mut cert_insert = inner_ctx
-                    .sqlite_conn
-                        .prepare_cached(
-                            "INSERT OR IGNORE INTO certs (leaf_hash, extra_hash, not_before, not_after, cert_type) VALUES (?, ?, ?, ?, ?)",
-                        )
-                        .unwrap();
-                    let mut entry_insert = inner_ctx
-                        .sqlite_conn
-                        .prepare_cached("INSERT OR IGNORE INTO log_entries (leaf_hash, log_id, ts, idx) VALUES (?, ?, ?, ?)")
-                        .unwrap();
-                    let mut domain_insert = inner_ctx
-                        .sqlite_conn
-                        .prepare_cached(
-                            "INSERT OR IGNORE INTO domains (leaf_hash, domain) VALUES (?, ?)",
-                        )
-                        .unwrap();
-                    let mut new_cache_items = Vec::new();
-                    for (idx, entry) in entries.into_iter().enumerate() {
-                        let idx: u64 = idx as u64 + start;
-                        let log_timestamp = entry.leaf_input.timestamped_entry.timestamp;
-                        let log_entry = &entry.leaf_input.timestamped_entry.log_entry;
-                        let cert_bytes = log_entry.inner_cert();
-                        let (cert_type, cert) = if let LogEntry::X509(cert) = log_entry {
-                            let cert: x509_certificate::rfc5280::Certificate =
-                                x509_certificate::X509Certificate::from_der(cert)
-                                    .unwrap()
-                                    .into();
-                            ("cert", cert.tbs_certificate)
-                        } else {
-                            let cert = Constructed::decode(
-                                cert_bytes.as_ref(),
-                                bcder::Mode::Der,
-                                |cons| x509_certificate::rfc5280::TbsCertificate::take_from(cons),
-                            )
-                            .expect("invalid cert in log");
-                            ("precert", cert)
-                        };
-
-                        let domains = belvi_cert::get_cert_domains(&cert);
-                        assert!(!domains.contains(&b"&".to_vec()), "{:#?}", cert);
+        let batches = self.next_batches(ctx, log, ctx.batches_per_log);
+        if batches.is_empty() {
+            trace!("Already updated certs for \"{}\"", log.description);
+            return None;
+        }
+        info!(
+            "Fetching {} batches of certs from \"{}\"",
+            batches.len(),
+            log.description
+        );
+        trace!("Desired ranges are {:?}", batches);
+        let sth = &self.log_states[&id].sth;
+        let results = futures::future::join_all(
+            batches
+                .iter()
+                .map(|&(start, end)| fetch_batch(&ctx.fetcher, log, sth, start, end)),
+        )
+        .await;
+        Some((batches, results))
+    }
 
-                        let validity = &cert.validity;
-                        let not_before = validity.not_before.clone();
-                        let not_after = validity.not_after.clone();
-                        trace!(
-                            "idx {} of \"{}\": {} with ts {}, valid from {:?} to {:?}",
-                            idx,
-                            log.description,
-                            cert_type,
-                            log_timestamp,
-                            not_before,
-                            not_after,
+    /// Adds fetched batches to the fetch state, and sends them to the writer. Batches that
+    /// succeeded are kept even if others failed, since the fetch state can have gaps. Returns the
+    /// number of entries fetched, or the first error. Errors (including batches that aren't in the
+    /// log's tree) make the log back off, so failed batches aren't fetched again right away.
+    pub async fn commit_batches(
+        &mut self,
+        ctx: &mut Ctx,
+        log: &Log,
+        batches: Vec<(u64, u64)>,
+        results: Vec<Result<Vec<GetEntriesItem>, BatchError>>,
+    ) -> Result<u64, BatchError> {
+        let id = LogId(log.log_id.clone());
+        let mut count = 0;
+        let mut first_err = None;
+        for ((start, end), result) in batches.into_iter().zip(results) {
+            let entries = match result {
+                Ok(entries) => entries,
+                Err(err) => {
+                    if let BatchError::NotIncluded(err) = &err {
+                        let tree_size = self.log_states[&id].sth.tree_size;
+                        warn!(
+                            "Rejecting certs from \"{}\" (range: {}-{}) that aren't in STH of size {}: {:?}",
+                            log.description, start, end, tree_size, err
                         );
-                        let leaf_hash_bytes = belvi_hash::db(log_entry.inner_cert());
-                        let leaf_hash = leaf_hash_bytes.to_vec();
-                        let extra_hash = belvi_hash::db(&entry.extra_data);
-                        cert_insert
-                            .execute(rusqlite::params![
-                                leaf_hash,
-                                extra_hash.to_vec(),
-                                time_to_unix(not_before),
-                                time_to_unix(not_after),
-                                log_entry.num(),
-                            ])
-                            .expect("failed to insert cert");
-                        entry_insert
-                            .execute(rusqlite::params![leaf_hash, id.num(), log_timestamp, idx])
-                            .expect("failed to insert entry");
-                        for domain in domains {
-                            domain_insert
-                                .execute(rusqlite::params![
-                                    leaf_hash,
-                                    String::from_utf8_lossy(&domain)
-                                ])
-                                .expect("failed to insert domain");
-                        }
-                        if inner_ctx.cache_certs {
-                            let cache_item_contents = log_entry.inner_cert().clone();
-                            new_cache_items.push((leaf_hash_bytes, cache_item_contents));
-                        }
-                    }
-                    drop(cert_insert);
-                    drop(entry_insert);
-                    drop(domain_insert);
-                    // TODO: parallelize
-                    for (id, content) in new_cache_items {
-                        inner_ctx.redis_conn.new_cert(&id, &content); // disable by default
-                    }
-                    drop(inner_ctx);
-                    debug!("Fetched {}-{} from \"{}\"", start, end, log.description);
-                    // adjust log_states
-                    {
-                        let mut self_inner = self_mutex.lock().unwrap();
-                        let log_state =
-                            self_inner.log_states.get_mut(&id).expect("no data for log");
-                        log_state.fetched_to = log_state.fetched_to.merge_fetched((start, end));
+                        ctx.writer
+                            .reject_batch(id.clone(), start, end, tree_size, format!("{:?}", err))
+                            .await;
                     }
-                    Some(end - start + 1)
+                    first_err.get_or_insert(err);
+                    continue;
                 }
-                Err(err) => {
-                    warn!(
-                        "Failed to fetch certs for \"{}\" (range: {}-{}): {:?}",
-                        log.description, start, end, err
+            };
+            let transient_entry = ctx.log_transient.entry(id.clone()).or_default();
+            transient_entry.fetches += 1;
+            transient_entry.highest_page_size = transient_entry
+                .highest_page_size
+                .max(entries.len().try_into().expect(">64 bit?"));
+            count += entries.len() as u64;
+            let log_state = self.log_states.get_mut(&id).expect("no data for log");
+            log_state.fetched_to.insert((start, end));
+            ctx.writer
+                .write_batch(Batch {
+                    log_id: id.clone(),
+                    description: log.description.clone(),
+                    mmd: log.mmd,
+                    start,
+                    entries,
+                    fetch_state: log_state.clone(),
+                })
+                .await;
+            debug!("Fetched {}-{} from \"{}\"", start, end, log.description);
+        }
+        match first_err {
+            Some(err) => {
+                if count > 0 {
+                    info!(
+                        "Fetched {} certs from \"{}\" despite error",
+                        count, log.description
                     );
-                    None
                 }
+                ctx.record_log_error(&id, &log.description, format!("{:?}", err));
+                Err(err)
             }
-        } else {
-            trace!("Already updated certs for \"{}\"", log.description);
-            None
+            None => {
+                ctx.record_log_success(&id, &log.description);
+                Ok(count)
+            }
+        }
+    }
+
+    /// Logs how much of each log has been fetched, and stores it so it can be shown by the
+    /// frontend.
+    pub fn save_progress(&self, ctx: &Ctx) {
+        let now = Utc::now().timestamp_millis();
+        for log in ctx.active_logs() {
+            let id = LogId(log.log_id.clone());
+            let state = match self.log_states.get(&id) {
+                Some(state) => state,
+                None => continue,
+            };
+            let fetched = state.fetched_to.fetched_in(state.sth.tree_size);
+            info!(
+                "\"{}\" is {:.2}% indexed ({} of {} entries)",
+                log.description,
+                state.progress(),
+                fetched,
+                state.sth.tree_size
+            );
+            ctx.sqlite_conn
+                .prepare_cached("INSERT OR REPLACE INTO log_progress (log_id, fetched, tree_size, backfill, ts) VALUES (?, ?, ?, ?, ?)")
+                .unwrap()
+                .execute(rusqlite::params![
+                    id.num(),
+                    fetched,
+                    state.sth.tree_size,
+                    ctx.backfill(log),
+                    now,
+                ])
+                .expect("failed to save progress");
         }
     }
 }
@@ -1,8 +1,8 @@
 // SPDX-License-Identifier: Apache-2.0
-use crate::{Ctx, FetchState, LogId};
+use crate::{Ctx, FetchState, LogFetchState, LogId, LogTransient};
+use belvi_log_list::{tiles::TILE_WIDTH, Log};
 use log::trace;
 use serde::{Deserialize, Serialize};
-use std::cmp::Ordering;
 
 /// Initially request certificates in batches of this size.
 const MAX_PAGE_SIZE: u64 = 1000;
@@ -11,8 +11,22 @@ const FETCHES_FOR_SMALLER_PAGES: u64 = 10;
 /// We always want at least the last N certs for every log.
 const MIN_HISTORY: u64 = 5000;
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-pub enum HistState {
+/// The entries that have been fetched from a log, as sorted inclusive ranges. Ranges never
+/// overlap or touch, since they are merged when they are added.
+///
+/// Batches can be fetched in any order, so there can be any number of gaps between the ranges.
+/// Ranges are only added once the entries are in the database, so a range is never lost or
+/// recorded early if the scanner stops in the middle of a batch.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(from = "StoredHistState")]
+pub struct HistState {
+    fetched: Vec<(u64, u64)>,
+}
+
+/// Fetch states from older versions of the scanner had one fetched range, with a gap below it
+/// that was being filled.
+#[derive(Deserialize)]
+enum LegacyHistState {
     NothingFetched,
     FillingHistGap {
         hist_gap: (u64, u64),
@@ -21,129 +35,279 @@ pub enum HistState {
     Fetching((u64, u64)),
 }
 
-impl Default for HistState {
-    fn default() -> Self {
-        Self::NothingFetched
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredHistState {
+    Current { fetched: Vec<(u64, u64)> },
+    Legacy(LegacyHistState),
+}
+
+impl From<StoredHistState> for HistState {
+    fn from(stored: StoredHistState) -> Self {
+        let ranges = match stored {
+            StoredHistState::Current { fetched } => fetched,
+            StoredHistState::Legacy(LegacyHistState::NothingFetched) => Vec::new(),
+            StoredHistState::Legacy(LegacyHistState::Fetching(fetched)) => vec![fetched],
+            StoredHistState::Legacy(LegacyHistState::FillingHistGap { hist_gap, fetching }) => {
+                vec![hist_gap, fetching]
+            }
+        };
+        let mut state = Self::default();
+        for range in ranges {
+            state.insert(range);
+        }
+        state
     }
 }
 
 impl HistState {
-    #[must_use]
-    fn merge_adjacent_ranges((a1, a2): (u64, u64), (b1, b2): (u64, u64)) -> Option<(u64, u64)> {
-        if a1 == (b2 + 1) {
-            // going forwards
-            Some((b1, a2))
-        } else if a2 == (b1 - 1) {
-            // going backwardss
-            Some((a1, b2))
-        } else {
-            None
+    /// Marks the inclusive range `start..=end` as fetched.
+    pub fn insert(&mut self, (mut start, mut end): (u64, u64)) {
+        assert!(start <= end);
+        let mut merged = Vec::with_capacity(self.fetched.len() + 1);
+        let mut inserted = false;
+        for &(cur_start, cur_end) in &self.fetched {
+            if cur_end.saturating_add(1) < start {
+                // entirely before the new range
+                merged.push((cur_start, cur_end));
+            } else if end.saturating_add(1) < cur_start {
+                // entirely after the new range
+                if !inserted {
+                    merged.push((start, end));
+                    inserted = true;
+                }
+                merged.push((cur_start, cur_end));
+            } else {
+                start = start.min(cur_start);
+                end = end.max(cur_end);
+            }
+        }
+        if !inserted {
+            merged.push((start, end));
         }
+        self.fetched = merged;
     }
+
+    /// Index of the first entry that has been fetched.
     #[must_use]
-    pub fn merge_fetched(self, new_range: (u64, u64)) -> Self {
-        match self {
-            Self::NothingFetched => Self::Fetching(new_range),
-            Self::Fetching(fetched) => {
-                if let Some(updated) = Self::merge_adjacent_ranges(fetched, new_range) {
-                    Self::Fetching(updated)
-                } else {
-                    Self::FillingHistGap {
-                        fetching: fetched,
-                        hist_gap: new_range,
-                    }
-                }
+    pub fn lowest(&self) -> Option<u64> {
+        self.fetched.first().map(|&(start, _)| start)
+    }
+
+    /// Number of entries that have been fetched from a tree of `tree_size` entries.
+    #[must_use]
+    pub fn fetched_in(&self, tree_size: u64) -> u64 {
+        self.fetched
+            .iter()
+            .take_while(|&&(start, _)| start < tree_size)
+            .map(|&(start, end)| end.min(tree_size - 1) - start + 1)
+            .sum()
+    }
+
+    /// Returns the highest range of entries within `floor..=top` that hasn't been fetched.
+    #[must_use]
+    pub fn highest_missing(&self, floor: u64, top: u64) -> Option<(u64, u64)> {
+        let mut top = top;
+        for &(start, end) in self.fetched.iter().rev() {
+            if start > top {
+                continue;
             }
-            Self::FillingHistGap { hist_gap, fetching } => {
-                let hist_gap = Self::merge_adjacent_ranges(hist_gap, new_range)
-                    .expect("non adjacent range when filling hist gap");
-                if let Some(combined) = Self::merge_adjacent_ranges(hist_gap, fetching) {
-                    Self::Fetching(combined)
-                } else {
-                    Self::FillingHistGap { fetching, hist_gap }
-                }
+            if end < top {
+                let gap_start = (end + 1).max(floor);
+                return (gap_start <= top).then_some((gap_start, top));
             }
+            // top has been fetched, so look below this range
+            top = start.checked_sub(1)?;
         }
+        (floor <= top).then_some((floor, top))
     }
 }
 
 impl FetchState {
+    /// Returns up to `max` batches to fetch next from the log. The batches don't overlap, so
+    /// they can be fetched at once.
+    pub fn next_batches(&self, ctx: &Ctx, log: &Log, max: usize) -> Vec<(u64, u64)> {
+        let id = LogId(log.log_id.clone());
+        let transient = ctx.log_transient.get(&id).copied().unwrap_or_default();
+        self.log_states[&id].next_batches(transient, log.is_tiled(), ctx.backfill(log), max)
+    }
+}
+
+impl LogFetchState {
+    fn next_batches(
+        &self,
+        transient: LogTransient,
+        tiled: bool,
+        backfill: bool,
+        max: usize,
+    ) -> Vec<(u64, u64)> {
+        let mut state = self.clone();
+        let mut batches = Vec::new();
+        while batches.len() < max {
+            match state.next_batch(transient, tiled, backfill) {
+                Some(batch) => {
+                    state.fetched_to.insert(batch);
+                    batches.push(batch);
+                }
+                None => break,
+            }
+        }
+        batches
+    }
+
     /// Returns the start and end index (inclusive) of the entries to retrieve next.
     /// The return value can be passed directly to the get-entries endpoint. `None` indicates
-    /// nothing should be fetched. The return value will be adjacent to the current fetched
-    /// endpoints.
-    pub fn next_batch(&self, ctx: &Ctx, id: LogId) -> Option<(u64, u64)> {
-        let transient = ctx
-            .log_transient
-            .get(&id)
-            .map(Clone::clone)
-            .unwrap_or_default();
-        let state = self
-            .log_states
-            .get(&id)
-            .expect("next_batch called with bad id");
+    /// nothing should be fetched. Newer entries are fetched first, so the highest missing
+    /// entries are returned. For tiled logs, the batch is limited to a single data tile.
+    ///
+    /// Without `backfill`, only entries since the first fetch (and at least the last
+    /// [`MIN_HISTORY`] entries) are wanted. With `backfill`, every entry is wanted.
+    fn next_batch(
+        &self,
+        transient: LogTransient,
+        tiled: bool,
+        backfill: bool,
+    ) -> Option<(u64, u64)> {
+        // subtract 1 to account for 0-indexing
+        let head = self.sth.tree_size.checked_sub(1)?;
+        let floor = if backfill {
+            0
+        } else {
+            let min_history = head.saturating_sub(MIN_HISTORY);
+            match self.fetched_to.lowest() {
+                Some(lowest) => lowest.min(min_history),
+                None => min_history,
+            }
+        };
+        let (gap_start, gap_end) = self.fetched_to.highest_missing(floor, head)?;
+        trace!("Highest missing range is {}-{}", gap_start, gap_end);
 
         let page_size = if transient.fetches > FETCHES_FOR_SMALLER_PAGES {
-            transient.highest_page_size
+            transient.highest_page_size.clamp(1, MAX_PAGE_SIZE)
         } else {
             MAX_PAGE_SIZE
         };
+        // start and end are both inclusive bounds!
+        let start = gap_start.max(gap_end.saturating_sub(page_size - 1));
+        Some(if tiled {
+            (start.max(gap_end - gap_end % TILE_WIDTH), gap_end)
+        } else {
+            (start, gap_end)
+        })
+    }
 
-        // subtract 1 to account for 0-indexing
-        let tree_size = state.sth.tree_size.saturating_sub(1);
+    /// Percentage of the entries in the tree that have been fetched.
+    #[must_use]
+    pub fn progress(&self) -> f64 {
+        if self.sth.tree_size == 0 {
+            100.0
+        } else {
+            self.fetched_to.fetched_in(self.sth.tree_size) as f64 * 100.0
+                / self.sth.tree_size as f64
+        }
+    }
+}
 
-        // start and end are both inclusive bounds!
-        #[must_use]
-        fn extend_range(cur_start: u64, cur_end: u64, endpoint: u64) -> Option<(u64, u64)> {
-            match cur_end.cmp(&endpoint) {
-                // we have got to the endpoint
-                Ordering::Equal => {
-                    trace!("Fetched up to endpoint");
-                    let desired_start = cur_end.saturating_sub(MIN_HISTORY);
-                    if desired_start < cur_start {
-                        Some((
-                            cur_start
-                                .saturating_sub(MIN_HISTORY)
-                                .max(cur_start.saturating_sub(MAX_PAGE_SIZE)),
-                            cur_start - 1,
-                        ))
-                    } else {
-                        None
-                    }
-                }
-                // need to fetch to get up to the endpoint
-                Ordering::Less => {
-                    trace!("Haven't fetched to endpoint");
-                    Some((
-                        // from the current end, fetch up to a page to get closer to the endpoint
-                        cur_end + 1,
-                        endpoint.min(cur_end + MAX_PAGE_SIZE),
-                    ))
-                }
-                Ordering::Greater => {
-                    panic!(
-                        "impossible, cur_end, {} is past endpoint, {}",
-                        cur_end, endpoint
-                    )
-                }
-            }
+#[cfg(test)]
+mod test {
+    use super::*;
+    use belvi_log_list::log_data::LogSth;
+
+    fn log_state(tree_size: u64, fetched_to: HistState) -> LogFetchState {
+        LogFetchState {
+            sth: LogSth {
+                tree_size,
+                timestamp: 0,
+                sha256_root_hash: String::new(),
+                tree_head_signature: String::new(),
+            },
+            fetched_to,
         }
-        match state.fetched_to {
-            HistState::NothingFetched => {
-                trace!("Initial fetch");
-                // initial fetch: one page from the beginning
-                Some((
-                    tree_size.saturating_sub(page_size - 1), // subtraction accounts for bounds inclusion
-                    tree_size,
-                ))
-            }
-            HistState::Fetching((cur_start, cur_end)) => {
-                extend_range(cur_start, cur_end, tree_size)
-            }
-            HistState::FillingHistGap {
-                hist_gap: (hist_gap_start, hist_gap_end),
-                fetching: (fetching_start, _fetching_end),
-            } => extend_range(hist_gap_start, hist_gap_end, fetching_start - 1),
+    }
+
+    fn ranges(ranges: &[(u64, u64)]) -> HistState {
+        HistState {
+            fetched: ranges.to_vec(),
+        }
+    }
+
+    #[test]
+    fn insert_ranges() {
+        let mut state = HistState::default();
+        state.insert((10, 19));
+        state.insert((40, 49));
+        state.insert((0, 4));
+        assert_eq!(state, ranges(&[(0, 4), (10, 19), (40, 49)]));
+        // touching ranges are merged
+        state.insert((20, 29));
+        assert_eq!(state, ranges(&[(0, 4), (10, 29), (40, 49)]));
+        // so are overlapping ranges, and ranges that cover others
+        state.insert((3, 45));
+        assert_eq!(state, ranges(&[(0, 49)]));
+        state.insert((100, 109));
+        assert_eq!(state.fetched_in(105), 55);
+        assert_eq!(state.fetched_in(1000), 60);
+    }
+
+    #[test]
+    fn highest_missing() {
+        let state = ranges(&[(10, 19), (40, 49)]);
+        assert_eq!(state.highest_missing(0, 99), Some((50, 99)));
+        assert_eq!(state.highest_missing(0, 49), Some((20, 39)));
+        assert_eq!(state.highest_missing(25, 49), Some((25, 39)));
+        assert_eq!(state.highest_missing(0, 15), Some((0, 9)));
+        assert_eq!(state.highest_missing(10, 49), Some((20, 39)));
+        assert_eq!(state.highest_missing(40, 49), None);
+        assert_eq!(ranges(&[(0, 9)]).highest_missing(0, 9), None);
+    }
+
+    #[test]
+    fn legacy_state() {
+        let parse = |json: &str| serde_json::from_str::<HistState>(json).unwrap();
+        assert_eq!(parse(r#""NothingFetched""#), HistState::default());
+        assert_eq!(parse(r#"{"Fetching":[5,9]}"#), ranges(&[(5, 9)]));
+        assert_eq!(
+            parse(r#"{"FillingHistGap":{"hist_gap":[0,3],"fetching":[5,9]}}"#),
+            ranges(&[(0, 3), (5, 9)])
+        );
+        let state = ranges(&[(0, 3), (5, 9)]);
+        assert_eq!(parse(&serde_json::to_string(&state).unwrap()), state);
+    }
+
+    #[test]
+    fn next_batches() {
+        let state = log_state(10_000, ranges(&[(7000, 7999)]));
+        let batches = state.next_batches(LogTransient::default(), false, false, 3);
+        assert_eq!(batches, vec![(9000, 9999), (8000, 8999), (6000, 6999)]);
+        // the batches can be added in any order
+        let mut fetched_to = state.fetched_to.clone();
+        for batch in batches.into_iter().rev() {
+            fetched_to.insert(batch);
         }
+        assert_eq!(fetched_to, ranges(&[(6000, 9999)]));
+
+        // without a backfill, fetching stops after the last MIN_HISTORY entries
+        let state = log_state(10_000, ranges(&[(4999, 9999)]));
+        assert!(state
+            .next_batches(LogTransient::default(), false, false, 3)
+            .is_empty());
+        assert_eq!(
+            state.next_batches(LogTransient::default(), false, true, 3),
+            vec![(3999, 4998), (2999, 3998), (1999, 2998)]
+        );
+        assert!((state.progress() - 50.01).abs() < 1e-9);
+
+        // tiled logs are fetched one tile at a time
+        let state = log_state(1000, HistState::default());
+        assert_eq!(
+            state.next_batches(LogTransient::default(), true, false, 3),
+            vec![(768, 999), (512, 767), (256, 511)]
+        );
+
+        let state = log_state(1000, ranges(&[(0, 999)]));
+        assert!(state
+            .next_batches(LogTransient::default(), false, true, 3)
+            .is_empty());
+        assert_eq!(state.progress(), 100.0);
     }
 }
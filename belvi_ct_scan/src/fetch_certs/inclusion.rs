@@ -0,0 +1,94 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Checks that entries returned by get-entries (or read from data tiles) are actually in the log's
+//! tree.
+use belvi_log_list::{
+    fetcher::{FetchError, Fetcher},
+    log_data::{CTParseError, GetEntriesItem, LogSth},
+    merkle, Log,
+};
+use log::trace;
+
+#[derive(Debug)]
+#[allow(dead_code)] // fields are only read through Debug
+pub enum InclusionError {
+    Fetch(FetchError),
+    Parse(CTParseError),
+    /// The log returned a different leaf than the one we have at the index.
+    LeafMismatch {
+        idx: u64,
+    },
+    /// The audit path didn't lead to the root hash of the STH.
+    BadProof {
+        first_leaf: u64,
+        height: u32,
+    },
+}
+
+/// Verifies that `entries`, starting at index `start`, are included in the tree with head `sth`.
+/// The range is split into complete subtrees, whose hashes are computed locally. One audit path
+/// is fetched for each subtree, so every entry is covered using only a few proofs per batch. For
+/// tiled logs, the audit paths are computed from the log's hash tiles.
+pub async fn check_batch(
+    fetcher: &Fetcher,
+    log: &Log,
+    sth: &LogSth,
+    start: u64,
+    entries: &[GetEntriesItem],
+) -> Result<(), InclusionError> {
+    let root = sth.root_hash().map_err(InclusionError::Parse)?;
+    let end = start + entries.len() as u64 - 1;
+    for (first_leaf, height) in merkle::aligned_subtrees(start, end) {
+        let offset = (first_leaf - start) as usize;
+        let leaves: Vec<merkle::Hash> = entries[offset..offset + (1 << height)]
+            .iter()
+            .map(|entry| entry.leaf_hash)
+            .collect();
+        let subtree_root = merkle::perfect_subtree_root(&leaves);
+        let above_subtree = if log.is_tiled() {
+            fetcher
+                .fetch_tiled_subtree_proof(log, first_leaf, height, sth.tree_size)
+                .await
+                .map_err(InclusionError::Fetch)?
+        } else {
+            let proof = fetcher
+                .fetch_proof_by_hash(log, &leaves[0], sth.tree_size)
+                .await
+                .map_err(InclusionError::Fetch)?;
+            let audit_path = if proof.leaf_index == first_leaf {
+                proof.audit_path().map_err(InclusionError::Parse)?
+            } else {
+                // the same leaf can appear more than once, so ask for this index specifically
+                trace!(
+                    "proof for {} from \"{}\" was for index {}",
+                    first_leaf,
+                    log.description,
+                    proof.leaf_index
+                );
+                let proof = fetcher
+                    .fetch_entry_and_proof(log, first_leaf, sth.tree_size)
+                    .await
+                    .map_err(InclusionError::Fetch)?;
+                if proof.leaf_hash().map_err(InclusionError::Parse)? != leaves[0] {
+                    return Err(InclusionError::LeafMismatch { idx: first_leaf });
+                }
+                proof.audit_path().map_err(InclusionError::Parse)?
+            };
+            // the first `height` hashes are in the subtree, which we computed ourselves
+            audit_path
+                .get(height as usize..)
+                .unwrap_or_default()
+                .to_vec()
+        };
+        if !merkle::verify_subtree_inclusion(
+            first_leaf,
+            height,
+            &subtree_root,
+            sth.tree_size,
+            &above_subtree,
+            &root,
+        ) {
+            return Err(InclusionError::BadProof { first_leaf, height });
+        }
+    }
+    Ok(())
+}
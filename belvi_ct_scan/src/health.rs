@@ -0,0 +1,178 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Tracks errors from each log, so that a misbehaving log is skipped for a while instead of
+//! stopping the scanner.
+use crate::{Ctx, LogId};
+use chrono::Utc;
+use log::{info, warn};
+use std::time::{Duration, Instant};
+
+/// A log is quarantined after this many errors in a row.
+const QUARANTINE_AFTER: u32 = 5;
+/// Backoff after the first error. It doubles with each error in a row.
+const BASE_BACKOFF: Duration = Duration::from_secs(10);
+const MAX_BACKOFF: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HealthStatus {
+    Healthy,
+    /// The last request to the log failed.
+    Degraded,
+    /// Many requests in a row failed, so the log is checked much less often.
+    Quarantined,
+}
+
+impl HealthStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Healthy => "healthy",
+            Self::Degraded => "degraded",
+            Self::Quarantined => "quarantined",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LogHealth {
+    pub status: HealthStatus,
+    pub consecutive_errors: u32,
+    pub total_errors: u64,
+    pub last_error: Option<String>,
+    /// The log isn't used until this time.
+    pub skip_until: Option<Instant>,
+}
+
+impl Default for LogHealth {
+    fn default() -> Self {
+        Self {
+            status: HealthStatus::Healthy,
+            consecutive_errors: 0,
+            total_errors: 0,
+            last_error: None,
+            skip_until: None,
+        }
+    }
+}
+
+impl LogHealth {
+    #[must_use]
+    pub fn available(&self, now: Instant) -> bool {
+        match self.skip_until {
+            Some(until) => now >= until,
+            None => true,
+        }
+    }
+
+    fn backoff(&self) -> Duration {
+        let doublings = self.consecutive_errors.saturating_sub(1).min(16);
+        (BASE_BACKOFF * 2u32.pow(doublings)).min(MAX_BACKOFF)
+    }
+
+    fn record_error(&mut self, now: Instant, err: String) {
+        self.consecutive_errors += 1;
+        self.total_errors += 1;
+        self.last_error = Some(err);
+        self.status = if self.consecutive_errors >= QUARANTINE_AFTER {
+            HealthStatus::Quarantined
+        } else {
+            HealthStatus::Degraded
+        };
+        self.skip_until = Some(now + self.backoff());
+    }
+
+    fn record_success(&mut self) {
+        self.consecutive_errors = 0;
+        self.status = HealthStatus::Healthy;
+        self.skip_until = None;
+    }
+}
+
+impl Ctx {
+    /// Can requests be made to the log, or is it backing off after errors?
+    pub fn log_available(&self, id: &LogId) -> bool {
+        match self.log_health.get(id) {
+            Some(health) => health.available(Instant::now()),
+            None => true,
+        }
+    }
+
+    pub fn record_log_error(&mut self, id: &LogId, description: &str, err: String) {
+        let now = Instant::now();
+        let health = self.log_health.entry(id.clone()).or_default();
+        health.record_error(now, err);
+        let backoff = health.backoff();
+        if health.status == HealthStatus::Quarantined {
+            warn!(
+                "Quarantining \"{}\" for {:?} after {} errors in a row",
+                description, backoff, health.consecutive_errors
+            );
+        } else {
+            warn!("Skipping \"{}\" for {:?} after error", description, backoff);
+        }
+        self.save_log_health(id, backoff);
+    }
+
+    pub fn record_log_success(&mut self, id: &LogId, description: &str) {
+        let health = match self.log_health.get_mut(id) {
+            Some(health) if health.status != HealthStatus::Healthy => health,
+            // nothing changed
+            _ => return,
+        };
+        info!("\"{}\" is healthy again", description);
+        health.record_success();
+        self.save_log_health(id, Duration::ZERO);
+    }
+
+    /// Stores the health of a log so it can be shown by the frontend.
+    fn save_log_health(&self, id: &LogId, backoff: Duration) {
+        let health = &self.log_health[id];
+        let now = Utc::now().timestamp_millis();
+        self.sqlite_conn
+            .prepare_cached("INSERT OR REPLACE INTO log_health (log_id, status, consecutive_errors, total_errors, last_error, skip_until, ts) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .unwrap()
+            .execute(rusqlite::params![
+                id.num(),
+                health.status.as_str(),
+                health.consecutive_errors,
+                health.total_errors,
+                health.last_error,
+                now + backoff.as_millis() as i64,
+                now,
+            ])
+            .expect("failed to save log health");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn backoff() {
+        let now = Instant::now();
+        let mut health = LogHealth::default();
+        assert!(health.available(now));
+
+        health.record_error(now, "bad status".to_string());
+        assert_eq!(health.status, HealthStatus::Degraded);
+        assert!(!health.available(now));
+        assert!(health.available(now + BASE_BACKOFF));
+
+        for _ in 1..QUARANTINE_AFTER {
+            health.record_error(now, "bad status".to_string());
+        }
+        assert_eq!(health.status, HealthStatus::Quarantined);
+        assert!(!health.available(now + BASE_BACKOFF * 8));
+        assert!(health.available(now + BASE_BACKOFF * 16));
+
+        for _ in 0..100 {
+            health.record_error(now, "bad status".to_string());
+        }
+        assert!(health.available(now + MAX_BACKOFF));
+
+        health.record_success();
+        assert_eq!(health.status, HealthStatus::Healthy);
+        assert!(health.available(now));
+        assert_eq!(health.total_errors, 105);
+        assert_eq!(health.last_error.as_deref(), Some("bad status"));
+    }
+}
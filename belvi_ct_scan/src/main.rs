@@ -4,45 +4,80 @@ use log::{debug, info, warn};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
-    env, fs,
+    fs,
     path::PathBuf,
-    sync::{atomic, Mutex},
+    sync::{atomic, Arc},
     time::{Duration, Instant},
 };
 
 mod fetch_certs;
+mod health;
 mod update_sths;
+mod writer;
 
-use belvi_log_list::{fetcher::Fetcher, log_data::LogSth};
+use belvi_cache::CertStore;
+use belvi_db::fetch_state::{self, StoredFetchState};
+use belvi_log_list::{
+    fetcher::Fetcher, log_data::LogSth, rate_limit::RateLimits, source::LogListSource,
+};
 use belvi_log_list::{Log, LogId, LogList};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone)]
 struct FetchState {
-    state_ver: u32,
+    log_states: HashMap<LogId, LogFetchState>,
+}
+
+/// The fetch state used to be stored in state.json, in this format.
+#[derive(Debug, Deserialize)]
+struct LegacyFetchState {
     log_states: HashMap<LogId, LogFetchState>,
 }
 
 impl FetchState {
-    fn new_sync(ctx: &Ctx) -> Self {
+    fn load_sync(ctx: &Ctx) -> Self {
+        let stored = fetch_state::load_all(&ctx.sqlite_conn).expect("failed to load fetch state");
+        if !stored.is_empty() {
+            info!("Loaded fetch state of {} logs", stored.len());
+            return Self {
+                log_states: stored.into_iter().map(LogFetchState::from_stored).collect(),
+            };
+        }
         if let Ok(data) = fs::read_to_string(&ctx.fetch_state_path) {
-            info!("Loading fetch state from {:?}", ctx.fetch_state_path);
-            serde_json::from_str(&data).unwrap()
-        } else {
-            warn!("No fetch state found, creating new");
-            Self {
-                state_ver: 1,
-                log_states: HashMap::new(),
-            }
+            return Self::import_sync(ctx, &data);
+        }
+        warn!("No fetch state found, creating new");
+        Self {
+            log_states: HashMap::new(),
         }
     }
-    async fn save(&self, ctx: &Ctx) {
-        info!("Saving fetch state to {:?}", ctx.data_path);
-        tokio::fs::write(
-            ctx.fetch_state_path.clone(),
-            serde_json::to_string(self).expect("couldn't stringify"),
+    /// Moves the fetch state from state.json into the database.
+    fn import_sync(ctx: &Ctx, data: &str) -> Self {
+        info!("Importing fetch state from {:?}", ctx.fetch_state_path);
+        let legacy: LegacyFetchState = serde_json::from_str(data).expect("invalid state.json");
+        ctx.sqlite_conn.execute_batch("BEGIN").unwrap();
+        for (id, state) in &legacy.log_states {
+            fetch_state::save(&ctx.sqlite_conn, id.num(), &state.to_stored(id))
+                .expect("failed to import fetch state");
+        }
+        ctx.sqlite_conn.execute_batch("COMMIT").unwrap();
+        // keep the old file around, but don't import it again
+        fs::rename(
+            &ctx.fetch_state_path,
+            ctx.fetch_state_path.with_extension("json.imported"),
         )
-        .await
-        .expect("failed to save");
+        .expect("failed to rename state.json");
+        Self {
+            log_states: legacy.log_states,
+        }
+    }
+    /// Saves the state of every log, and waits until it is committed. The state of a log is
+    /// also saved whenever entries from it are written.
+    async fn save(&self, ctx: &Ctx) {
+        debug!("Saving fetch state");
+        for (id, state) in &self.log_states {
+            ctx.writer.save_state(id.clone(), state.clone()).await;
+        }
+        ctx.writer.flush().await;
     }
 }
 
@@ -52,19 +87,52 @@ struct LogFetchState {
     fetched_to: fetch_certs::batcher::HistState,
 }
 
+impl LogFetchState {
+    fn to_stored(&self, id: &LogId) -> StoredFetchState {
+        StoredFetchState {
+            log_id: id.0.clone(),
+            tree_size: self.sth.tree_size,
+            timestamp: self.sth.timestamp,
+            sha256_root_hash: self.sth.sha256_root_hash.clone(),
+            tree_head_signature: self.sth.tree_head_signature.clone(),
+            fetched: serde_json::to_string(&self.fetched_to).expect("couldn't stringify"),
+        }
+    }
+    fn from_stored(stored: StoredFetchState) -> (LogId, Self) {
+        let state = Self {
+            sth: LogSth {
+                tree_size: stored.tree_size,
+                timestamp: stored.timestamp,
+                sha256_root_hash: stored.sha256_root_hash,
+                tree_head_signature: stored.tree_head_signature,
+            },
+            fetched_to: serde_json::from_str(&stored.fetched).expect("invalid fetched ranges"),
+        };
+        (LogId(stored.log_id), state)
+    }
+}
+
 #[derive(Debug)]
 struct Ctx {
-    data_path: PathBuf,
+    /// state.json, where older versions stored the fetch state. It is imported once.
     fetch_state_path: PathBuf,
-    #[allow(dead_code)]
-    certs_path: PathBuf,
     log_list: LogList,
+    log_list_source: LogListSource,
+    log_list_loaded: Instant,
     fetcher: Fetcher,
     start_time: DateTime<Utc>,
-    cache_certs: bool,
+    /// Whether to fetch from test logs, whose certificates aren't trusted by browsers.
+    include_test_logs: bool,
     log_transient: HashMap<LogId, LogTransient>,
+    log_health: HashMap<LogId, health::LogHealth>,
+    /// How many batches to fetch from each log at once.
+    batches_per_log: usize,
+    /// Whether to fetch every entry from all logs, rather than only recent entries.
+    backfill_all: bool,
+    /// IDs of logs to fetch every entry from.
+    backfill_logs: HashSet<String>,
     sqlite_conn: rusqlite::Connection,
-    redis_conn: belvi_cache::Connection,
+    writer: writer::Writer,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -83,48 +151,106 @@ impl Default for LogTransient {
 }
 
 impl Ctx {
-    // redis_conn is an argument since it can only be created in an async fn
-    fn from_env_sync(redis_conn: belvi_cache::Connection) -> Self {
-        let mut args = env::args_os();
-        let data_path: PathBuf = args.nth(1).unwrap().into();
-        let fetch_state_path = data_path.join("state.json");
-        let certs_path = data_path.join("certs");
-        if !certs_path.exists() {
-            warn!("certs directory doesn't exist; creating");
-            fs::create_dir(certs_path.clone()).unwrap();
-        }
+    // cert_store is an argument since it can only be opened in an async fn
+    fn from_env_sync(cert_store: Arc<dyn CertStore>) -> Self {
+        let config = belvi_config::get();
+        let fetch_state_path = config.data_path.join("state.json");
         let start_time = Utc::now();
         debug!("Start time is {:?}", start_time);
-        let cache_certs = env::var("BELVI_NO_CACHE").is_err();
+        let backfill_all = config.backfill_logs.iter().any(|id| id == "all");
+        let backfill_logs = config
+            .backfill_logs
+            .iter()
+            .filter(|id| *id != "all")
+            .cloned()
+            .collect();
         let sqlite_conn = belvi_db::connect();
+        let writer = writer::Writer::spawn(
+            belvi_db::connect(),
+            if config.cache_certs {
+                Some(cert_store)
+            } else {
+                None
+            },
+        );
+        let log_list_source = LogListSource::new(
+            config.log_list.as_deref(),
+            config.log_list_sig.as_deref(),
+            config.log_list_key.as_deref(),
+            &config.extra_log_lists,
+            Duration::from_secs(config.log_list_reload),
+        )
+        .expect("invalid log list config");
+        let log_list = log_list_source.load().expect("failed to load log list");
+        let fetcher = Fetcher::with_contact(
+            &config.contact,
+            RateLimits::load_or_default(config.rate_limits.as_deref())
+                .expect("invalid rate limits"),
+        );
+        fetcher.set_log_list(&log_list);
         Ctx {
-            data_path,
             fetch_state_path,
-            certs_path,
             start_time,
-            cache_certs,
+            include_test_logs: config.include_test_logs,
             sqlite_conn,
             log_transient: HashMap::new(),
-            log_list: LogList::google(),
-            fetcher: Fetcher::new(),
-            redis_conn,
+            log_health: HashMap::new(),
+            log_list,
+            log_list_source,
+            log_list_loaded: Instant::now(),
+            fetcher,
+            batches_per_log: config.batches_per_log,
+            backfill_all,
+            backfill_logs,
+            writer,
+        }
+    }
+    /// Reloads the log list if it is old. The old list is kept if the new one can't be loaded.
+    fn reload_log_list(&mut self) {
+        if self.log_list_loaded.elapsed() < self.log_list_source.reload_interval {
+            return;
+        }
+        self.log_list_loaded = Instant::now();
+        match self.log_list_source.load() {
+            Ok(log_list) => {
+                if log_list != self.log_list {
+                    info!(
+                        "Loaded log list version {} with {} logs",
+                        log_list.version,
+                        log_list.logs().count()
+                    );
+                }
+                self.fetcher.set_log_list(&log_list);
+                self.log_list = log_list;
+            }
+            Err(err) => warn!("Failed to reload log list, keeping old list: {:?}", err),
         }
     }
+    /// Should all entries of the log be fetched, starting from the first one?
+    fn backfill(&self, log: &Log) -> bool {
+        self.backfill_all || self.backfill_logs.contains(&log.log_id)
+    }
     fn active_logs(&self) -> impl Iterator<Item = &Log> {
         self.log_list
             .logs()
             .filter(|log| log.has_active_certs(self.start_time))
+            .filter(|log| self.include_test_logs || !log.is_test())
     }
 }
 
 const MAX_RECHECK_GAP: u64 = 90;
 const WAIT_TIME: u64 = 8;
+/// Number of certificates stored by older versions that are linked to their precerts each time
+/// the STHs are updated.
+const PRECERT_BACKFILL_CHUNK: u32 = 10_000;
 
 static STOP_FETCHING: atomic::AtomicBool = atomic::AtomicBool::new(false);
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
+    // fail early if the configuration is invalid
+    belvi_config::get();
     info!("Starting Belvi fetcher");
 
     tokio::spawn(async move {
@@ -133,44 +259,57 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         STOP_FETCHING.store(true, atomic::Ordering::Relaxed);
     });
 
-    let ctx = Ctx::from_env_sync(belvi_cache::Connection::new().await);
-    let mut fetch_state = FetchState::new_sync(&ctx);
+    let cert_store = belvi_cache::open(belvi_config::get())
+        .await
+        .expect("failed to open certificate store");
+    let mut ctx = Ctx::from_env_sync(cert_store);
+    let mut fetch_state = FetchState::load_sync(&ctx);
 
-    fetch_state.update_sths(&ctx).await;
+    fetch_state.update_sths(&mut ctx).await;
     fetch_state.save(&ctx).await;
     let mut last_fetch_state_check = Instant::now();
-    // TODO: use Tokio mutex
-    let fetch_state = Mutex::new(fetch_state);
 
     let mut active_logs: Vec<Log> = ctx.active_logs().cloned().collect();
     let mut checked_logs: HashSet<String> = HashSet::new();
-    ctx.sqlite_conn
-        .prepare_cached("BEGIN DEFERRED")
-        .unwrap()
-        .execute([])
-        .unwrap();
-    let ctx = Mutex::new(ctx);
     loop {
         fastrand::shuffle(&mut active_logs);
-        let mut futures = Vec::new();
         let mut logs = Vec::new();
         for log in &active_logs {
             if checked_logs.contains(&log.log_id) {
                 continue;
             }
-            futures.push(FetchState::fetch_next_batch(&fetch_state, &ctx, log));
+            if !ctx.log_available(&LogId(log.log_id.clone())) {
+                // backing off after errors
+                checked_logs.insert(log.log_id.clone());
+                continue;
+            }
             logs.push(log);
         }
-        for (idx, count) in futures::future::join_all(futures)
-            .await
-            .into_iter()
-            .enumerate()
-        {
-            let log = logs[idx];
-            if let Some(count) = count {
-                info!("Fetched {} certs from \"{}\"", count, log.description);
-            } else {
-                checked_logs.insert(log.log_id.clone());
+        let fetched =
+            futures::future::join_all(logs.iter().map(|log| fetch_state.fetch_batches(&ctx, log)))
+                .await;
+        for (log, fetched) in logs.into_iter().zip(fetched) {
+            let id = LogId(log.log_id.clone());
+            let (batches, results) = match fetched {
+                Some(fetched) => fetched,
+                None => {
+                    // nothing left to fetch, but the log is working
+                    ctx.record_log_success(&id, &log.description);
+                    checked_logs.insert(log.log_id.clone());
+                    continue;
+                }
+            };
+            match fetch_state
+                .commit_batches(&mut ctx, log, batches, results)
+                .await
+            {
+                Ok(count) => {
+                    info!("Fetched {} certs from \"{}\"", count, log.description);
+                }
+                // the log is backing off, so don't try it again in this round
+                Err(_) => {
+                    checked_logs.insert(log.log_id.clone());
+                }
             }
         }
 
@@ -180,16 +319,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         let stop_fetching = STOP_FETCHING.load(atomic::Ordering::Relaxed);
 
         if long_time_since_recheck || nothing_left || stop_fetching {
-            // save state
-            let inner_ctx = ctx.lock().unwrap();
-            let mut inner_fetch_state = fetch_state.lock().unwrap();
-            inner_fetch_state.save(&inner_ctx).await;
-            inner_ctx
-                .sqlite_conn
-                .prepare_cached("COMMIT")
-                .unwrap()
-                .execute([])
-                .unwrap();
+            // the fetch state is saved by the writer along with the certs
+            ctx.writer.flush().await;
+            fetch_state.save_progress(&ctx);
 
             if stop_fetching {
                 return Ok(());
@@ -202,18 +334,23 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 tokio::time::sleep(Duration::from_secs(WAIT_TIME)).await;
             }
 
-            // update STHs
-            inner_fetch_state.update_sths(&inner_ctx).await;
+            // update STHs, including for logs that were just added to the list
+            ctx.reload_log_list();
+            fetch_state.update_sths(&mut ctx).await;
+            fetch_state.save(&ctx).await;
+            let linked = ctx
+                .writer
+                .backfill_precert_links(PRECERT_BACKFILL_CHUNK)
+                .await;
+            if linked > 0 {
+                info!(
+                    "Linked {} certs that were stored before precert links",
+                    linked
+                );
+            }
+            active_logs = ctx.active_logs().cloned().collect();
             checked_logs = HashSet::new(); // checked logs may need to be rechecked again
             last_fetch_state_check = Instant::now();
-
-            // start another tx
-            inner_ctx
-                .sqlite_conn
-                .prepare_cached("BEGIN DEFERRED")
-                .unwrap()
-                .execute([])
-                .unwrap();
         }
     }
 }
@@ -1,34 +1,320 @@
 // SPDX-License-Identifier: Apache-2.0
 use crate::{fetch_certs::batcher::HistState, Ctx, FetchState, LogFetchState, LogId};
-use log::{debug, error, info, trace};
+use belvi_db::sth_history::{self, StoredSth};
+use belvi_log_list::{
+    fetcher::FetchError,
+    log_data::{CTParseError, LogSth},
+    merkle, Log,
+};
+use chrono::Utc;
+use log::{debug, error, info, trace, warn};
+use rusqlite::{Connection, OptionalExtension};
+
+#[derive(Debug)]
+#[allow(dead_code)] // fields are only read through Debug
+enum ConsistencyError {
+    /// The proof couldn't be retrieved, so we don't know if the log is consistent.
+    Fetch(FetchError),
+    Parse(CTParseError),
+    /// The new tree is smaller than the old tree.
+    Shrunk,
+    /// The tree size didn't change, but the root hash did.
+    RootChanged,
+    /// The consistency proof didn't match the root hashes.
+    BadProof,
+}
+
+/// Checks that `new_sth` is an append-only extension of `old_sth`, given a consistency proof
+/// between them.
+fn verify_consistency(
+    old_sth: &LogSth,
+    new_sth: &LogSth,
+    proof: &[merkle::Hash],
+) -> Result<(), ConsistencyError> {
+    let old_root = old_sth.root_hash().map_err(ConsistencyError::Parse)?;
+    let new_root = new_sth.root_hash().map_err(ConsistencyError::Parse)?;
+    if new_sth.tree_size < old_sth.tree_size {
+        return Err(ConsistencyError::Shrunk);
+    }
+    if new_sth.tree_size == old_sth.tree_size {
+        return if old_root == new_root {
+            Ok(())
+        } else {
+            Err(ConsistencyError::RootChanged)
+        };
+    }
+    if merkle::verify_consistency(
+        old_sth.tree_size,
+        &old_root,
+        new_sth.tree_size,
+        &new_root,
+        proof,
+    ) {
+        Ok(())
+    } else {
+        Err(ConsistencyError::BadProof)
+    }
+}
+
+/// Checks that `new_sth` is an append-only extension of `old_sth`.
+async fn check_consistency(
+    ctx: &Ctx,
+    log: &Log,
+    old_sth: &LogSth,
+    new_sth: &LogSth,
+) -> Result<(), ConsistencyError> {
+    if new_sth.tree_size <= old_sth.tree_size || old_sth.tree_size == 0 {
+        // no proof needed
+        return verify_consistency(old_sth, new_sth, &[]);
+    }
+    if log.is_tiled() {
+        // tiled logs don't serve consistency proofs, but the old root can be computed from the
+        // hash tiles of the new tree
+        let old_root = old_sth.root_hash().map_err(ConsistencyError::Parse)?;
+        let new_root = new_sth.root_hash().map_err(ConsistencyError::Parse)?;
+        let tiled_root = |size| ctx.fetcher.fetch_tiled_root(log, size, new_sth.tree_size);
+        let tiled_old_root = tiled_root(old_sth.tree_size)
+            .await
+            .map_err(ConsistencyError::Fetch)?;
+        let tiled_new_root = tiled_root(new_sth.tree_size)
+            .await
+            .map_err(ConsistencyError::Fetch)?;
+        return if tiled_old_root == old_root && tiled_new_root == new_root {
+            Ok(())
+        } else {
+            Err(ConsistencyError::BadProof)
+        };
+    }
+    let proof = ctx
+        .fetcher
+        .fetch_sth_consistency(log, old_sth.tree_size, new_sth.tree_size)
+        .await
+        .map_err(ConsistencyError::Fetch)?
+        .consistency()
+        .map_err(ConsistencyError::Parse)?;
+    verify_consistency(old_sth, new_sth, &proof)
+}
+
+/// Moves the log on to `new_sth`, but only if it was proven consistent with the STH we had, so
+/// that an inconsistent STH is never used as the base for later checks or fetches.
+fn advance_sth(
+    state: &mut LogFetchState,
+    new_sth: LogSth,
+    result: &Result<(), ConsistencyError>,
+) -> bool {
+    if result.is_ok() {
+        state.sth = new_sth;
+        true
+    } else {
+        false
+    }
+}
+
+/// Saves the result of a consistency check. An inconsistent pair of STHs is only flagged as an
+/// event the first time it is checked, since the old STH is kept and the same pair is checked
+/// again until the log serves a different STH.
+fn record_verdict(
+    db: &Connection,
+    log_id: &LogId,
+    old_sth: &LogSth,
+    new_sth: &LogSth,
+    result: &Result<(), ConsistencyError>,
+) {
+    let now = Utc::now().timestamp_millis();
+    let detail = match result {
+        Ok(()) => None,
+        Err(err) => Some(format!("{:?}", err)),
+    };
+    let previous: Option<bool> = db
+        .prepare_cached("SELECT consistent FROM log_consistency WHERE log_id = ? AND first_size = ? AND first_root = ? AND second_size = ? AND second_root = ?")
+        .unwrap()
+        .query_row(
+            rusqlite::params![
+                log_id.num(),
+                old_sth.tree_size,
+                old_sth.sha256_root_hash,
+                new_sth.tree_size,
+                new_sth.sha256_root_hash,
+            ],
+            |row| row.get(0),
+        )
+        .optional()
+        .expect("failed to read consistency verdict");
+    db.prepare_cached("INSERT OR REPLACE INTO log_consistency (log_id, consistent, first_size, first_root, second_size, second_root, ts, detail) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+        .unwrap()
+        .execute(rusqlite::params![
+            log_id.num(),
+            result.is_ok(),
+            old_sth.tree_size,
+            old_sth.sha256_root_hash,
+            new_sth.tree_size,
+            new_sth.sha256_root_hash,
+            now,
+            detail,
+        ])
+        .expect("failed to record consistency verdict");
+    if previous == Some(result.is_ok()) {
+        return;
+    }
+    if let Some(detail) = detail {
+        record_event(
+            db,
+            log_id,
+            "inconsistent_sth",
+            format!(
+                "STH of size {} ({}) is not consistent with STH of size {} ({}): {}",
+                new_sth.tree_size,
+                new_sth.sha256_root_hash,
+                old_sth.tree_size,
+                old_sth.sha256_root_hash,
+                detail
+            ),
+        );
+    }
+}
+
+/// Adds the STH to the history, and checks if it reveals a split view.
+fn record_sth(ctx: &Ctx, log: &Log, log_id: &LogId, sth: &LogSth) {
+    let stored = StoredSth {
+        tree_size: sth.tree_size,
+        timestamp: sth.timestamp,
+        sha256_root_hash: sth.sha256_root_hash.clone(),
+        tree_head_signature: sth.tree_head_signature.clone(),
+        fetched_at: Utc::now().timestamp_millis(),
+    };
+    let is_new = sth_history::record_sth(&ctx.sqlite_conn, log_id.num(), &stored)
+        .expect("failed to record STH");
+    if !is_new {
+        return;
+    }
+    let split_view = sth_history::split_view_at(&ctx.sqlite_conn, log_id.num(), sth.tree_size)
+        .expect("failed to check for split view");
+    if let Some(split_view) = split_view {
+        error!(
+            "Log \"{}\" has presented a split view at tree size {}",
+            log.description, sth.tree_size
+        );
+        record_event(
+            &ctx.sqlite_conn,
+            log_id,
+            "split_view",
+            format!(
+                "{} STHs of size {} have different root hashes: {}",
+                split_view.sths.len(),
+                sth.tree_size,
+                serde_json::to_string(&split_view).unwrap()
+            ),
+        );
+    }
+}
+
+/// Flags misbehaviour by a log, to be shown on the log's page.
+fn record_event(db: &Connection, log_id: &LogId, kind: &str, detail: String) {
+    db.prepare_cached("INSERT INTO log_events (log_id, ts, kind, detail) VALUES (?, ?, ?, ?)")
+        .unwrap()
+        .execute(rusqlite::params![
+            log_id.num(),
+            Utc::now().timestamp_millis(),
+            kind,
+            detail
+        ])
+        .expect("failed to record log event");
+}
 
 impl FetchState {
-    pub async fn update_sths(&mut self, ctx: &Ctx) {
+    pub async fn update_sths(&mut self, ctx: &mut Ctx) {
         info!("Fetching all log STHs");
+        let logs: Vec<Log> = ctx.active_logs().cloned().collect();
         // TODO: in parallel
-        for log in ctx.active_logs() {
-            let new_sth = ctx.fetcher.fetch_sth(log).await.unwrap_or_else(|err| {
-                panic!(
-                    "Failed to fetch log STH for \"{}\", bailing: {:?}",
-                    log.description, err,
-                )
-            });
-            trace!("Fetching STH for \"{}\"", log.description);
+        for log in &logs {
             let log_id = LogId(log.log_id.clone());
+            if !ctx.log_available(&log_id) {
+                trace!("Not fetching STH for \"{}\", backing off", log.description);
+                continue;
+            }
+            trace!("Fetching STH for \"{}\"", log.description);
+            let new_sth = match ctx.fetcher.fetch_sth(log).await {
+                Ok(sth) => sth,
+                Err(FetchError::BadSignature(err)) => {
+                    error!(
+                        "Log \"{}\" sent STH with bad signature: {:?}",
+                        log.description, err
+                    );
+                    record_event(
+                        &ctx.sqlite_conn,
+                        &log_id,
+                        "bad_sth_signature",
+                        format!("STH signature could not be verified: {:?}", err),
+                    );
+                    ctx.record_log_error(
+                        &log_id,
+                        &log.description,
+                        format!("bad STH signature: {:?}", err),
+                    );
+                    continue;
+                }
+                Err(err) => {
+                    warn!(
+                        "Failed to fetch log STH for \"{}\", skipping: {:?}",
+                        log.description, err,
+                    );
+                    ctx.record_log_error(
+                        &log_id,
+                        &log.description,
+                        format!("failed to fetch STH: {:?}", err),
+                    );
+                    continue;
+                }
+            };
+            record_sth(ctx, log, &log_id, &new_sth);
             match self.log_states.get_mut(&log_id) {
                 Some(state) => {
                     let old_sth = &state.sth;
-                    if old_sth.tree_size > new_sth.tree_size
-                        || old_sth.timestamp > new_sth.timestamp
+                    if old_sth.timestamp > new_sth.timestamp
+                        && old_sth.tree_size >= new_sth.tree_size
                     {
-                        error!("log violated append-only {:?} to {:?}", old_sth, new_sth);
+                        // probably served by an out of date frontend
+                        debug!("Log \"{}\" sent an older STH, ignoring", log.description);
+                        continue;
                     }
-                    if old_sth.tree_size == new_sth.tree_size {
-                        debug!("Log \"{}\" is unchanged", log.description);
-                    } else {
+                    let result = check_consistency(ctx, log, old_sth, &new_sth).await;
+                    match &result {
+                        Ok(()) => (),
+                        Err(ConsistencyError::Fetch(err)) => {
+                            // try again next time, before we use the new STH
+                            warn!(
+                                "Failed to fetch consistency proof for \"{}\", keeping old STH: {:?}",
+                                log.description, err
+                            );
+                            ctx.record_log_error(
+                                &log_id,
+                                &log.description,
+                                format!("failed to fetch consistency proof: {:?}", err),
+                            );
+                            continue;
+                        }
+                        Err(err) => {
+                            error!(
+                                "Log \"{}\" violated append-only {:?} to {:?}, keeping old STH: {:?}",
+                                log.description, old_sth, new_sth, err
+                            );
+                        }
+                    }
+                    record_verdict(&ctx.sqlite_conn, &log_id, old_sth, &new_sth, &result);
+                    let grew = new_sth.tree_size > old_sth.tree_size;
+                    if !advance_sth(state, new_sth, &result) {
+                        ctx.record_log_error(
+                            &log_id,
+                            &log.description,
+                            format!("inconsistent STH: {:?}", result),
+                        );
+                        continue;
+                    }
+                    if grew {
                         debug!("Log \"{}\" has new certs", log.description);
+                    } else {
+                        debug!("Log \"{}\" is unchanged", log.description);
                     }
-                    state.sth = new_sth;
                 }
                 None => {
                     info!("Got first STH for log \"{}\"", log.description);
@@ -44,3 +330,97 @@ impl FetchState {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sth(tree_size: u64, root: &merkle::Hash) -> LogSth {
+        LogSth {
+            tree_size,
+            timestamp: tree_size,
+            sha256_root_hash: base64::encode(root),
+            tree_head_signature: String::new(),
+        }
+    }
+
+    #[test]
+    fn bad_proof_keeps_old_sth() {
+        let leaves: Vec<merkle::Hash> = (0u8..4).map(|i| merkle::leaf_hash(&[i])).collect();
+        let old_sth = sth(2, &merkle::perfect_subtree_root(&leaves[..2]));
+        let new_sth = sth(4, &merkle::perfect_subtree_root(&leaves));
+        let mut state = LogFetchState {
+            sth: old_sth.clone(),
+            fetched_to: HistState::default(),
+        };
+
+        let bad_proof = [merkle::node_hash(&leaves[3], &leaves[2])];
+        let result = verify_consistency(&old_sth, &new_sth, &bad_proof);
+        assert!(matches!(result, Err(ConsistencyError::BadProof)));
+        assert!(!advance_sth(&mut state, new_sth.clone(), &result));
+        assert_eq!(state.sth, old_sth);
+
+        let good_proof = [merkle::node_hash(&leaves[2], &leaves[3])];
+        let result = verify_consistency(&old_sth, &new_sth, &good_proof);
+        assert!(result.is_ok());
+        assert!(advance_sth(&mut state, new_sth.clone(), &result));
+        assert_eq!(state.sth, new_sth);
+    }
+
+    #[test]
+    fn repeated_inconsistency_is_one_event() {
+        let db = belvi_db::memory();
+        let log_id = LogId("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=".to_string());
+        let leaves: Vec<merkle::Hash> = (0u8..4).map(|i| merkle::leaf_hash(&[i])).collect();
+        let old_sth = sth(2, &merkle::perfect_subtree_root(&leaves[..2]));
+        let new_sth = sth(4, &merkle::perfect_subtree_root(&leaves));
+        let mut state = LogFetchState {
+            sth: old_sth.clone(),
+            fetched_to: HistState::default(),
+        };
+        let events = || -> u32 {
+            db.query_row(
+                "SELECT COUNT(*) FROM log_events WHERE kind = 'inconsistent_sth'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        // the log keeps serving the same inconsistent STH in later rounds
+        let bad_proof = [merkle::node_hash(&leaves[3], &leaves[2])];
+        for _ in 0..2 {
+            let result = verify_consistency(&state.sth, &new_sth, &bad_proof);
+            record_verdict(&db, &log_id, &state.sth, &new_sth, &result);
+            assert!(!advance_sth(&mut state, new_sth.clone(), &result));
+        }
+        assert_eq!(events(), 1);
+
+        // a different inconsistent STH is flagged again
+        let other_sth = sth(4, &merkle::perfect_subtree_root(&leaves[..2]));
+        let result = verify_consistency(&state.sth, &other_sth, &bad_proof);
+        record_verdict(&db, &log_id, &state.sth, &other_sth, &result);
+        assert_eq!(events(), 2);
+    }
+
+    #[test]
+    fn shrunk_or_changed_root_keeps_old_sth() {
+        let leaves: Vec<merkle::Hash> = (0u8..4).map(|i| merkle::leaf_hash(&[i])).collect();
+        let old_sth = sth(4, &merkle::perfect_subtree_root(&leaves));
+        let mut state = LogFetchState {
+            sth: old_sth.clone(),
+            fetched_to: HistState::default(),
+        };
+
+        let shrunk = sth(2, &merkle::perfect_subtree_root(&leaves[..2]));
+        let result = verify_consistency(&old_sth, &shrunk, &[]);
+        assert!(matches!(result, Err(ConsistencyError::Shrunk)));
+        assert!(!advance_sth(&mut state, shrunk, &result));
+
+        let changed = sth(4, &merkle::perfect_subtree_root(&leaves[..2]));
+        let result = verify_consistency(&old_sth, &changed, &[]);
+        assert!(matches!(result, Err(ConsistencyError::RootChanged)));
+        assert!(!advance_sth(&mut state, changed, &result));
+        assert_eq!(state.sth, old_sth);
+    }
+}
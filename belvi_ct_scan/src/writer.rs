@@ -0,0 +1,551 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Parses fetched entries and writes them to the database. This runs on a thread of its own, so
+//! that fetching doesn't wait for SQLite.
+//!
+//! Messages that arrive while a transaction is being written are added to the same transaction,
+//! so the database is written in large transactions when fetching is fast.
+use crate::{LogFetchState, LogId};
+use bcder::decode::Constructed;
+use belvi_cache::{CertStore, NewCert};
+use belvi_db::{
+    merge_delay::{self, EntryDelay},
+    quarantine::QuarantinedEntry,
+    scts::StoredSct,
+};
+use belvi_log_list::log_data::{GetEntriesItem, LogEntry};
+use chrono::Utc;
+use log::{debug, trace, warn};
+use std::sync::Arc;
+use tokio::{
+    runtime::Handle,
+    sync::{mpsc, oneshot},
+    task,
+};
+use x509_certificate::{asn1time::Time, rfc5280::TbsCertificate};
+
+/// Number of messages that can be waiting to be written before fetching has to wait.
+const QUEUE_SIZE: usize = 64;
+
+/// Entries that were fetched from a log and are included in the log's tree.
+#[derive(Debug)]
+pub struct Batch {
+    pub log_id: LogId,
+    pub description: String,
+    /// Maximum merge delay of the log, in seconds.
+    pub mmd: u32,
+    /// Index of the first entry.
+    pub start: u64,
+    pub entries: Vec<GetEntriesItem>,
+    /// State of the log once the batch is added, which is saved with the batch.
+    pub fetch_state: LogFetchState,
+}
+
+#[derive(Debug)]
+enum Message {
+    Batch(Batch),
+    Rejected {
+        log_id: LogId,
+        start: u64,
+        end: u64,
+        tree_size: u64,
+        reason: String,
+    },
+    SaveState {
+        log_id: LogId,
+        state: LogFetchState,
+    },
+    /// Link certificates that were stored before precerts were linked, replying with how many
+    /// were taken from the queue.
+    BackfillPrecertLinks {
+        limit: u32,
+        reply: oneshot::Sender<usize>,
+    },
+    /// Reply once everything before this message is committed.
+    Flush(oneshot::Sender<()>),
+}
+
+#[derive(Debug, Clone)]
+pub struct Writer {
+    sender: mpsc::Sender<Message>,
+}
+
+fn time_to_unix(time: Time) -> i64 {
+    match time {
+        Time::UtcTime(time) => *time,
+        Time::GeneralTime(time) => time.into(),
+    }
+    .timestamp()
+}
+
+impl Writer {
+    /// Starts the writer thread. Certificates are also added to `cert_store` if it is set.
+    pub fn spawn(
+        sqlite_conn: rusqlite::Connection,
+        cert_store: Option<Arc<dyn CertStore>>,
+    ) -> Self {
+        let (sender, receiver) = mpsc::channel(QUEUE_SIZE);
+        task::spawn_blocking(move || run(sqlite_conn, cert_store, receiver));
+        Self { sender }
+    }
+
+    async fn send(&self, message: Message) {
+        self.sender
+            .send(message)
+            .await
+            .expect("writer thread stopped");
+    }
+
+    pub async fn write_batch(&self, batch: Batch) {
+        self.send(Message::Batch(batch)).await;
+    }
+
+    /// Records a batch of entries that aren't in the tree of the log.
+    pub async fn reject_batch(
+        &self,
+        log_id: LogId,
+        start: u64,
+        end: u64,
+        tree_size: u64,
+        reason: String,
+    ) {
+        self.send(Message::Rejected {
+            log_id,
+            start,
+            end,
+            tree_size,
+            reason,
+        })
+        .await;
+    }
+
+    pub async fn save_state(&self, log_id: LogId, state: LogFetchState) {
+        self.send(Message::SaveState { log_id, state }).await;
+    }
+
+    /// Links up to `limit` certificates that were stored before precerts were linked to their
+    /// final certificates, and returns how many were linked or found to be missing from the
+    /// certificate store. Nothing is linked if certificates aren't stored.
+    pub async fn backfill_precert_links(&self, limit: u32) -> usize {
+        let (reply, receiver) = oneshot::channel();
+        self.send(Message::BackfillPrecertLinks { limit, reply })
+            .await;
+        receiver.await.expect("writer thread stopped")
+    }
+
+    /// Waits until everything sent so far is committed.
+    pub async fn flush(&self) {
+        let (sender, receiver) = oneshot::channel();
+        self.send(Message::Flush(sender)).await;
+        receiver.await.expect("writer thread stopped");
+    }
+}
+
+fn run(
+    sqlite_conn: rusqlite::Connection,
+    cert_store: Option<Arc<dyn CertStore>>,
+    mut receiver: mpsc::Receiver<Message>,
+) {
+    while let Some(message) = receiver.blocking_recv() {
+        sqlite_conn
+            .prepare_cached("BEGIN DEFERRED")
+            .unwrap()
+            .execute([])
+            .unwrap();
+        let mut flushes = Vec::new();
+        let mut next = Some(message);
+        while let Some(message) = next {
+            match message {
+                Message::Batch(batch) => write_batch(&sqlite_conn, cert_store.as_deref(), batch),
+                Message::Rejected {
+                    log_id,
+                    start,
+                    end,
+                    tree_size,
+                    reason,
+                } => {
+                    sqlite_conn
+                        .prepare_cached("INSERT INTO rejected_batches (log_id, start_idx, end_idx, tree_size, reason, ts) VALUES (?, ?, ?, ?, ?, ?)")
+                        .unwrap()
+                        .execute(rusqlite::params![
+                            log_id.num(),
+                            start,
+                            end,
+                            tree_size,
+                            reason,
+                            Utc::now().timestamp_millis(),
+                        ])
+                        .expect("failed to record rejected batch");
+                }
+                Message::SaveState { log_id, state } => {
+                    save_state(&sqlite_conn, &log_id, &state);
+                }
+                Message::BackfillPrecertLinks { limit, reply } => {
+                    let count = match &cert_store {
+                        Some(cert_store) => {
+                            backfill_precert_links(&sqlite_conn, &**cert_store, limit)
+                        }
+                        None => 0,
+                    };
+                    // the task that asked might have stopped waiting
+                    let _ = reply.send(count);
+                }
+                Message::Flush(reply) => flushes.push(reply),
+            }
+            next = receiver.try_recv().ok();
+        }
+        sqlite_conn
+            .prepare_cached("COMMIT")
+            .unwrap()
+            .execute([])
+            .unwrap();
+        for reply in flushes {
+            // the task that asked might have stopped waiting
+            let _ = reply.send(());
+        }
+    }
+    debug!("Writer thread stopping");
+}
+
+fn save_state(sqlite_conn: &rusqlite::Connection, log_id: &LogId, state: &LogFetchState) {
+    belvi_db::fetch_state::save(sqlite_conn, log_id.num(), &state.to_stored(log_id))
+        .expect("failed to save fetch state");
+}
+
+/// Parses the certificate or precert TBS in an entry, and gets its domains. Entries that can't be
+/// added to the database are quarantined with the returned reason instead of stopping the
+/// scanner.
+fn parse_entry(
+    log_entry: &LogEntry,
+) -> Result<(&'static str, TbsCertificate, Vec<Vec<u8>>), String> {
+    let (cert_type, cert) = match log_entry {
+        LogEntry::X509(cert) => {
+            let cert: x509_certificate::rfc5280::Certificate =
+                x509_certificate::X509Certificate::from_der(cert)
+                    .map_err(|err| format!("invalid certificate: {}", err))?
+                    .into();
+            ("cert", cert.tbs_certificate)
+        }
+        LogEntry::Precert {
+            tbs_certificate, ..
+        } => {
+            let cert = Constructed::decode(
+                tbs_certificate.as_ref(),
+                bcder::Mode::Der,
+                TbsCertificate::take_from,
+            )
+            .map_err(|err| format!("invalid precert TBS: {}", err))?;
+            ("precert", cert)
+        }
+    };
+    let domains = belvi_cert::get_cert_domains(&cert);
+    // a domain of "&" means that the SANs weren't parsed as expected
+    if domains.contains(&b"&".to_vec()) {
+        return Err("domain is \"&\"".to_string());
+    }
+    Ok((cert_type, cert, domains))
+}
+
+/// Parses a certificate as it is stored: the DER of a final certificate, or the TBS of a precert.
+fn parse_stored(content: Vec<u8>, cert_type: u8) -> Option<TbsCertificate> {
+    let log_entry = if cert_type == belvi_db::precert_links::CERT {
+        LogEntry::X509(content)
+    } else {
+        LogEntry::Precert {
+            issuer_key_hash: [0; 32],
+            tbs_certificate: content,
+        }
+    };
+    parse_entry(&log_entry).ok().map(|(_, cert, _)| cert)
+}
+
+/// Links the next `limit` certificates from the backfill queue, reading them from the store.
+fn backfill_precert_links(
+    sqlite_conn: &rusqlite::Connection,
+    cert_store: &dyn CertStore,
+    limit: u32,
+) -> usize {
+    let pending = belvi_db::precert_links::pending_backfill(sqlite_conn, limit)
+        .expect("failed to read precert link backfill");
+    for (leaf_hash, cert_type) in &pending {
+        // this runs on a blocking thread, so it can wait for the store
+        let content = Handle::current()
+            .block_on(cert_store.get_cert(leaf_hash))
+            .expect("failed to read cert");
+        // certificates that were dropped from a cache can't be linked
+        let tbs_hash = content
+            .and_then(|content| parse_stored(content, *cert_type))
+            .map(|cert| belvi_hash::db(&belvi_cert::precert_tbs_der(&cert)));
+        belvi_db::precert_links::finish_backfill(
+            sqlite_conn,
+            leaf_hash,
+            tbs_hash.as_ref().map(|hash| &hash[..]),
+            *cert_type,
+        )
+        .expect("failed to link precert");
+    }
+    pending.len()
+}
+
+fn write_batch(
+    sqlite_conn: &rusqlite::Connection,
+    cert_store: Option<&dyn CertStore>,
+    batch: Batch,
+) {
+    let mut cert_insert = sqlite_conn
+        .prepare_cached(
+            "INSERT OR IGNORE INTO certs (leaf_hash, extra_hash, not_before, not_after, cert_type) VALUES (?, ?, ?, ?, ?)",
+        )
+        .unwrap();
+    let mut entry_insert = sqlite_conn
+        .prepare_cached(
+            "INSERT OR IGNORE INTO log_entries (leaf_hash, log_id, ts, idx) VALUES (?, ?, ?, ?)",
+        )
+        .unwrap();
+    let mut domain_insert = sqlite_conn
+        .prepare_cached("INSERT OR IGNORE INTO domains (leaf_hash, domain) VALUES (?, ?)")
+        .unwrap();
+    let mut certs = Vec::new();
+    let mut violations = Vec::new();
+    for (idx, entry) in batch.entries.iter().enumerate() {
+        let idx: u64 = idx as u64 + batch.start;
+        let log_timestamp = entry.leaf_input.timestamped_entry.timestamp;
+        let log_entry = &entry.leaf_input.timestamped_entry.log_entry;
+        let leaf_hash = belvi_hash::db(log_entry.inner_cert()).to_vec();
+        let (cert_type, cert, domains) = match parse_entry(log_entry) {
+            Ok(parsed) => parsed,
+            Err(reason) => {
+                warn!(
+                    "Quarantining idx {} of \"{}\": {}",
+                    idx, batch.description, reason
+                );
+                belvi_db::quarantine::add(
+                    sqlite_conn,
+                    &QuarantinedEntry {
+                        log_id: batch.log_id.num(),
+                        idx,
+                        leaf_hash,
+                        cert_type: log_entry.num(),
+                        reason,
+                        ts: Utc::now().timestamp_millis(),
+                    },
+                )
+                .expect("failed to quarantine entry");
+                continue;
+            }
+        };
+
+        let validity = &cert.validity;
+        let not_before = validity.not_before.clone();
+        let not_after = validity.not_after.clone();
+        trace!(
+            "idx {} of \"{}\": {} with ts {}, valid from {:?} to {:?}",
+            idx,
+            batch.description,
+            cert_type,
+            log_timestamp,
+            not_before,
+            not_after,
+        );
+        let extra_hash = belvi_hash::db(&entry.extra_data);
+        let not_after = time_to_unix(not_after);
+        let new_cert = cert_insert
+            .execute(rusqlite::params![
+                leaf_hash,
+                extra_hash.to_vec(),
+                time_to_unix(not_before),
+                not_after,
+                log_entry.num(),
+            ])
+            .expect("failed to insert cert")
+            == 1;
+        let new_entry = entry_insert
+            .execute(rusqlite::params![
+                leaf_hash,
+                batch.log_id.num(),
+                log_timestamp,
+                idx
+            ])
+            .expect("failed to insert entry")
+            == 1;
+        // entries that are fetched again were already measured
+        if new_entry {
+            let delay = EntryDelay {
+                log_id: batch.log_id.num(),
+                idx,
+                leaf_hash: leaf_hash.clone(),
+                sct_ts: log_timestamp,
+                incorporation: merge_delay::incorporation(sqlite_conn, batch.log_id.num(), idx)
+                    .expect("failed to find incorporation"),
+                mmd: batch.mmd,
+            };
+            if delay.is_violation() {
+                violations.push(idx);
+            }
+            merge_delay::record(sqlite_conn, &delay).expect("failed to record merge delay");
+        }
+        for domain in domains {
+            domain_insert
+                .execute(rusqlite::params![
+                    leaf_hash,
+                    String::from_utf8_lossy(&domain)
+                ])
+                .expect("failed to insert domain");
+        }
+        belvi_db::ips::add(sqlite_conn, &leaf_hash, &belvi_cert::get_cert_ips(&cert))
+            .expect("failed to insert IP addresses");
+        for name in belvi_cert::get_cert_tagged_names(&cert) {
+            belvi_db::alt_names::add(sqlite_conn, &leaf_hash, name.kind.num(), &name.value)
+                .expect("failed to insert name");
+        }
+        // the chain of a certificate is stored from the first entry that has it, and it only
+        // needs to be linked to its precert or final certificate once
+        if new_cert {
+            let tbs_hash = belvi_hash::db(&belvi_cert::precert_tbs_der(&cert));
+            belvi_db::precert_links::add(sqlite_conn, &leaf_hash, &tbs_hash, log_entry.num())
+                .expect("failed to link precert");
+            match belvi_cert::sct::get_cert_scts(&cert) {
+                Ok(scts) => {
+                    let scts: Vec<StoredSct> = scts
+                        .into_iter()
+                        .map(|sct| StoredSct {
+                            log_id: belvi_db::scts::log_num(&sct.log_id),
+                            log_key: sct.log_id.to_vec(),
+                            ts: sct.timestamp as i64,
+                            version: sct.version,
+                            hash_alg: sct.hash_algorithm,
+                            sig_alg: sct.signature_algorithm,
+                            signature: sct.signature,
+                            extensions: sct.extensions,
+                        })
+                        .collect();
+                    belvi_db::scts::add(sqlite_conn, &leaf_hash, &scts)
+                        .expect("failed to insert SCTs");
+                }
+                Err(err) => warn!(
+                    "Invalid SCTs in idx {} of \"{}\": {:?}",
+                    idx, batch.description, err
+                ),
+            }
+            match entry.chain() {
+                Ok(chain) => {
+                    belvi_db::chains::add(sqlite_conn, &leaf_hash, &chain.chain)
+                        .expect("failed to insert chain");
+                    if let Some(pre_certificate) = chain.pre_certificate {
+                        let precert_hash = belvi_hash::db(pre_certificate).to_vec();
+                        belvi_db::chains::add_precert(sqlite_conn, &leaf_hash, &precert_hash)
+                            .expect("failed to insert precertificate");
+                        certs.push((precert_hash, pre_certificate, not_after));
+                    }
+                }
+                Err(err) => warn!(
+                    "Invalid extra data for idx {} of \"{}\": {:?}",
+                    idx, batch.description, err
+                ),
+            }
+        }
+        certs.push((leaf_hash, &log_entry.inner_cert()[..], not_after));
+    }
+    if let (Some(first), Some(last)) = (violations.first(), violations.last()) {
+        warn!(
+            "\"{}\" didn't incorporate {} entries within its MMD",
+            batch.description,
+            violations.len()
+        );
+        sqlite_conn
+            .prepare_cached("INSERT INTO log_events (log_id, ts, kind, detail) VALUES (?, ?, ?, ?)")
+            .unwrap()
+            .execute(rusqlite::params![
+                batch.log_id.num(),
+                Utc::now().timestamp_millis(),
+                "mmd_violation",
+                format!(
+                    "{} entries between index {} and {} were missing from a tree head signed more than {} seconds after their timestamp",
+                    violations.len(),
+                    first,
+                    last,
+                    batch.mmd
+                ),
+            ])
+            .expect("failed to record log event");
+    }
+    if let Some(cert_store) = cert_store {
+        let certs: Vec<NewCert> = certs
+            .iter()
+            .map(|(id, content, not_after)| NewCert {
+                id,
+                content,
+                not_after: *not_after,
+            })
+            .collect();
+        // this runs on a blocking thread, so it can wait for the store
+        let stored = Handle::current()
+            .block_on(cert_store.new_certs(&certs))
+            .expect("failed to store certs");
+        trace!("stored {} new certs from \"{}\"", stored, batch.description);
+    }
+    save_state(sqlite_conn, &batch.log_id, &batch.fetch_state);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use belvi_cache::{retention::Retention, MemoryStore};
+    use belvi_db::precert_links::{CERT, PRECERT};
+
+    #[test]
+    fn invalid_entries_are_errors() {
+        assert!(parse_entry(&LogEntry::X509(b"not a cert".to_vec()))
+            .unwrap_err()
+            .starts_with("invalid certificate"));
+        assert!(parse_entry(&LogEntry::Precert {
+            issuer_key_hash: [0; 32],
+            tbs_certificate: Vec::new(),
+        })
+        .unwrap_err()
+        .starts_with("invalid precert TBS"));
+    }
+
+    #[test]
+    fn backfill_precert_links() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let _guard = runtime.enter();
+        let db = belvi_db::memory();
+        let store = MemoryStore::new(Retention::default());
+        let cert = include_bytes!("../../test_certs/geckome.der");
+        let tbs = parse_stored(cert.to_vec(), CERT).unwrap();
+        // the precert of the certificate has the same TBS, apart from its extensions
+        let precert = belvi_cert::precert_tbs_der(&tbs);
+        for (leaf_hash, content, cert_type) in [
+            (&[1][..], &cert[..], CERT),
+            (&[2][..], &precert[..], PRECERT),
+            (&[3][..], &precert[..], PRECERT),
+        ] {
+            db.execute(
+                "INSERT INTO precert_link_backfill (leaf_hash, cert_type) VALUES (?, ?)",
+                rusqlite::params![leaf_hash, cert_type],
+            )
+            .unwrap();
+            // the last precert was dropped from the store
+            if leaf_hash != [3] {
+                store
+                    .new_cert(NewCert {
+                        id: leaf_hash,
+                        content,
+                        not_after: i64::MAX,
+                    })
+                    .unwrap();
+            }
+        }
+
+        assert_eq!(super::backfill_precert_links(&db, &store, 2), 2);
+        assert_eq!(
+            belvi_db::precert_links::final_certs(&db, &[2]).unwrap(),
+            vec![vec![1]]
+        );
+        assert_eq!(super::backfill_precert_links(&db, &store, 2), 1);
+        assert!(belvi_db::precert_links::final_certs(&db, &[3])
+            .unwrap()
+            .is_empty());
+        assert_eq!(super::backfill_precert_links(&db, &store, 2), 0);
+    }
+}
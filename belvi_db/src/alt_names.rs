@@ -0,0 +1,47 @@
+// SPDX-License-Identifier: Apache-2.0
+//! subjectAltNames that aren't domains or IP addresses. Each name is stored with the number of
+//! its kind, so the same text can be searched for as one kind of name without matching others.
+use rusqlite::Connection;
+
+/// Records a name of a certificate. `kind` is the number of a `belvi_cert::NameKind`.
+pub fn add(db: &Connection, leaf_hash: &[u8], kind: u8, name: &str) -> rusqlite::Result<()> {
+    db.prepare_cached("INSERT OR IGNORE INTO alt_names (kind, name, leaf_hash) VALUES (?, ?, ?)")?
+        .execute(rusqlite::params![kind, name, leaf_hash])?;
+    Ok(())
+}
+
+/// Gets the names of a certificate, ordered by kind.
+pub fn get(db: &Connection, leaf_hash: &[u8]) -> rusqlite::Result<Vec<(u8, String)>> {
+    db.prepare_cached("SELECT kind, name FROM alt_names WHERE leaf_hash = ? ORDER BY kind, name")?
+        .query_map([leaf_hash], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn store_names() {
+        let db = crate::memory();
+        add(&db, b"cert1", 3, "juliet@im.example.com").unwrap();
+        add(&db, b"cert1", 1, "juliet@corp.example").unwrap();
+        // the same text can be different kinds of name
+        add(&db, b"cert1", 2, "juliet@corp.example").unwrap();
+        add(&db, b"cert1", 1, "juliet@corp.example").unwrap();
+        add(&db, b"cert2", 1, "romeo@corp.example").unwrap();
+        assert_eq!(
+            get(&db, b"cert1").unwrap(),
+            vec![
+                (1, "juliet@corp.example".to_string()),
+                (2, "juliet@corp.example".to_string()),
+                (3, "juliet@im.example.com".to_string()),
+            ]
+        );
+        assert_eq!(
+            get(&db, b"cert2").unwrap(),
+            vec![(1, "romeo@corp.example".to_string())]
+        );
+        assert!(get(&db, b"cert3").unwrap().is_empty());
+    }
+}
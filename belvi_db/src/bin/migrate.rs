@@ -0,0 +1,28 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Migrates the database to the latest schema. Databases are also migrated when the scanner
+//! starts, but large migrations can take a while, so it can be useful to run them separately.
+//!
+//! Usage: `migrate <data path> [status]`. With `status`, nothing is changed, and the migrations
+//! that would be applied are listed.
+use belvi_db::migrations;
+
+fn main() {
+    env_logger::init();
+    let mut db = belvi_db::open();
+    let status = migrations::status(&db).expect("failed to read database version");
+    println!(
+        "Database is at version {} of {}",
+        status.version, status.latest
+    );
+    for migration in &status.pending {
+        println!(
+            "Pending: version {}: {}",
+            migration.version, migration.description
+        );
+    }
+    if belvi_config::get().args.first().map(String::as_str) == Some("status") {
+        return;
+    }
+    let applied = migrations::migrate(&mut db).expect("failed to migrate database");
+    println!("Applied {} migrations", applied);
+}
@@ -0,0 +1,11 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Prints evidence of every split view that has been detected as JSON.
+fn main() {
+    let db = belvi_db::connect_readonly();
+    let log_id = belvi_config::get()
+        .args
+        .first()
+        .map(|id| id.parse().expect("log ID must be a number"));
+    let views = belvi_db::sth_history::split_views(&db, log_id).unwrap();
+    println!("{}", serde_json::to_string_pretty(&views).unwrap());
+}
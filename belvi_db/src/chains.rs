@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Certificate chains that were submitted with entries. Issuers are shared by many certificates,
+//! so each one is stored once and chains refer to them by hash.
+use rusqlite::{Connection, OptionalExtension};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issuer {
+    /// Hash of the certificate, like `certs.leaf_hash`.
+    pub hash: Vec<u8>,
+    /// DER certificate.
+    pub cert: Vec<u8>,
+}
+
+/// Stores the chain of a certificate, starting with its issuer. Chains that were already stored
+/// for the certificate are kept, since a certificate can be submitted with different chains.
+pub fn add(db: &Connection, leaf_hash: &[u8], chain: &[&[u8]]) -> rusqlite::Result<()> {
+    let stored: bool = db
+        .prepare_cached("SELECT EXISTS (SELECT 1 FROM cert_chains WHERE leaf_hash = ?)")?
+        .query_row([leaf_hash], |row| row.get(0))?;
+    if stored {
+        return Ok(());
+    }
+    let mut issuer_insert =
+        db.prepare_cached("INSERT OR IGNORE INTO issuers (issuer_hash, cert) VALUES (?, ?)")?;
+    let mut chain_insert = db.prepare_cached(
+        "INSERT OR IGNORE INTO cert_chains (leaf_hash, position, issuer_hash) VALUES (?, ?, ?)",
+    )?;
+    for (position, cert) in chain.iter().enumerate() {
+        let issuer_hash = belvi_hash::db(cert).to_vec();
+        issuer_insert.execute(rusqlite::params![issuer_hash, cert])?;
+        chain_insert.execute(rusqlite::params![leaf_hash, position, issuer_hash])?;
+    }
+    Ok(())
+}
+
+/// Gets the chain of a certificate, starting with its issuer.
+pub fn get(db: &Connection, leaf_hash: &[u8]) -> rusqlite::Result<Vec<Issuer>> {
+    db.prepare_cached(
+        "SELECT issuers.issuer_hash, issuers.cert FROM cert_chains INNER JOIN issuers USING (issuer_hash) WHERE cert_chains.leaf_hash = ? ORDER BY cert_chains.position",
+    )?
+    .query_map([leaf_hash], |row| {
+        Ok(Issuer {
+            hash: row.get(0)?,
+            cert: row.get(1)?,
+        })
+    })?
+    .collect()
+}
+
+pub fn get_issuer(db: &Connection, issuer_hash: &[u8]) -> rusqlite::Result<Option<Issuer>> {
+    db.prepare_cached("SELECT issuer_hash, cert FROM issuers WHERE issuer_hash = ?")?
+        .query_row([issuer_hash], |row| {
+            Ok(Issuer {
+                hash: row.get(0)?,
+                cert: row.get(1)?,
+            })
+        })
+        .optional()
+}
+
+/// Records the precertificate of a precert. The precertificate itself is kept in the cert store.
+pub fn add_precert(db: &Connection, leaf_hash: &[u8], precert_hash: &[u8]) -> rusqlite::Result<()> {
+    db.prepare_cached(
+        "INSERT OR IGNORE INTO precertificates (leaf_hash, precert_hash) VALUES (?, ?)",
+    )?
+    .execute([leaf_hash, precert_hash])?;
+    Ok(())
+}
+
+/// Gets the ID of a precert's precertificate in the cert store.
+pub fn get_precert(db: &Connection, leaf_hash: &[u8]) -> rusqlite::Result<Option<Vec<u8>>> {
+    db.prepare_cached("SELECT precert_hash FROM precertificates WHERE leaf_hash = ?")?
+        .query_row([leaf_hash], |row| row.get(0))
+        .optional()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn chains() {
+        let db = crate::memory();
+        let intermediate: &[u8] = b"intermediate";
+        let root: &[u8] = b"root";
+        add(&db, b"leaf1", &[intermediate, root]).unwrap();
+        add(&db, b"leaf2", &[intermediate]).unwrap();
+        // a different chain for the same certificate is ignored
+        add(&db, b"leaf2", &[root, intermediate]).unwrap();
+
+        let chain = get(&db, b"leaf1").unwrap();
+        let certs: Vec<&[u8]> = chain.iter().map(|issuer| &issuer.cert[..]).collect();
+        assert_eq!(certs, [intermediate, root]);
+        let chain = get(&db, b"leaf2").unwrap();
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain[0].cert, intermediate);
+        assert_eq!(get(&db, b"leaf3").unwrap(), Vec::new());
+
+        let issuers: u32 = db
+            .query_row("SELECT count(*) FROM issuers", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(issuers, 2);
+        assert_eq!(
+            get_issuer(&db, &chain[0].hash).unwrap(),
+            Some(chain[0].clone())
+        );
+        assert_eq!(get_issuer(&db, b"missing").unwrap(), None);
+
+        assert_eq!(get_precert(&db, b"leaf1").unwrap(), None);
+        add_precert(&db, b"leaf1", b"precert").unwrap();
+        assert_eq!(
+            get_precert(&db, b"leaf1").unwrap(),
+            Some(b"precert".to_vec())
+        );
+    }
+}
@@ -0,0 +1,72 @@
+// SPDX-License-Identifier: Apache-2.0
+//! What the scanner has fetched from each log. This is written in the same transaction as the
+//! fetched entries, so it always matches the entries in the database.
+use rusqlite::Connection;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredFetchState {
+    /// Base64 log ID. The `log_id` column only has the first 4 bytes of the ID.
+    pub log_id: String,
+    /// STH that the fetched entries were checked against.
+    pub tree_size: u64,
+    pub timestamp: u64,
+    pub sha256_root_hash: String,
+    pub tree_head_signature: String,
+    /// JSON of the ranges of entries that have been fetched.
+    pub fetched: String,
+}
+
+pub fn save(db: &Connection, log_id: u32, state: &StoredFetchState) -> rusqlite::Result<()> {
+    db.prepare_cached("INSERT OR REPLACE INTO fetch_state (log_id, log_id_base64, tree_size, sth_ts, root_hash, signature, fetched) VALUES (?, ?, ?, ?, ?, ?, ?)")?
+        .execute(rusqlite::params![
+            log_id,
+            state.log_id,
+            state.tree_size,
+            state.timestamp,
+            state.sha256_root_hash,
+            state.tree_head_signature,
+            state.fetched,
+        ])?;
+    Ok(())
+}
+
+pub fn load_all(db: &Connection) -> rusqlite::Result<Vec<StoredFetchState>> {
+    db.prepare_cached(
+        "SELECT log_id_base64, tree_size, sth_ts, root_hash, signature, fetched FROM fetch_state",
+    )?
+    .query_map([], |row| {
+        Ok(StoredFetchState {
+            log_id: row.get(0)?,
+            tree_size: row.get(1)?,
+            timestamp: row.get(2)?,
+            sha256_root_hash: row.get(3)?,
+            tree_head_signature: row.get(4)?,
+            fetched: row.get(5)?,
+        })
+    })?
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn save_and_load() {
+        let db = crate::memory();
+        assert_eq!(load_all(&db).unwrap(), Vec::new());
+        let mut state = StoredFetchState {
+            log_id: "b1N=".to_string(),
+            tree_size: 10,
+            timestamp: 1,
+            sha256_root_hash: "root".to_string(),
+            tree_head_signature: "sig".to_string(),
+            fetched: "[[0,9]]".to_string(),
+        };
+        save(&db, 1, &state).unwrap();
+        state.tree_size = 20;
+        state.fetched = "[[0,19]]".to_string();
+        save(&db, 1, &state).unwrap();
+        assert_eq!(load_all(&db).unwrap(), vec![state]);
+    }
+}
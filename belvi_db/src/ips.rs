@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: Apache-2.0
+//! IP addresses in the subjectAltName extension of certificates. Addresses are stored as 16
+//! bytes, with IPv4 addresses mapped into IPv6 (`::ffff:0:0/96`), so that a CIDR range is a
+//! range of keys in the index.
+use rusqlite::Connection;
+use std::net::{IpAddr, Ipv6Addr};
+
+/// The key of an address in the `ips` table.
+pub fn key(ip: IpAddr) -> [u8; 16] {
+    match ip {
+        IpAddr::V4(ip) => ip.to_ipv6_mapped().octets(),
+        IpAddr::V6(ip) => ip.octets(),
+    }
+}
+
+/// A range of addresses, inclusive of both ends.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    pub start: [u8; 16],
+    pub end: [u8; 16],
+}
+
+impl IpRange {
+    /// Parses a single address, or a range in CIDR notation such as `192.0.2.0/24` or
+    /// `2001:db8::/32`.
+    pub fn parse(query: &str) -> Option<Self> {
+        let query = query.trim();
+        let (ip, prefix_len) = match query.split_once('/') {
+            Some((ip, prefix_len)) => (ip.parse().ok()?, Some(prefix_len.parse::<u32>().ok()?)),
+            None => (query.parse().ok()?, None),
+        };
+        let (ip, max_len) = match ip {
+            IpAddr::V4(_) => (key(ip), 32),
+            IpAddr::V6(_) => (key(ip), 128),
+        };
+        let prefix_len = prefix_len.unwrap_or(max_len);
+        if prefix_len > max_len {
+            return None;
+        }
+        let host_bits = max_len - prefix_len;
+        let mask = u128::MAX.checked_shl(host_bits).unwrap_or(0);
+        let ip = u128::from_be_bytes(ip);
+        Some(IpRange {
+            start: (ip & mask).to_be_bytes(),
+            end: (ip | !mask).to_be_bytes(),
+        })
+    }
+}
+
+/// Records the IP addresses of a certificate.
+pub fn add(db: &Connection, leaf_hash: &[u8], ips: &[IpAddr]) -> rusqlite::Result<()> {
+    let mut insert =
+        db.prepare_cached("INSERT OR IGNORE INTO ips (ip, address, leaf_hash) VALUES (?, ?, ?)")?;
+    for ip in ips {
+        insert.execute(rusqlite::params![&key(*ip)[..], ip.to_string(), leaf_hash])?;
+    }
+    Ok(())
+}
+
+/// Gets the IP addresses of a certificate, in order.
+pub fn get(db: &Connection, leaf_hash: &[u8]) -> rusqlite::Result<Vec<IpAddr>> {
+    db.prepare_cached("SELECT ip FROM ips WHERE leaf_hash = ? ORDER BY ip")?
+        .query_map([leaf_hash], |row| {
+            let ip: [u8; 16] = row.get(0)?;
+            let ip = Ipv6Addr::from(ip);
+            Ok(match ip.to_ipv4_mapped() {
+                Some(ip) => IpAddr::V4(ip),
+                None => IpAddr::V6(ip),
+            })
+        })?
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn range(query: &str) -> Option<(IpAddr, IpAddr)> {
+        IpRange::parse(query).map(|range| {
+            (
+                Ipv6Addr::from(range.start).into(),
+                Ipv6Addr::from(range.end).into(),
+            )
+        })
+    }
+
+    #[test]
+    fn ranges() {
+        let ip = |ip: &str| ip.parse::<IpAddr>().unwrap();
+        assert_eq!(
+            range("192.0.2.1"),
+            Some((ip("::ffff:192.0.2.1"), ip("::ffff:192.0.2.1")))
+        );
+        assert_eq!(
+            range(" 192.0.2.77/24 "),
+            Some((ip("::ffff:192.0.2.0"), ip("::ffff:192.0.2.255")))
+        );
+        assert_eq!(
+            range("0.0.0.0/0"),
+            Some((ip("::ffff:0.0.0.0"), ip("::ffff:255.255.255.255")))
+        );
+        assert_eq!(
+            range("2001:db8::/32"),
+            Some((
+                ip("2001:db8::"),
+                ip("2001:db8:ffff:ffff:ffff:ffff:ffff:ffff")
+            ))
+        );
+        assert_eq!(
+            range("::/0"),
+            Some((ip("::"), ip("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff")))
+        );
+        assert_eq!(range("192.0.2.0/33"), None);
+        assert_eq!(range("2001:db8::/129"), None);
+        assert_eq!(range("example.com"), None);
+        assert_eq!(range("192.0.2.0/"), None);
+    }
+
+    #[test]
+    fn store_ips() {
+        let db = crate::memory();
+        let v4: IpAddr = "34.117.169.92".parse().unwrap();
+        let v6: IpAddr = "2600:1901:0:631B::".parse().unwrap();
+        add(&db, b"cert1", &[v4, v6]).unwrap();
+        add(&db, b"cert2", &[v4]).unwrap();
+        // adding an address again doesn't change anything
+        add(&db, b"cert1", &[v4]).unwrap();
+        assert_eq!(get(&db, b"cert1").unwrap(), vec![v4, v6]);
+        assert_eq!(get(&db, b"cert2").unwrap(), vec![v4]);
+        let address: String = db
+            .query_row(
+                "SELECT address FROM ips WHERE ip = ?",
+                [&key(v6)[..]],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(address, "2600:1901:0:631b::");
+
+        let range = IpRange::parse("34.117.0.0/16").unwrap();
+        let found: Vec<Vec<u8>> = db
+            .prepare("SELECT leaf_hash FROM ips WHERE ip >= ? AND ip <= ? ORDER BY ip, leaf_hash")
+            .unwrap()
+            .query_map([&range.start[..], &range.end[..]], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(found, vec![b"cert1".to_vec(), b"cert2".to_vec()]);
+    }
+}
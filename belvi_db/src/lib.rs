@@ -1,14 +1,23 @@
 // SPDX-License-Identifier: Apache-2.0
 use log::debug;
 use rusqlite::{Connection, OpenFlags};
-use std::{env, path::PathBuf};
+use std::path::PathBuf;
 
+pub mod alt_names;
+pub mod chains;
 mod exts;
+pub mod fetch_state;
+pub mod ips;
+pub mod merge_delay;
+pub mod migrations;
+pub mod precert_links;
+pub mod quarantine;
+pub mod scts;
+pub mod sth_history;
 pub use exts::domrev;
 
 fn get_data_path() -> PathBuf {
-    let mut args = env::args_os();
-    args.nth(1).unwrap().into()
+    belvi_config::get().data_path.clone()
 }
 
 pub fn connect_readonly() -> Connection {
@@ -19,7 +28,8 @@ pub fn connect_readonly() -> Connection {
     db
 }
 
-pub fn connect() -> Connection {
+/// Opens the database for writing, without migrating it.
+pub fn open() -> Connection {
     let db_path = get_data_path().join("data.db");
     let mut db = Connection::open(db_path).unwrap();
     exts::register(&mut db);
@@ -28,9 +38,18 @@ pub fn connect() -> Connection {
     db
 }
 
+/// Opens the database for writing, and migrates it to the latest version.
+pub fn connect() -> Connection {
+    let mut db = open();
+    migrations::migrate(&mut db).expect("failed to migrate database");
+    db.execute_batch("PRAGMA optimize").unwrap();
+    db
+}
+
 pub fn memory() -> Connection {
     let mut db = Connection::open_in_memory().unwrap();
     exts::register(&mut db);
     db.execute_batch(include_str!("init_db.sql")).unwrap();
+    migrations::migrate(&mut db).expect("failed to migrate database");
     db
 }
@@ -0,0 +1,217 @@
+// SPDX-License-Identifier: Apache-2.0
+//! How long logs take to incorporate entries. A log promises to incorporate an entry within its
+//! maximum merge delay (MMD) of the entry's timestamp, so a tree head that is signed after that
+//! and doesn't include the entry proves that the log broke its promise.
+//!
+//! When an entry was incorporated is only known from the tree heads we saw, so entries that were
+//! already in the tree when we first saw the log aren't measured.
+use rusqlite::{Connection, OptionalExtension};
+
+/// Timestamps of the tree heads around when an entry was incorporated.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Incorporation {
+    /// Timestamp of the last STH that doesn't include the entry.
+    pub missing_sth_ts: Option<u64>,
+    /// Timestamp of the first STH that includes the entry.
+    pub included_sth_ts: Option<u64>,
+}
+
+/// An entry of a log, and when it was incorporated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryDelay {
+    pub log_id: u32,
+    pub idx: u64,
+    pub leaf_hash: Vec<u8>,
+    /// Timestamp of the entry, which is the same as the timestamp of its SCT.
+    pub sct_ts: u64,
+    pub incorporation: Incorporation,
+    /// MMD of the log, in seconds.
+    pub mmd: u32,
+}
+
+impl EntryDelay {
+    /// Milliseconds from the SCT timestamp to the first STH with the entry, if the entry was seen
+    /// being incorporated.
+    pub fn delay(&self) -> Option<u64> {
+        match self.incorporation {
+            Incorporation {
+                missing_sth_ts: Some(_),
+                included_sth_ts: Some(included),
+            } => Some(included.saturating_sub(self.sct_ts)),
+            _ => None,
+        }
+    }
+
+    /// Whether an STH signed after the MMD expired doesn't include the entry.
+    pub fn is_violation(&self) -> bool {
+        matches!(
+            self.incorporation.missing_sth_ts,
+            Some(missing) if missing > self.sct_ts + u64::from(self.mmd) * 1000
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub struct MergeDelayStats {
+    pub measured: u64,
+    /// In milliseconds.
+    pub total_delay: u64,
+    /// In milliseconds.
+    pub max_delay: u64,
+    pub violations: u64,
+}
+
+impl MergeDelayStats {
+    /// Mean delay in milliseconds.
+    pub fn mean_delay(&self) -> Option<u64> {
+        self.total_delay.checked_div(self.measured)
+    }
+}
+
+/// Finds the STHs around when the entry at `idx` was incorporated. Logs only grow, so these are
+/// the largest STH that is too small to include the entry and the smallest STH that includes it.
+pub fn incorporation(db: &Connection, log_id: u32, idx: u64) -> rusqlite::Result<Incorporation> {
+    let missing_sth_ts = db
+        .prepare_cached("SELECT ts FROM sths WHERE log_id = ? AND tree_size <= ? ORDER BY tree_size DESC, ts DESC LIMIT 1")?
+        .query_row(rusqlite::params![log_id, idx], |row| row.get(0))
+        .optional()?;
+    let included_sth_ts = db
+        .prepare_cached(
+            "SELECT ts FROM sths WHERE log_id = ? AND tree_size > ? ORDER BY tree_size, ts LIMIT 1",
+        )?
+        .query_row(rusqlite::params![log_id, idx], |row| row.get(0))
+        .optional()?;
+    Ok(Incorporation {
+        missing_sth_ts,
+        included_sth_ts,
+    })
+}
+
+/// Adds an entry to the statistics of its log, and records it if it is a violation. This should
+/// only be called once for each entry.
+pub fn record(db: &Connection, entry: &EntryDelay) -> rusqlite::Result<()> {
+    let violation = entry.is_violation();
+    let delay = entry.delay();
+    if delay.is_none() && !violation {
+        return Ok(());
+    }
+    db.prepare_cached("INSERT INTO merge_delay_stats (log_id, measured, total_delay, max_delay, violations) VALUES (?1, ?2, ?3, ?3, ?4) ON CONFLICT (log_id) DO UPDATE SET measured = measured + ?2, total_delay = total_delay + ?3, max_delay = max(max_delay, ?3), violations = violations + ?4")?
+        .execute(rusqlite::params![
+            entry.log_id,
+            delay.is_some() as u64,
+            delay.unwrap_or(0),
+            violation as u64,
+        ])?;
+    if violation {
+        db.prepare_cached("INSERT OR REPLACE INTO mmd_violations (log_id, idx, leaf_hash, sct_ts, missing_sth_ts, included_sth_ts, mmd) VALUES (?, ?, ?, ?, ?, ?, ?)")?
+            .execute(rusqlite::params![
+                entry.log_id,
+                entry.idx,
+                entry.leaf_hash,
+                entry.sct_ts,
+                entry.incorporation.missing_sth_ts,
+                entry.incorporation.included_sth_ts,
+                entry.mmd,
+            ])?;
+    }
+    Ok(())
+}
+
+pub fn stats(db: &Connection, log_id: u32) -> rusqlite::Result<Option<MergeDelayStats>> {
+    db.prepare_cached("SELECT measured, total_delay, max_delay, violations FROM merge_delay_stats WHERE log_id = ?")?
+        .query_row([log_id], |row| {
+            Ok(MergeDelayStats {
+                measured: row.get(0)?,
+                total_delay: row.get(1)?,
+                max_delay: row.get(2)?,
+                violations: row.get(3)?,
+            })
+        })
+        .optional()
+}
+
+/// Gets the violations of a log with the most recent entries first.
+pub fn violations(db: &Connection, log_id: u32, limit: u32) -> rusqlite::Result<Vec<EntryDelay>> {
+    db.prepare_cached("SELECT idx, leaf_hash, sct_ts, missing_sth_ts, included_sth_ts, mmd FROM mmd_violations WHERE log_id = ? ORDER BY sct_ts DESC LIMIT ?")?
+        .query_map(rusqlite::params![log_id, limit], |row| {
+            Ok(EntryDelay {
+                log_id,
+                idx: row.get(0)?,
+                leaf_hash: row.get(1)?,
+                sct_ts: row.get(2)?,
+                incorporation: Incorporation {
+                    missing_sth_ts: row.get(3)?,
+                    included_sth_ts: row.get(4)?,
+                },
+                mmd: row.get(5)?,
+            })
+        })?
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::sth_history::{record_sth, StoredSth};
+
+    #[test]
+    fn delays() {
+        let db = crate::memory();
+        for (tree_size, timestamp) in [(10, 1_000), (20, 100_000), (30, 200_000)] {
+            let sth = StoredSth {
+                tree_size,
+                timestamp,
+                sha256_root_hash: String::new(),
+                tree_head_signature: String::new(),
+                fetched_at: 0,
+            };
+            record_sth(&db, 1, &sth).unwrap();
+        }
+        let entry = |idx, sct_ts| EntryDelay {
+            log_id: 1,
+            idx,
+            leaf_hash: vec![idx as u8],
+            sct_ts,
+            incorporation: incorporation(&db, 1, idx).unwrap(),
+            mmd: 60,
+        };
+
+        // in the tree before the log was first seen
+        let old = entry(5, 0);
+        assert_eq!(old.incorporation.missing_sth_ts, None);
+        assert_eq!(old.delay(), None);
+        // incorporated 99 seconds after its timestamp, but it isn't known that it wasn't
+        // incorporated sooner
+        let on_time = entry(15, 1_000);
+        assert_eq!(
+            on_time.incorporation,
+            Incorporation {
+                missing_sth_ts: Some(1_000),
+                included_sth_ts: Some(100_000),
+            }
+        );
+        assert_eq!(on_time.delay(), Some(99_000));
+        assert!(!on_time.is_violation());
+        // missing from the STH at 100 seconds, when it should have been incorporated by 90
+        let late = entry(25, 30_000);
+        assert!(late.is_violation());
+        // not incorporated yet
+        let pending = entry(35, 199_000);
+        assert_eq!(pending.delay(), None);
+        assert!(!pending.is_violation());
+
+        for entry in [&old, &on_time, &late, &pending] {
+            record(&db, entry).unwrap();
+        }
+        let stats = stats(&db, 1).unwrap().unwrap();
+        assert_eq!(stats.measured, 2);
+        assert_eq!(stats.violations, 1);
+        assert_eq!(stats.max_delay, late.delay().unwrap());
+        assert_eq!(
+            stats.mean_delay(),
+            Some((on_time.delay().unwrap() + late.delay().unwrap()) / 2)
+        );
+        assert_eq!(violations(&db, 1, 10).unwrap(), vec![late]);
+        assert_eq!(super::stats(&db, 2).unwrap(), None);
+    }
+}
@@ -0,0 +1,332 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Ordered changes to the schema of the database.
+//!
+//! The version of the schema is stored in `PRAGMA user_version`, which is the version of the
+//! last migration that was applied. Each migration runs in its own transaction together with the
+//! update of the version, so a migration that fails (or is interrupted) is run again from the
+//! start next time. Migrations change the existing database in place, so they must never drop
+//! data that can't be recreated.
+//!
+//! To change the schema, add a migration to the end of [`MIGRATIONS`]. Never edit a migration
+//! that has been released, since databases that already applied it won't run it again.
+use log::info;
+use rusqlite::{Connection, Transaction};
+
+#[derive(Debug)]
+#[allow(dead_code)] // Debug trait is ignored for dead code analysis, but some fields are only here for better messages
+pub enum MigrationError {
+    Sqlite {
+        version: u32,
+        err: rusqlite::Error,
+    },
+    /// The database was migrated by a newer version of Belvi.
+    TooNew {
+        version: u32,
+        latest: u32,
+    },
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum Action {
+    Sql(&'static str),
+    /// For changes that can't be made with SQL alone, such as backfilling a column with values
+    /// computed in Rust. Large tables should be updated in chunks, rather than read into memory.
+    Rust(fn(&Transaction) -> rusqlite::Result<()>),
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Migration {
+    pub version: u32,
+    pub description: &'static str,
+    pub action: Action,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Create tables for certificates",
+        action: Action::Sql(include_str!("migrations/0001_initial.sql")),
+    },
+    Migration {
+        version: 2,
+        description: "Create tables for monitoring logs",
+        action: Action::Sql(include_str!("migrations/0002_log_monitoring.sql")),
+    },
+    Migration {
+        version: 3,
+        description: "Create table for quarantined log entries",
+        action: Action::Sql(include_str!("migrations/0003_quarantine.sql")),
+    },
+    Migration {
+        version: 4,
+        description: "Create tables for submitted certificate chains",
+        action: Action::Sql(include_str!("migrations/0004_cert_chains.sql")),
+    },
+    Migration {
+        version: 5,
+        description: "Create tables for linking precerts to final certificates",
+        action: Action::Sql(include_str!("migrations/0005_precert_links.sql")),
+    },
+    Migration {
+        version: 6,
+        description: "Create table for embedded SCTs",
+        action: Action::Sql(include_str!("migrations/0006_scts.sql")),
+    },
+    Migration {
+        version: 7,
+        description: "Create tables for maximum merge delay compliance",
+        action: Action::Sql(include_str!("migrations/0007_merge_delay.sql")),
+    },
+    Migration {
+        version: 8,
+        description: "Create table for IP addresses in certificates",
+        action: Action::Sql(include_str!("migrations/0008_ips.sql")),
+    },
+    Migration {
+        version: 9,
+        description: "Create table for other subjectAltNames",
+        action: Action::Sql(include_str!("migrations/0009_alt_names.sql")),
+    },
+    Migration {
+        version: 10,
+        description: "Queue stored certificates to be linked to their precerts",
+        action: Action::Sql(include_str!("migrations/0010_precert_link_backfill.sql")),
+    },
+];
+
+/// Which migrations have been applied to a database.
+#[derive(Debug, Clone)]
+pub struct Status {
+    pub version: u32,
+    pub latest: u32,
+    pub pending: Vec<Migration>,
+}
+
+fn user_version(db: &Connection) -> rusqlite::Result<u32> {
+    db.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+pub fn status(db: &Connection) -> Result<Status, MigrationError> {
+    status_with(db, MIGRATIONS)
+}
+
+fn status_with(db: &Connection, migrations: &[Migration]) -> Result<Status, MigrationError> {
+    let version = user_version(db).map_err(|err| MigrationError::Sqlite { version: 0, err })?;
+    let latest = migrations.last().map_or(0, |migration| migration.version);
+    if version > latest {
+        return Err(MigrationError::TooNew { version, latest });
+    }
+    Ok(Status {
+        version,
+        latest,
+        pending: migrations
+            .iter()
+            .filter(|migration| migration.version > version)
+            .copied()
+            .collect(),
+    })
+}
+
+/// Applies all migrations that haven't been applied yet. Returns the number of migrations
+/// applied.
+pub fn migrate(db: &mut Connection) -> Result<usize, MigrationError> {
+    migrate_with(db, MIGRATIONS)
+}
+
+fn migrate_with(db: &mut Connection, migrations: &[Migration]) -> Result<usize, MigrationError> {
+    let pending = status_with(db, migrations)?.pending;
+    for migration in &pending {
+        info!(
+            "Migrating database to version {}: {}",
+            migration.version, migration.description
+        );
+        let version = migration.version;
+        let apply = |db: &mut Connection| {
+            let tx = db.transaction()?;
+            match migration.action {
+                Action::Sql(sql) => tx.execute_batch(sql)?,
+                Action::Rust(apply) => apply(&tx)?,
+            }
+            tx.pragma_update(None, "user_version", version)?;
+            tx.commit()
+        };
+        apply(db).map_err(|err| MigrationError::Sqlite { version, err })?;
+    }
+    Ok(pending.len())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn open() -> Connection {
+        let mut db = Connection::open_in_memory().unwrap();
+        crate::exts::register(&mut db);
+        db
+    }
+
+    fn tables(db: &Connection) -> Vec<String> {
+        db.prepare("SELECT name FROM sqlite_master WHERE type = 'table' ORDER BY name")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn versions_are_ordered() {
+        for (idx, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, idx + 1);
+        }
+    }
+
+    #[test]
+    fn migrate_new_db() {
+        let mut db = open();
+        assert_eq!(status(&db).unwrap().pending.len(), MIGRATIONS.len());
+        assert_eq!(migrate(&mut db).unwrap(), MIGRATIONS.len());
+        assert_eq!(user_version(&db).unwrap(), MIGRATIONS.len() as u32);
+        assert!(tables(&db).contains(&"fetch_state".to_string()));
+        // nothing left to do
+        assert_eq!(migrate(&mut db).unwrap(), 0);
+        assert!(status(&db).unwrap().pending.is_empty());
+    }
+
+    #[test]
+    fn migrate_unversioned_db() {
+        // databases from before migrations have the first version, and some later tables
+        let mut db = open();
+        db.execute_batch(include_str!("migrations/0001_initial.sql"))
+            .unwrap();
+        db.execute_batch(
+            "PRAGMA user_version = 1;
+            INSERT INTO meta (k, v) VALUES ('migration', '1.0.0');
+            CREATE TABLE log_health (log_id NUMBER PRIMARY KEY NOT NULL, status TEXT NOT NULL, consecutive_errors NUMBER NOT NULL, total_errors NUMBER NOT NULL, last_error TEXT, skip_until NUMBER NOT NULL, ts NUMBER NOT NULL) WITHOUT ROWID;
+            INSERT INTO certs VALUES (x'01', x'02', 3, 4, 0);",
+        )
+        .unwrap();
+        assert_eq!(migrate(&mut db).unwrap(), MIGRATIONS.len() - 1);
+        let certs: u32 = db
+            .query_row("SELECT count(*) FROM certs", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(certs, 1);
+        let meta: u32 = db
+            .query_row("SELECT count(*) FROM meta", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(meta, 0);
+    }
+
+    #[test]
+    fn backfill_and_rebuild() {
+        fn backfill(tx: &Transaction) -> rusqlite::Result<()> {
+            let mut update = tx.prepare("UPDATE items SET doubled = ? WHERE id = ?")?;
+            let values: Vec<(i64, i64)> = tx
+                .prepare("SELECT id, value FROM items")?
+                .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<Result<_, _>>()?;
+            for (id, value) in values {
+                update.execute([value * 2, id])?;
+            }
+            Ok(())
+        }
+        let migrations = [
+            Migration {
+                version: 1,
+                description: "Create items",
+                action: Action::Sql(
+                    "CREATE TABLE items (id INTEGER PRIMARY KEY, value INTEGER NOT NULL);
+                    CREATE INDEX idx_items_value ON items(value);",
+                ),
+            },
+            Migration {
+                version: 2,
+                description: "Add doubled column",
+                action: Action::Sql("ALTER TABLE items ADD COLUMN doubled INTEGER"),
+            },
+            Migration {
+                version: 3,
+                description: "Backfill doubled column",
+                action: Action::Rust(backfill),
+            },
+            Migration {
+                version: 4,
+                description: "Index doubled column instead",
+                action: Action::Sql(
+                    "DROP INDEX idx_items_value;
+                    CREATE INDEX idx_items_doubled ON items(doubled);",
+                ),
+            },
+        ];
+        let mut db = open();
+        migrate_with(&mut db, &migrations[..1]).unwrap();
+        db.execute_batch("INSERT INTO items (value) VALUES (1), (2), (3)")
+            .unwrap();
+        let status = status_with(&db, &migrations).unwrap();
+        assert_eq!((status.version, status.latest), (1, 4));
+        assert_eq!(status.pending.len(), 3);
+
+        assert_eq!(migrate_with(&mut db, &migrations).unwrap(), 3);
+        let sum: i64 = db
+            .query_row("SELECT sum(doubled) FROM items", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(sum, 12);
+
+        // a database migrated by a newer version can't be used
+        assert!(matches!(
+            migrate_with(&mut db, &migrations[..2]),
+            Err(MigrationError::TooNew {
+                version: 4,
+                latest: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn precert_link_backfill() {
+        let mut db = open();
+        migrate_with(&mut db, &MIGRATIONS[..9]).unwrap();
+        db.execute_batch(
+            "INSERT INTO certs VALUES (x'01', x'00', 0, 0, 1), (x'02', x'00', 0, 0, 2), (x'03', x'00', 0, 0, 1);
+            INSERT INTO tbs_hashes VALUES (x'03', x'aa', 1);",
+        )
+        .unwrap();
+        migrate_with(&mut db, &MIGRATIONS[..10]).unwrap();
+        // only certificates that were stored without a TBS hash are queued
+        let pending = crate::precert_links::pending_backfill(&db, 10).unwrap();
+        assert_eq!(pending, vec![(vec![1], 1), (vec![2], 2)]);
+
+        crate::precert_links::finish_backfill(&db, &[1], Some(&[0xbb]), 1).unwrap();
+        crate::precert_links::finish_backfill(&db, &[2], Some(&[0xbb]), 2).unwrap();
+        assert!(crate::precert_links::pending_backfill(&db, 10)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            crate::precert_links::final_certs(&db, &[2]).unwrap(),
+            vec![vec![1]]
+        );
+    }
+
+    #[test]
+    fn failed_migration_is_rolled_back() {
+        let migrations = [
+            Migration {
+                version: 1,
+                description: "Create items",
+                action: Action::Sql("CREATE TABLE items (id INTEGER PRIMARY KEY)"),
+            },
+            Migration {
+                version: 2,
+                description: "Broken",
+                action: Action::Sql("CREATE TABLE more (id INTEGER); SELECT * FROM missing"),
+            },
+        ];
+        let mut db = open();
+        assert!(matches!(
+            migrate_with(&mut db, &migrations),
+            Err(MigrationError::Sqlite { version: 2, .. })
+        ));
+        assert_eq!(user_version(&db).unwrap(), 1);
+        assert_eq!(tables(&db), vec!["items".to_string()]);
+    }
+}
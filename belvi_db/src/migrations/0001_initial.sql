@@ -0,0 +1,33 @@
+-- SPDX-License-Identifier: Apache-2.0
+-- Tables for certificates and the domains in them.
+-- once SQLite 3.37 (2021-11-27) is more widely deployed, make these strict tables
+CREATE TABLE IF NOT EXISTS meta(
+    k TEXT PRIMARY KEY,
+    v TEXT
+); -- WITH ROWID
+CREATE TABLE IF NOT EXISTS certs (
+    leaf_hash BLOB PRIMARY KEY NOT NULL, -- SHA256 of leaf data
+    extra_hash BLOB NOT NULL, -- SHA256 of extra data
+    not_before INTEGER NOT NULL,
+    not_after INTEGER NOT NULL,
+    cert_type NUMBER NOT NULL
+) WITHOUT ROWID;
+CREATE TABLE IF NOT EXISTS log_entries (
+    leaf_hash BLOB NOT NULL, -- SHA256 of leaf data
+    log_id NUMBER NOT NULL, -- ID of log
+    idx NUMBER NOT NULL, -- index in log
+    ts NUMBER NOT NULL, -- time the certificate was incorporated into the first log we saw this cert in
+    PRIMARY KEY (leaf_hash, log_id)
+);
+CREATE TABLE IF NOT EXISTS domains (
+    -- TODO: also store reverse of domain to make *.com queries possible with < and >
+    domain TEXT NOT NULL, -- normalized FQDN without trailing .
+    leaf_hash BLOB NOT NULL, -- SHA256 of leaf data
+    PRIMARY KEY (domain, leaf_hash),
+    FOREIGN KEY (leaf_hash) REFERENCES log_entries(leaf_hash)
+); -- WITH ROWID
+
+CREATE INDEX IF NOT EXISTS idx_domains_domain1 ON domains(domain);
+CREATE INDEX IF NOT EXISTS idx_domains_leaf_hash1 ON domains(leaf_hash);
+CREATE INDEX IF NOT EXISTS idx_domains_lower_domrev2 ON domains(domrev(lower(domain)));
+CREATE INDEX IF NOT EXISTS idx_log_entries_ts1 ON log_entries(ts);
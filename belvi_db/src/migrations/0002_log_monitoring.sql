@@ -0,0 +1,74 @@
+-- SPDX-License-Identifier: Apache-2.0
+-- Tables for monitoring logs, and the state of the scanner. These were created before there were
+-- migrations, so they might already exist.
+CREATE TABLE IF NOT EXISTS rejected_batches (
+    -- batches from get-entries that couldn't be verified against the log's STH
+    log_id NUMBER NOT NULL, -- ID of log
+    start_idx NUMBER NOT NULL, -- first index in batch
+    end_idx NUMBER NOT NULL, -- last index in batch (inclusive)
+    tree_size NUMBER NOT NULL, -- size of the STH the batch was checked against
+    reason TEXT NOT NULL,
+    ts NUMBER NOT NULL -- time the batch was rejected
+); -- WITH ROWID
+CREATE TABLE IF NOT EXISTS sths (
+    -- every signed tree head we have seen
+    log_id NUMBER NOT NULL, -- ID of log
+    tree_size NUMBER NOT NULL,
+    ts NUMBER NOT NULL, -- timestamp of the STH
+    root_hash TEXT NOT NULL, -- base64 SHA256 root hash
+    signature TEXT NOT NULL, -- base64 TLS DigitallySigned struct
+    fetched_at NUMBER NOT NULL, -- time we first saw the STH
+    PRIMARY KEY (log_id, tree_size, ts, root_hash)
+) WITHOUT ROWID;
+CREATE TABLE IF NOT EXISTS log_consistency (
+    -- result of the last consistency check between successive STHs of a log
+    log_id NUMBER PRIMARY KEY NOT NULL, -- ID of log
+    consistent NUMBER NOT NULL, -- 1 if the STHs were consistent
+    first_size NUMBER NOT NULL, -- size of older STH
+    first_root TEXT NOT NULL, -- base64 root hash of older STH
+    second_size NUMBER NOT NULL, -- size of newer STH
+    second_root TEXT NOT NULL, -- base64 root hash of newer STH
+    ts NUMBER NOT NULL, -- time of the check
+    detail TEXT -- why the check failed
+) WITHOUT ROWID;
+CREATE TABLE IF NOT EXISTS log_events (
+    -- misbehaviour by a log, shown on the log's page
+    log_id NUMBER NOT NULL, -- ID of log
+    ts NUMBER NOT NULL, -- time the event was detected
+    kind TEXT NOT NULL, -- type of event, such as inconsistent_sth
+    detail TEXT NOT NULL -- human-readable description
+); -- WITH ROWID
+CREATE TABLE IF NOT EXISTS log_health (
+    -- errors from each log, updated by the scanner when they change
+    log_id NUMBER PRIMARY KEY NOT NULL, -- ID of log
+    status TEXT NOT NULL, -- healthy, degraded, or quarantined
+    consecutive_errors NUMBER NOT NULL, -- errors since the last success
+    total_errors NUMBER NOT NULL, -- errors since the scanner started
+    last_error TEXT, -- most recent error
+    skip_until NUMBER NOT NULL, -- log isn't used until this time
+    ts NUMBER NOT NULL -- time the status changed
+) WITHOUT ROWID;
+CREATE TABLE IF NOT EXISTS fetch_state (
+    -- what the scanner has fetched from each log, written with the fetched entries
+    log_id NUMBER PRIMARY KEY NOT NULL, -- ID of log
+    log_id_base64 TEXT NOT NULL, -- full ID of log
+    tree_size NUMBER NOT NULL, -- size of the STH that entries are checked against
+    sth_ts NUMBER NOT NULL, -- timestamp of the STH
+    root_hash TEXT NOT NULL, -- base64 SHA256 root hash of the STH
+    signature TEXT NOT NULL, -- base64 TLS DigitallySigned struct of the STH
+    fetched TEXT NOT NULL -- JSON of the ranges of entries fetched
+) WITHOUT ROWID;
+CREATE TABLE IF NOT EXISTS log_progress (
+    -- how much of each log has been fetched, updated by the scanner
+    log_id NUMBER PRIMARY KEY NOT NULL, -- ID of log
+    fetched NUMBER NOT NULL, -- number of entries fetched
+    tree_size NUMBER NOT NULL, -- size of the tree when the progress was saved
+    backfill NUMBER NOT NULL, -- 1 if all entries are being fetched, 0 if only recent entries
+    ts NUMBER NOT NULL -- time the progress was saved
+) WITHOUT ROWID;
+
+
+CREATE INDEX IF NOT EXISTS idx_log_events_log_id1 ON log_events(log_id);
+
+-- the schema version is now the user_version
+DELETE FROM meta WHERE k = 'migration';
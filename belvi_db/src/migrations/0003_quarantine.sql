@@ -0,0 +1,14 @@
+-- SPDX-License-Identifier: Apache-2.0
+-- Entries that the scanner couldn't add to the database, so that they can be investigated.
+CREATE TABLE quarantined_entries (
+    -- log entries that couldn't be parsed, or had unexpected contents
+    log_id NUMBER NOT NULL, -- ID of log
+    idx NUMBER NOT NULL, -- index of the entry in the log
+    leaf_hash BLOB NOT NULL, -- SHA256 of leaf data, as in the certs table
+    cert_type NUMBER NOT NULL, -- 1 for certs, 2 for precerts, as in the certs table
+    reason TEXT NOT NULL, -- why the entry was quarantined
+    ts NUMBER NOT NULL, -- time the entry was quarantined
+    PRIMARY KEY (log_id, idx)
+) WITHOUT ROWID;
+
+CREATE INDEX idx_quarantined_entries_ts ON quarantined_entries(ts);
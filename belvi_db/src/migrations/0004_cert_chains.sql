@@ -0,0 +1,19 @@
+-- SPDX-License-Identifier: Apache-2.0
+-- Certificates that were submitted with each entry in its extra_data.
+CREATE TABLE issuers (
+    -- certificates from submitted chains, each stored once
+    issuer_hash BLOB PRIMARY KEY NOT NULL, -- SHA256 of the DER certificate
+    cert BLOB NOT NULL -- DER certificate
+) WITHOUT ROWID;
+CREATE TABLE cert_chains (
+    -- chain submitted with the first entry we saw for each certificate
+    leaf_hash BLOB NOT NULL, -- SHA256 of leaf data
+    position NUMBER NOT NULL, -- 0 for the issuer of the leaf, increasing towards the root
+    issuer_hash BLOB NOT NULL, -- certificate in the issuers table
+    PRIMARY KEY (leaf_hash, position)
+) WITHOUT ROWID;
+CREATE TABLE precertificates (
+    -- precertificate submitted with the first entry we saw for each precert
+    leaf_hash BLOB PRIMARY KEY NOT NULL, -- SHA256 of leaf data
+    precert_hash BLOB NOT NULL -- SHA256 of the DER precertificate, which is its ID in the cert store
+) WITHOUT ROWID;
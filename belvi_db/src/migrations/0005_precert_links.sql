@@ -0,0 +1,17 @@
+-- SPDX-License-Identifier: Apache-2.0
+-- Links between precerts and the final certificates that were issued for them.
+CREATE TABLE tbs_hashes (
+    -- hash of each certificate's TBS without the poison and SCT list extensions
+    leaf_hash BLOB PRIMARY KEY NOT NULL, -- SHA256 of leaf data
+    tbs_hash BLOB NOT NULL, -- SHA256 of the TBS, which is the same for a precert and its final cert
+    cert_type NUMBER NOT NULL -- as in the certs table
+) WITHOUT ROWID;
+CREATE TABLE precert_links (
+    -- a precert and a final certificate with the same TBS
+    precert_hash BLOB NOT NULL, -- leaf hash of the precert
+    cert_hash BLOB NOT NULL, -- leaf hash of the final certificate
+    PRIMARY KEY (precert_hash, cert_hash)
+) WITHOUT ROWID;
+
+CREATE INDEX idx_tbs_hashes_tbs_hash ON tbs_hashes(tbs_hash);
+CREATE INDEX idx_precert_links_cert_hash ON precert_links(cert_hash);
@@ -0,0 +1,16 @@
+-- SPDX-License-Identifier: Apache-2.0
+-- Signed certificate timestamps embedded in final certificates.
+CREATE TABLE scts (
+    leaf_hash BLOB NOT NULL, -- SHA256 of leaf data
+    log_id NUMBER NOT NULL, -- ID of log, as in log_entries
+    log_key BLOB NOT NULL, -- full 32 byte ID of log
+    ts NUMBER NOT NULL, -- timestamp of the SCT, in milliseconds
+    version NUMBER NOT NULL, -- 0 for v1
+    hash_alg NUMBER NOT NULL, -- TLS HashAlgorithm of signature
+    sig_alg NUMBER NOT NULL, -- TLS SignatureAlgorithm of signature
+    signature BLOB NOT NULL,
+    extensions BLOB NOT NULL,
+    PRIMARY KEY (leaf_hash, log_key, ts)
+) WITHOUT ROWID;
+
+CREATE INDEX idx_scts_ts ON scts(ts);
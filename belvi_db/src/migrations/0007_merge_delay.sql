@@ -0,0 +1,21 @@
+-- SPDX-License-Identifier: Apache-2.0
+-- How long logs take to incorporate entries, compared to their maximum merge delay.
+CREATE TABLE merge_delay_stats (
+    -- entries whose incorporation was observed, updated by the scanner
+    log_id NUMBER PRIMARY KEY NOT NULL, -- ID of log
+    measured NUMBER NOT NULL, -- entries that weren't in an STH we saw, and were in a later one
+    total_delay NUMBER NOT NULL, -- sum of the time from SCT timestamp to the first STH with the entry, in milliseconds
+    max_delay NUMBER NOT NULL, -- longest time from SCT timestamp to the first STH with the entry, in milliseconds
+    violations NUMBER NOT NULL -- entries that were missing from an STH signed after the MMD expired
+) WITHOUT ROWID;
+CREATE TABLE mmd_violations (
+    -- entries that were missing from an STH signed after the MMD expired
+    log_id NUMBER NOT NULL, -- ID of log
+    idx NUMBER NOT NULL, -- index of the entry in the log
+    leaf_hash BLOB NOT NULL, -- SHA256 of leaf data
+    sct_ts NUMBER NOT NULL, -- timestamp of the entry, in milliseconds
+    missing_sth_ts NUMBER NOT NULL, -- timestamp of the last STH without the entry
+    included_sth_ts NUMBER, -- timestamp of the first STH with the entry
+    mmd NUMBER NOT NULL, -- MMD of the log, in seconds
+    PRIMARY KEY (log_id, idx)
+) WITHOUT ROWID;
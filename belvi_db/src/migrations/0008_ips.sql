@@ -0,0 +1,10 @@
+-- SPDX-License-Identifier: Apache-2.0
+-- IP addresses in the subjectAltName extension of certificates.
+CREATE TABLE ips (
+    ip BLOB NOT NULL, -- 16 bytes in network byte order, with IPv4 addresses mapped into IPv6 so ranges can be searched
+    address TEXT NOT NULL, -- canonical textual form
+    leaf_hash BLOB NOT NULL, -- SHA256 of leaf data
+    PRIMARY KEY (ip, leaf_hash)
+); -- WITH ROWID
+
+CREATE INDEX idx_ips_leaf_hash ON ips(leaf_hash);
@@ -0,0 +1,11 @@
+-- SPDX-License-Identifier: Apache-2.0
+-- subjectAltNames that aren't domains or IP addresses, such as the otherNames in S/MIME and client
+-- authentication certificates.
+CREATE TABLE alt_names (
+    kind NUMBER NOT NULL, -- NameKind::num in belvi_cert
+    name TEXT NOT NULL, -- textual form of the name
+    leaf_hash BLOB NOT NULL, -- SHA256 of leaf data
+    PRIMARY KEY (kind, name, leaf_hash)
+); -- WITH ROWID
+
+CREATE INDEX idx_alt_names_leaf_hash ON alt_names(leaf_hash);
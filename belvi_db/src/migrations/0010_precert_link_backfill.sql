@@ -0,0 +1,12 @@
+-- SPDX-License-Identifier: Apache-2.0
+-- Certificates that were stored before precerts were linked to their final certificates. Their
+-- bodies are in the certificate store rather than the database, so the scanner links them (and
+-- removes them from here) a chunk at a time.
+CREATE TABLE precert_link_backfill (
+    leaf_hash BLOB PRIMARY KEY NOT NULL, -- SHA256 of leaf data
+    cert_type NUMBER NOT NULL -- as in the certs table
+) WITHOUT ROWID;
+
+INSERT INTO precert_link_backfill (leaf_hash, cert_type)
+SELECT leaf_hash, cert_type FROM certs
+WHERE NOT EXISTS (SELECT 1 FROM tbs_hashes WHERE tbs_hashes.leaf_hash = certs.leaf_hash);
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Links between precerts and the final certificates issued for them. Precerts and final
+//! certificates have different leaf hashes, so they are matched by the hash of their TBS without
+//! the poison and SCT list extensions.
+use rusqlite::Connection;
+
+/// `cert_type` of final certificates.
+pub const CERT: u8 = 1;
+/// `cert_type` of precerts.
+pub const PRECERT: u8 = 2;
+
+/// Records the TBS hash of a certificate, and links it to the precerts or final certificates
+/// that were already recorded with the same hash.
+pub fn add(
+    db: &Connection,
+    leaf_hash: &[u8],
+    tbs_hash: &[u8],
+    cert_type: u8,
+) -> rusqlite::Result<()> {
+    let inserted = db
+        .prepare_cached(
+            "INSERT OR IGNORE INTO tbs_hashes (leaf_hash, tbs_hash, cert_type) VALUES (?, ?, ?)",
+        )?
+        .execute(rusqlite::params![leaf_hash, tbs_hash, cert_type])?;
+    if inserted == 0 {
+        return Ok(());
+    }
+    let other_type = if cert_type == PRECERT { CERT } else { PRECERT };
+    let others = db
+        .prepare_cached("SELECT leaf_hash FROM tbs_hashes WHERE tbs_hash = ? AND cert_type = ?")?
+        .query_map(rusqlite::params![tbs_hash, other_type], |row| {
+            row.get::<_, Vec<u8>>(0)
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+    let mut link_insert = db.prepare_cached(
+        "INSERT OR IGNORE INTO precert_links (precert_hash, cert_hash) VALUES (?, ?)",
+    )?;
+    for other in others {
+        if cert_type == PRECERT {
+            link_insert.execute([leaf_hash, &other])?;
+        } else {
+            link_insert.execute([&other, leaf_hash])?;
+        }
+    }
+    Ok(())
+}
+
+/// Gets up to `limit` certificates that were stored before precerts were linked, with their
+/// `cert_type`. Their bodies have to be read from the certificate store to link them.
+pub fn pending_backfill(db: &Connection, limit: u32) -> rusqlite::Result<Vec<(Vec<u8>, u8)>> {
+    db.prepare_cached("SELECT leaf_hash, cert_type FROM precert_link_backfill LIMIT ?")?
+        .query_map([limit], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect()
+}
+
+/// Links a certificate from [`pending_backfill`], and removes it from the queue. `tbs_hash` is
+/// `None` if the certificate can't be linked, such as when it isn't in the certificate store.
+pub fn finish_backfill(
+    db: &Connection,
+    leaf_hash: &[u8],
+    tbs_hash: Option<&[u8]>,
+    cert_type: u8,
+) -> rusqlite::Result<()> {
+    if let Some(tbs_hash) = tbs_hash {
+        add(db, leaf_hash, tbs_hash, cert_type)?;
+    }
+    db.prepare_cached("DELETE FROM precert_link_backfill WHERE leaf_hash = ?")?
+        .execute([leaf_hash])?;
+    Ok(())
+}
+
+/// Gets the leaf hashes of the final certificates issued for a precert.
+pub fn final_certs(db: &Connection, precert_hash: &[u8]) -> rusqlite::Result<Vec<Vec<u8>>> {
+    db.prepare_cached("SELECT cert_hash FROM precert_links WHERE precert_hash = ?")?
+        .query_map([precert_hash], |row| row.get(0))?
+        .collect()
+}
+
+/// Gets the leaf hashes of the precerts of a final certificate.
+pub fn precerts(db: &Connection, cert_hash: &[u8]) -> rusqlite::Result<Vec<Vec<u8>>> {
+    db.prepare_cached("SELECT precert_hash FROM precert_links WHERE cert_hash = ?")?
+        .query_map([cert_hash], |row| row.get(0))?
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn links() {
+        let db = crate::memory();
+        // the precert can be logged before or after the final certificate
+        add(&db, b"precert1", b"tbs1", PRECERT).unwrap();
+        add(&db, b"cert1", b"tbs1", CERT).unwrap();
+        add(&db, b"cert2", b"tbs2", CERT).unwrap();
+        add(&db, b"precert2", b"tbs2", PRECERT).unwrap();
+        add(&db, b"precert3", b"tbs3", PRECERT).unwrap();
+        // adding a certificate again doesn't change anything
+        add(&db, b"cert1", b"tbs1", CERT).unwrap();
+
+        assert_eq!(
+            final_certs(&db, b"precert1").unwrap(),
+            vec![b"cert1".to_vec()]
+        );
+        assert_eq!(
+            final_certs(&db, b"precert2").unwrap(),
+            vec![b"cert2".to_vec()]
+        );
+        assert_eq!(
+            final_certs(&db, b"precert3").unwrap(),
+            Vec::<Vec<u8>>::new()
+        );
+        assert_eq!(precerts(&db, b"cert1").unwrap(), vec![b"precert1".to_vec()]);
+        assert_eq!(precerts(&db, b"cert2").unwrap(), vec![b"precert2".to_vec()]);
+        let links: u32 = db
+            .query_row("SELECT count(*) FROM precert_links", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(links, 2);
+    }
+}
@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Log entries that the scanner couldn't add to the database, such as certificates that can't be
+//! parsed. They are skipped rather than stopping the scanner, and listed by the frontend so that
+//! they can be investigated.
+use rusqlite::Connection;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedEntry {
+    pub log_id: u32,
+    pub idx: u64,
+    /// Hash of the leaf data, like `certs.leaf_hash`.
+    pub leaf_hash: Vec<u8>,
+    /// Same as `certs.cert_type`.
+    pub cert_type: u8,
+    pub reason: String,
+    /// Unix time in milliseconds when the entry was quarantined.
+    pub ts: i64,
+}
+
+/// Adds an entry. If the entry was already quarantined, it is replaced.
+pub fn add(db: &Connection, entry: &QuarantinedEntry) -> rusqlite::Result<()> {
+    db.prepare_cached("INSERT OR REPLACE INTO quarantined_entries (log_id, idx, leaf_hash, cert_type, reason, ts) VALUES (?, ?, ?, ?, ?, ?)")?
+        .execute(rusqlite::params![
+            entry.log_id,
+            entry.idx,
+            entry.leaf_hash,
+            entry.cert_type,
+            entry.reason,
+            entry.ts,
+        ])?;
+    Ok(())
+}
+
+/// Gets the most recently quarantined entries, newest first.
+pub fn recent(db: &Connection, limit: u32) -> rusqlite::Result<Vec<QuarantinedEntry>> {
+    db.prepare_cached(
+        "SELECT log_id, idx, leaf_hash, cert_type, reason, ts FROM quarantined_entries ORDER BY ts DESC, log_id, idx LIMIT ?",
+    )?
+    .query_map([limit], |row| {
+        Ok(QuarantinedEntry {
+            log_id: row.get(0)?,
+            idx: row.get(1)?,
+            leaf_hash: row.get(2)?,
+            cert_type: row.get(3)?,
+            reason: row.get(4)?,
+            ts: row.get(5)?,
+        })
+    })?
+    .collect()
+}
+
+pub fn count(db: &Connection) -> rusqlite::Result<u64> {
+    db.prepare_cached("SELECT count(*) FROM quarantined_entries")?
+        .query_row([], |row| row.get(0))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn add_and_list() {
+        let db = crate::memory();
+        assert_eq!(recent(&db, 10).unwrap(), Vec::new());
+        let mut entry = QuarantinedEntry {
+            log_id: 7,
+            idx: 42,
+            leaf_hash: vec![1, 2, 3],
+            cert_type: 1,
+            reason: "invalid certificate".to_string(),
+            ts: 1000,
+        };
+        add(&db, &entry).unwrap();
+        let newer = QuarantinedEntry {
+            idx: 43,
+            ts: 2000,
+            ..entry.clone()
+        };
+        add(&db, &newer).unwrap();
+        assert_eq!(recent(&db, 10).unwrap(), vec![newer.clone(), entry.clone()]);
+        assert_eq!(recent(&db, 1).unwrap(), vec![newer.clone()]);
+
+        // quarantining an entry again replaces it
+        entry.reason = "domain contains &".to_string();
+        entry.ts = 3000;
+        add(&db, &entry).unwrap();
+        assert_eq!(count(&db).unwrap(), 2);
+        assert_eq!(recent(&db, 10).unwrap(), vec![entry, newer]);
+    }
+}
@@ -0,0 +1,174 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Signed certificate timestamps embedded in final certificates. An SCT is a promise by a log to
+//! incorporate the precert within its maximum merge delay, so SCTs from logs that never
+//! incorporated the entry are evidence of misbehaviour.
+use rusqlite::Connection;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredSct {
+    /// ID of the log, as in `log_entries.log_id`.
+    pub log_id: u32,
+    /// Full ID of the log.
+    pub log_key: Vec<u8>,
+    /// Milliseconds since the Unix epoch.
+    pub ts: i64,
+    pub version: u8,
+    pub hash_alg: u8,
+    pub sig_alg: u8,
+    pub signature: Vec<u8>,
+    pub extensions: Vec<u8>,
+}
+
+/// An SCT of a certificate that wasn't found in the log that issued it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingEntry {
+    pub leaf_hash: Vec<u8>,
+    pub log_id: u32,
+    pub ts: i64,
+}
+
+/// Converts the first 4 bytes of a log ID to the ID used in the database.
+pub fn log_num(log_key: &[u8]) -> u32 {
+    u32::from_le_bytes(log_key[0..4].try_into().unwrap())
+}
+
+pub fn add(db: &Connection, leaf_hash: &[u8], scts: &[StoredSct]) -> rusqlite::Result<()> {
+    let mut insert = db.prepare_cached("INSERT OR IGNORE INTO scts (leaf_hash, log_id, log_key, ts, version, hash_alg, sig_alg, signature, extensions) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")?;
+    for sct in scts {
+        insert.execute(rusqlite::params![
+            leaf_hash,
+            sct.log_id,
+            sct.log_key,
+            sct.ts,
+            sct.version,
+            sct.hash_alg,
+            sct.sig_alg,
+            sct.signature,
+            sct.extensions,
+        ])?;
+    }
+    Ok(())
+}
+
+pub fn get(db: &Connection, leaf_hash: &[u8]) -> rusqlite::Result<Vec<StoredSct>> {
+    db.prepare_cached("SELECT log_id, log_key, ts, version, hash_alg, sig_alg, signature, extensions FROM scts WHERE leaf_hash = ? ORDER BY ts")?
+        .query_map([leaf_hash], |row| {
+            Ok(StoredSct {
+                log_id: row.get(0)?,
+                log_key: row.get(1)?,
+                ts: row.get(2)?,
+                version: row.get(3)?,
+                hash_alg: row.get(4)?,
+                sig_alg: row.get(5)?,
+                signature: row.get(6)?,
+                extensions: row.get(7)?,
+            })
+        })?
+        .collect()
+}
+
+/// Gets the logs that have an entry for a certificate or its precert.
+pub fn logs_with_entry(db: &Connection, leaf_hash: &[u8]) -> rusqlite::Result<Vec<u32>> {
+    db.prepare_cached(
+        "SELECT DISTINCT log_id FROM log_entries WHERE leaf_hash = ?1 OR leaf_hash IN (SELECT precert_hash FROM precert_links WHERE cert_hash = ?1)",
+    )?
+    .query_map([leaf_hash], |row| row.get(0))?
+    .collect()
+}
+
+/// Gets SCTs from logs that don't have an entry for the certificate or its precert, newest first.
+/// Only logs that the scanner has fetched a tree head from more than `mmd` milliseconds after
+/// the SCT are included, since logs have that long to incorporate entries.
+pub fn missing_entries(
+    db: &Connection,
+    mmd: i64,
+    limit: u32,
+) -> rusqlite::Result<Vec<MissingEntry>> {
+    db.prepare_cached(
+        "SELECT scts.leaf_hash, scts.log_id, scts.ts FROM scts
+        INNER JOIN fetch_state ON fetch_state.log_id = scts.log_id
+        WHERE scts.ts + ? < fetch_state.sth_ts
+        AND NOT EXISTS (SELECT 1 FROM log_entries WHERE log_entries.log_id = scts.log_id AND log_entries.leaf_hash = scts.leaf_hash)
+        AND NOT EXISTS (SELECT 1 FROM precert_links INNER JOIN log_entries ON log_entries.leaf_hash = precert_links.precert_hash WHERE precert_links.cert_hash = scts.leaf_hash AND log_entries.log_id = scts.log_id)
+        ORDER BY scts.ts DESC
+        LIMIT ?",
+    )?
+    .query_map(rusqlite::params![mmd, limit], |row| {
+        Ok(MissingEntry {
+            leaf_hash: row.get(0)?,
+            log_id: row.get(1)?,
+            ts: row.get(2)?,
+        })
+    })?
+    .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::fetch_state::{self, StoredFetchState};
+
+    fn sct(log_key: [u8; 32], ts: i64) -> StoredSct {
+        StoredSct {
+            log_id: log_num(&log_key),
+            log_key: log_key.to_vec(),
+            ts,
+            version: 0,
+            hash_alg: 4,
+            sig_alg: 3,
+            signature: vec![1, 2, 3],
+            extensions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn missing() {
+        let db = crate::memory();
+        let (log1, log2, unfetched) = ([1; 32], [2; 32], [3; 32]);
+        for log in [log1, log2] {
+            let state = StoredFetchState {
+                log_id: "AQ==".to_string(),
+                tree_size: 10,
+                timestamp: 10_000,
+                sha256_root_hash: String::new(),
+                tree_head_signature: String::new(),
+                fetched: "[]".to_string(),
+            };
+            fetch_state::save(&db, log_num(&log), &state).unwrap();
+        }
+        let entry = |leaf_hash: &[u8], log: [u8; 32]| {
+            db.execute(
+                "INSERT INTO log_entries (leaf_hash, log_id, ts, idx) VALUES (?, ?, 0, 0)",
+                rusqlite::params![leaf_hash, log_num(&log)],
+            )
+            .unwrap();
+        };
+
+        // cert1 was incorporated by log1 as a precert, but not by log2
+        add(&db, b"cert1", &[sct(log1, 1000), sct(log2, 1000)]).unwrap();
+        crate::precert_links::add(&db, b"precert1", b"tbs1", crate::precert_links::PRECERT)
+            .unwrap();
+        crate::precert_links::add(&db, b"cert1", b"tbs1", crate::precert_links::CERT).unwrap();
+        entry(b"precert1", log1);
+        // cert2 was incorporated directly by log2, and the scanner doesn't fetch the other log
+        add(&db, b"cert2", &[sct(log2, 2000), sct(unfetched, 2000)]).unwrap();
+        entry(b"cert2", log2);
+        // log1 still has time to incorporate cert3
+        add(&db, b"cert3", &[sct(log1, 9500)]).unwrap();
+
+        assert_eq!(get(&db, b"cert1").unwrap().len(), 2);
+        assert_eq!(
+            logs_with_entry(&db, b"cert1").unwrap(),
+            vec![log_num(&log1)]
+        );
+        assert_eq!(
+            missing_entries(&db, 1000, 10).unwrap(),
+            vec![MissingEntry {
+                leaf_hash: b"cert1".to_vec(),
+                log_id: log_num(&log2),
+                ts: 1000,
+            }]
+        );
+        assert_eq!(missing_entries(&db, 100, 10).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,145 @@
+// SPDX-License-Identifier: Apache-2.0
+//! History of every signed tree head observed from each log.
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+
+/// A signed tree head, as it was observed from a log. Field names match the `get-sth` response
+/// so the evidence can be checked by anyone with the log's key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoredSth {
+    pub tree_size: u64,
+    pub timestamp: u64,
+    pub sha256_root_hash: String,
+    pub tree_head_signature: String,
+    /// When we first saw the STH, in milliseconds since the Unix epoch.
+    pub fetched_at: i64,
+}
+
+/// Two or more signed tree heads of the same size but with different root hashes. Since logs
+/// are append-only, this proves the log is presenting different views of the tree.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SplitView {
+    pub log_id: u32,
+    pub tree_size: u64,
+    pub sths: Vec<StoredSth>,
+}
+
+/// Records an STH, returning `true` if it hadn't been seen before.
+pub fn record_sth(db: &Connection, log_id: u32, sth: &StoredSth) -> rusqlite::Result<bool> {
+    let inserted = db
+        .prepare_cached("INSERT OR IGNORE INTO sths (log_id, tree_size, ts, root_hash, signature, fetched_at) VALUES (?, ?, ?, ?, ?, ?)")?
+        .execute(rusqlite::params![
+            log_id,
+            sth.tree_size,
+            sth.timestamp,
+            sth.sha256_root_hash,
+            sth.tree_head_signature,
+            sth.fetched_at,
+        ])?;
+    Ok(inserted > 0)
+}
+
+/// Finds the split view at a tree size, if there is one.
+pub fn split_view_at(
+    db: &Connection,
+    log_id: u32,
+    tree_size: u64,
+) -> rusqlite::Result<Option<SplitView>> {
+    let sths = db
+        .prepare_cached("SELECT tree_size, ts, root_hash, signature, fetched_at FROM sths WHERE log_id = ? AND tree_size = ? ORDER BY fetched_at")?
+        .query_map(rusqlite::params![log_id, tree_size], |row| {
+            Ok(StoredSth {
+                tree_size: row.get(0)?,
+                timestamp: row.get(1)?,
+                sha256_root_hash: row.get(2)?,
+                tree_head_signature: row.get(3)?,
+                fetched_at: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    let first_root = match sths.first() {
+        Some(sth) => &sth.sha256_root_hash,
+        None => return Ok(None),
+    };
+    if sths.iter().all(|sth| sth.sha256_root_hash == *first_root) {
+        Ok(None)
+    } else {
+        Ok(Some(SplitView {
+            log_id,
+            tree_size,
+            sths,
+        }))
+    }
+}
+
+/// Finds all split views, optionally only for one log.
+pub fn split_views(db: &Connection, log_id: Option<u32>) -> rusqlite::Result<Vec<SplitView>> {
+    let candidates = db
+        .prepare_cached("SELECT log_id, tree_size FROM sths WHERE ?1 IS NULL OR log_id = ?1 GROUP BY log_id, tree_size HAVING COUNT(DISTINCT root_hash) > 1")?
+        .query_map([log_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<(u32, u64)>, _>>()?;
+    let mut views = Vec::with_capacity(candidates.len());
+    for (log_id, tree_size) in candidates {
+        if let Some(view) = split_view_at(db, log_id, tree_size)? {
+            views.push(view);
+        }
+    }
+    Ok(views)
+}
+
+/// The most recently observed STH for a log.
+pub fn latest_sth(db: &Connection, log_id: u32) -> rusqlite::Result<Option<StoredSth>> {
+    db.prepare_cached("SELECT tree_size, ts, root_hash, signature, fetched_at FROM sths WHERE log_id = ? ORDER BY tree_size DESC, ts DESC LIMIT 1")?
+        .query_row([log_id], |row| {
+            Ok(StoredSth {
+                tree_size: row.get(0)?,
+                timestamp: row.get(1)?,
+                sha256_root_hash: row.get(2)?,
+                tree_head_signature: row.get(3)?,
+                fetched_at: row.get(4)?,
+            })
+        })
+        .optional()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sth(tree_size: u64, root: &str, fetched_at: i64) -> StoredSth {
+        StoredSth {
+            tree_size,
+            timestamp: fetched_at as u64,
+            sha256_root_hash: root.to_string(),
+            tree_head_signature: "sig".to_string(),
+            fetched_at,
+        }
+    }
+
+    #[test]
+    fn detects_split_view() {
+        let db = crate::memory();
+        assert!(record_sth(&db, 1, &sth(10, "a", 1)).unwrap());
+        // same STH again
+        assert!(!record_sth(&db, 1, &sth(10, "a", 1)).unwrap());
+        assert!(record_sth(&db, 1, &sth(11, "b", 2)).unwrap());
+        // same root at a different size in another log isn't a split view
+        assert!(record_sth(&db, 2, &sth(10, "c", 3)).unwrap());
+        assert_eq!(split_views(&db, None).unwrap(), Vec::new());
+        assert_eq!(split_view_at(&db, 1, 10).unwrap(), None);
+
+        assert!(record_sth(&db, 1, &sth(10, "d", 4)).unwrap());
+        let views = split_views(&db, None).unwrap();
+        assert_eq!(
+            views,
+            vec![SplitView {
+                log_id: 1,
+                tree_size: 10,
+                sths: vec![sth(10, "a", 1), sth(10, "d", 4)],
+            }]
+        );
+        assert_eq!(split_views(&db, Some(2)).unwrap(), Vec::new());
+        assert_eq!(split_view_at(&db, 1, 10).unwrap(), Some(views[0].clone()));
+        assert_eq!(latest_sth(&db, 1).unwrap(), Some(sth(11, "b", 2)));
+    }
+}
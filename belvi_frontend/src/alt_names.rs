@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: Apache-2.0
+//! subjectAltNames that aren't domains or IP addresses, shown on the cert page.
+use belvi_cert::TaggedName;
+use belvi_render::html_escape::HtmlEscapable;
+
+pub fn render(names: &[TaggedName]) -> String {
+    if names.is_empty() {
+        return "<p>This certificate has no other names.</p>".to_string();
+    }
+    let rows = names
+        .iter()
+        .map(|name| {
+            format!(
+                "<tr><th>{}</th><td><code>{}</code></td></tr>",
+                name.kind.description().html_escape(),
+                name.value.html_escape()
+            )
+        })
+        .collect::<String>();
+    format!(r#"<table class="bvfront-alt-names">{}</table>"#, rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use belvi_cert::NameKind;
+
+    #[test]
+    fn render_names() {
+        assert!(render(&[]).contains("no other names"));
+        let html = render(&[
+            TaggedName {
+                kind: NameKind::Upn,
+                value: "juliet@corp.example".to_string(),
+            },
+            TaggedName {
+                kind: NameKind::DirectoryName,
+                value: "CN=<Juliet>".to_string(),
+            },
+        ]);
+        assert!(html.contains(&"User principal name".html_escape()));
+        assert!(html.contains(&"juliet@corp.example".html_escape()));
+        assert!(html.contains(&"CN=<Juliet>".html_escape()));
+        assert!(!html.contains("<Juliet>"));
+    }
+}
@@ -1,18 +1,19 @@
 // SPDX-License-Identifier: Apache-2.0
 use belvi_frontend::search::{self, QueryMode, SearchResults};
-use std::{ffi::OsString, time::Instant};
+use std::time::Instant;
 
 fn main() {
     env_logger::init();
 
     let db = belvi_db::connect_readonly();
+    let args = &belvi_config::get().args;
     let limit = 50;
     let query = search::Query {
-        query: std::env::args_os().nth(2).map(|s| s.into_string().unwrap()),
-        mode: match std::env::args_os().nth(3) {
+        query: args.first().cloned(),
+        mode: match args.get(1).map(String::as_str) {
             None => None,
-            Some(x) if x == OsString::from("regex") => Some(QueryMode::Regex),
-            Some(x) if x == OsString::from("subdomain") => Some(QueryMode::Subdomain),
+            Some("regex") => Some(QueryMode::Regex),
+            Some("subdomain") => Some(QueryMode::Subdomain),
             Some(_) => panic!("invalid mode"),
         },
         limit: Some(limit),
@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Certificate chains that were submitted with entries, shown on the cert page.
+use belvi_db::chains::Issuer;
+use belvi_render::html_escape::HtmlEscapable;
+use x509_certificate::X509Certificate;
+
+/// Name of the subject of a DER certificate, for links to it.
+pub fn subject_name(cert: &[u8]) -> String {
+    match X509Certificate::from_der(cert) {
+        Ok(cert) => cert
+            .subject_name()
+            .user_friendly_str()
+            .unwrap_or_else(|_| "(invalid name)".to_string()),
+        Err(_) => "(invalid certificate)".to_string(),
+    }
+}
+
+/// Renders the chain of a certificate, starting with its issuer. `precert_hash` is the ID of the
+/// precertificate that was submitted, for precerts.
+pub fn render(chain: &[Issuer], precert_hash: Option<&[u8]>) -> String {
+    let precert = match precert_hash {
+        Some(hash) => {
+            let hash = hex::encode(hash);
+            format!(
+                r#"<p>The precertificate that was submitted can be downloaded as <a href="/precert/{hash}.der">DER</a> or <a href="/precert/{hash}.pem">PEM</a>, if it is still stored.</p>"#,
+                hash = hash
+            )
+        }
+        None => String::new(),
+    };
+    if chain.is_empty() {
+        return format!(
+            "{}<p>The chain that was submitted with this certificate wasn't stored.</p>",
+            precert
+        );
+    }
+    let issuers = chain
+        .iter()
+        .map(|issuer| {
+            format!(
+                r#"<li><a href="/issuer/{}">{}</a></li>"#,
+                hex::encode(&issuer.hash),
+                subject_name(&issuer.cert).html_escape()
+            )
+        })
+        .fold(String::new(), |a, b| a + &b);
+    format!(
+        r#"{}<p>Issued by each of these, in order:</p><ol class="bvfront-chain">{}</ol>"#,
+        precert, issuers
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_chain() {
+        assert!(render(&[], None).contains("wasn't stored"));
+        let chain = [Issuer {
+            hash: vec![0x12, 0x34],
+            cert: b"not a cert".to_vec(),
+        }];
+        let html = render(&chain, Some(&[0xab]));
+        assert!(html.contains(r#"<a href="/issuer/1234">"#));
+        assert!(html.contains(&"(invalid certificate)".html_escape()));
+        assert!(html.contains(r#"<a href="/precert/ab.der">"#));
+    }
+}
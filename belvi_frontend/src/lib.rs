@@ -2,8 +2,14 @@
 //! This library has modules useful for the frontend. It is seperate from the binary target to
 //! allow it to be tested seperately.
 
+pub mod alt_names;
+pub mod chain;
 pub mod domain_sort;
+pub mod log_info;
+pub mod precert;
+pub mod quarantine;
 pub mod res;
+pub mod scts;
 pub mod search;
 
 pub const PRODUCT_NAME: &str = match option_env!("BELVI_PRODUCT_NAME") {
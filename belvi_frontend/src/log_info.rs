@@ -0,0 +1,344 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Information about a CT log, shown on the log's page.
+use crate::search::format_date;
+use belvi_db::{
+    merge_delay::{self, EntryDelay, MergeDelayStats},
+    sth_history::{self, StoredSth},
+};
+use belvi_log_list::{Log, LogState};
+use belvi_render::html_escape::HtmlEscapable;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rusqlite::{Connection, OptionalExtension};
+
+fn from_millis(ts: i64) -> DateTime<Utc> {
+    DateTime::<Utc>::from_utc(
+        NaiveDateTime::from_timestamp(ts / 1000, ((ts % 1000) * 1_000_000) as u32),
+        Utc,
+    )
+}
+
+pub(crate) fn render_time(ts: i64) -> String {
+    let time = from_millis(ts);
+    format!(
+        r#"<time datetime="{}">{}</time>"#,
+        time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+        format_date(time)
+    )
+}
+
+/// Misbehaviour by a log that was detected by the scanner.
+#[derive(Debug, Clone)]
+pub struct LogEvent {
+    pub ts: i64,
+    pub kind: String,
+    pub detail: String,
+}
+
+/// Result of the last consistency check between STHs.
+#[derive(Debug, Clone)]
+pub struct Consistency {
+    pub consistent: bool,
+    pub first_size: u64,
+    pub second_size: u64,
+    pub ts: i64,
+}
+
+/// Errors the scanner has had with the log.
+#[derive(Debug, Clone)]
+pub struct Health {
+    pub status: String,
+    pub consecutive_errors: u32,
+    pub total_errors: u64,
+    pub last_error: Option<String>,
+    pub skip_until: i64,
+}
+
+/// How much of the log the scanner has fetched.
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub fetched: u64,
+    pub tree_size: u64,
+    pub backfill: bool,
+    pub ts: i64,
+}
+
+#[derive(Debug, Clone)]
+pub struct LogInfo {
+    pub log_num: u32,
+    pub events: Vec<LogEvent>,
+    pub consistency: Option<Consistency>,
+    pub latest_sth: Option<StoredSth>,
+    pub health: Option<Health>,
+    pub progress: Option<Progress>,
+    pub merge_delay: Option<MergeDelayStats>,
+    /// Most recent entries that weren't incorporated within the MMD.
+    pub mmd_violations: Vec<EntryDelay>,
+}
+
+/// Number of MMD violations shown on the page.
+const VIOLATIONS_LIMIT: u32 = 50;
+
+fn format_duration(ms: u64) -> String {
+    let secs = ms / 1000;
+    if secs < 60 {
+        format!("{}.{:03} seconds", secs, ms % 1000)
+    } else if secs < 60 * 60 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {}m", secs / (60 * 60), secs / 60 % 60)
+    }
+}
+
+impl LogInfo {
+    pub fn query(db: &Connection, log_num: u32) -> rusqlite::Result<Self> {
+        let events = db
+            .prepare_cached(
+                "SELECT ts, kind, detail FROM log_events WHERE log_id = ? ORDER BY ts DESC",
+            )?
+            .query_map([log_num], |row| {
+                Ok(LogEvent {
+                    ts: row.get(0)?,
+                    kind: row.get(1)?,
+                    detail: row.get(2)?,
+                })
+            })?
+            .collect::<Result<_, _>>()?;
+        let consistency = db
+            .prepare_cached(
+                "SELECT consistent, first_size, second_size, ts FROM log_consistency WHERE log_id = ?",
+            )?
+            .query_row([log_num], |row| {
+                Ok(Consistency {
+                    consistent: row.get(0)?,
+                    first_size: row.get(1)?,
+                    second_size: row.get(2)?,
+                    ts: row.get(3)?,
+                })
+            })
+            .optional()?;
+        let health = db
+            .prepare_cached(
+                "SELECT status, consecutive_errors, total_errors, last_error, skip_until FROM log_health WHERE log_id = ?",
+            )?
+            .query_row([log_num], |row| {
+                Ok(Health {
+                    status: row.get(0)?,
+                    consecutive_errors: row.get(1)?,
+                    total_errors: row.get(2)?,
+                    last_error: row.get(3)?,
+                    skip_until: row.get(4)?,
+                })
+            })
+            .optional()?;
+        let progress = db
+            .prepare_cached(
+                "SELECT fetched, tree_size, backfill, ts FROM log_progress WHERE log_id = ?",
+            )?
+            .query_row([log_num], |row| {
+                Ok(Progress {
+                    fetched: row.get(0)?,
+                    tree_size: row.get(1)?,
+                    backfill: row.get(2)?,
+                    ts: row.get(3)?,
+                })
+            })
+            .optional()?;
+        Ok(Self {
+            log_num,
+            events,
+            consistency,
+            latest_sth: sth_history::latest_sth(db, log_num)?,
+            health,
+            progress,
+            merge_delay: merge_delay::stats(db, log_num)?,
+            mmd_violations: merge_delay::violations(db, log_num, VIOLATIONS_LIMIT)?,
+        })
+    }
+
+    pub fn render(&self, log: &Log, operator: &str) -> String {
+        let state = match log.state {
+            LogState::Pending { .. } => "Pending",
+            LogState::Qualified { .. } => "Qualified",
+            LogState::Usable { .. } => "Usable",
+            LogState::Retired { .. } => "Retired",
+            LogState::ReadOnly { .. } => "Read-only",
+            LogState::Rejected { .. } => "Rejected",
+        };
+        let log_type = if log.is_test() {
+            "Test (certificates aren't trusted by browsers)"
+        } else {
+            "Production"
+        };
+        let api = if log.is_tiled() {
+            "Static CT API (tiled)"
+        } else {
+            "RFC 6962"
+        };
+        let previous_operators = if log.previous_operators.is_empty() {
+            "None".to_string()
+        } else {
+            log.previous_operators
+                .iter()
+                .map(|op| {
+                    format!(
+                        "{} (until {})",
+                        op.name.html_escape(),
+                        op.end_time.html_escape()
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let consistency = match &self.consistency {
+            None => "Not checked yet".to_string(),
+            Some(check) => format!(
+                "{} (STH of size {} to {}, checked {})",
+                if check.consistent {
+                    "Consistent"
+                } else {
+                    "<strong>Inconsistent</strong>"
+                },
+                check.first_size,
+                check.second_size,
+                render_time(check.ts),
+            ),
+        };
+        let health = match &self.health {
+            None => "No errors".to_string(),
+            Some(health) if health.status == "healthy" => format!(
+                "Healthy ({} errors in total, last: <code>{}</code>)",
+                health.total_errors,
+                health.last_error.as_deref().unwrap_or_default().html_escape(),
+            ),
+            Some(health) => format!(
+                "<strong>{}</strong>, {} errors in a row, not checked until {} (last error: <code>{}</code>)",
+                if health.status == "quarantined" {
+                    "Quarantined"
+                } else {
+                    "Degraded"
+                },
+                health.consecutive_errors,
+                render_time(health.skip_until),
+                health.last_error.as_deref().unwrap_or_default().html_escape(),
+            ),
+        };
+        let progress = match &self.progress {
+            None => "Not fetched yet".to_string(),
+            Some(progress) => format!(
+                "{:.2}% ({} of {} entries, {}) as of {}",
+                if progress.tree_size == 0 {
+                    100.0
+                } else {
+                    progress.fetched as f64 * 100.0 / progress.tree_size as f64
+                },
+                progress.fetched,
+                progress.tree_size,
+                if progress.backfill {
+                    "fetching all entries"
+                } else {
+                    "fetching recent entries"
+                },
+                render_time(progress.ts),
+            ),
+        };
+        let merge_delay = match &self.merge_delay {
+            Some(stats) if stats.measured > 0 => format!(
+                "{} on average, {} at most ({} entries seen being incorporated, {} violations)",
+                format_duration(stats.mean_delay().unwrap_or(0)),
+                format_duration(stats.max_delay),
+                stats.measured,
+                if stats.violations > 0 {
+                    format!("<strong>{}</strong>", stats.violations)
+                } else {
+                    "0".to_string()
+                },
+            ),
+            Some(stats) => format!("<strong>{}</strong> violations", stats.violations),
+            None => "No entries seen being incorporated yet".to_string(),
+        };
+        let latest_sth = match &self.latest_sth {
+            None => "None fetched yet".to_string(),
+            Some(sth) => format!(
+                "{} entries at {} (<code>{}</code>)",
+                sth.tree_size,
+                render_time(sth.timestamp as i64),
+                sth.sha256_root_hash.html_escape(),
+            ),
+        };
+        let info = [
+            ("Operator", operator.html_escape()),
+            match &log.monitoring_url {
+                Some(url) => ("Monitoring URL", url.html_escape()),
+                None => ("URL", log.url.html_escape()),
+            },
+            ("Previous operators", previous_operators),
+            ("Type", log_type.to_string()),
+            ("API", api.to_string()),
+            ("State", state.to_string()),
+            ("Maximum merge delay", format!("{} seconds", log.mmd)),
+            ("Merge delay", merge_delay),
+            ("Latest tree head", latest_sth),
+            ("Consistency", consistency),
+            ("Health", health),
+            ("Indexed", progress),
+        ]
+        .into_iter()
+        .map(|(k, v)| format!("<tr><th>{}</th><td>{}</td></tr>", k, v))
+        .fold(String::new(), |a, b| a + &b);
+        let events = if self.events.is_empty() {
+            "<p>No events have been flagged for this log.</p>".to_string()
+        } else {
+            format!(
+                "<ul>{}</ul>",
+                self.events
+                    .iter()
+                    .map(|event| format!(
+                        r#"<li class="bvfront-log-event">{}: <code>{}</code> {}</li>"#,
+                        render_time(event.ts),
+                        event.kind.html_escape(),
+                        event.detail.html_escape()
+                    ))
+                    .fold(String::new(), |a, b| a + &b)
+            )
+        };
+        let violations = if self.mmd_violations.is_empty() {
+            "<p>No entries have been seen missing after the maximum merge delay.</p>".to_string()
+        } else {
+            format!(
+                "<ul>{}</ul>",
+                self.mmd_violations
+                    .iter()
+                    .map(|entry| format!(
+                        r#"<li class="bvfront-log-event"><a href="/cert/{}">#{}</a> with timestamp {} was missing from the tree head at {}</li>"#,
+                        hex::encode(&entry.leaf_hash),
+                        entry.idx,
+                        render_time(entry.sct_ts as i64),
+                        render_time(entry.incorporation.missing_sth_ts.unwrap_or(0) as i64),
+                    ))
+                    .fold(String::new(), |a, b| a + &b)
+            )
+        };
+        format!(
+            include_str!("tmpl/log_info.html"),
+            info = info,
+            events = events,
+            violations = violations,
+            log_num = self.log_num,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(1_500), "1.500 seconds");
+        assert_eq!(format_duration(61_000), "1m 1s");
+        assert_eq!(
+            format_duration(26 * 60 * 60 * 1000 + 5 * 60 * 1000),
+            "26h 5m"
+        );
+    }
+}
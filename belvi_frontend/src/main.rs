@@ -11,18 +11,25 @@ use axum::{
     Extension, Router,
 };
 use bcder::decode::Constructed;
+use belvi_cache::{stats::STATS, NewCert};
 use belvi_frontend::*;
-use belvi_log_list::{fetcher::Fetcher, LogId, LogList};
+use belvi_log_list::{
+    fetcher::Fetcher, rate_limit::RateLimits, source::LogListSource, LogId, LogList,
+};
 use belvi_render::{html_escape::HtmlEscapable, Render};
-use log::debug;
+use log::{debug, warn};
 use rusqlite::Connection;
-use std::{fmt::Debug, net::SocketAddr, sync::Arc, time::Instant};
-use tokio::{sync::Mutex, task};
+use std::{
+    fmt::Debug,
+    net::SocketAddr,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+use tokio::task;
 use tower_http::set_header::SetResponseHeaderLayer;
 
 struct CacheState {
-    cache_conn: belvi_cache::Connection,
-    log_list: LogList,
+    cert_store: Arc<dyn belvi_cache::CertStore>,
     fetcher: Fetcher,
 }
 
@@ -57,11 +64,12 @@ async fn get_root(query: Query<search::Query>) -> impl IntoResponse {
                 Err(resp) => return resp,
             };
             let run_time = (Instant::now() - start).as_secs_f64();
-            let domain = query
-                .query
-                .clone()
-                .unwrap_or_else(|| "".to_string())
-                .html_escape();
+            let query_text = query.query.clone().unwrap_or_default().html_escape();
+            let (domain, ip, alt_name) = match query.mode {
+                Some(search::QueryMode::Ip) => (String::new(), query_text, String::new()),
+                Some(search::QueryMode::AltName) => (String::new(), String::new(), query_text),
+                _ => (query_text, String::new(), String::new()),
+            };
             (
                 StatusCode::OK,
                 res::html_headers(),
@@ -83,6 +91,8 @@ async fn get_root(query: Query<search::Query>) -> impl IntoResponse {
                         format!(
                             include_str!("tmpl/no_results.html"),
                             domain = domain,
+                            ip = ip,
+                            alt_name = alt_name,
                             time = run_time,
                         )
                     } else {
@@ -100,6 +110,8 @@ async fn get_root(query: Query<search::Query>) -> impl IntoResponse {
                                 String::new()
                             },
                             domain = domain,
+                            ip = ip,
+                            alt_name = alt_name,
                             certs = certs
                                 .iter()
                                 .map(search::CertData::render)
@@ -127,30 +139,72 @@ async fn get_root(query: Query<search::Query>) -> impl IntoResponse {
 }
 
 lazy_static::lazy_static! {
-    // TODO: don't duplicate CacheState
-    static ref LOG_LIST: LogList = LogList::google();
+    static ref LOG_LIST_SOURCE: LogListSource = {
+        let config = belvi_config::get();
+        LogListSource::new(
+            config.log_list.as_deref(),
+            config.log_list_sig.as_deref(),
+            config.log_list_key.as_deref(),
+            &config.extra_log_lists,
+            Duration::from_secs(config.log_list_reload),
+        )
+        .expect("invalid log list config")
+    };
+    static ref LOG_LIST: RwLock<Arc<LogList>> = RwLock::new(Arc::new(
+        LOG_LIST_SOURCE.load().expect("failed to load log list")
+    ));
+}
+
+/// The current log list. It is periodically reloaded by [`reload_log_list`].
+fn log_list() -> Arc<LogList> {
+    LOG_LIST.read().unwrap().clone()
+}
+
+async fn reload_log_list(fetcher: Fetcher) {
+    loop {
+        tokio::time::sleep(LOG_LIST_SOURCE.reload_interval).await;
+        match task::spawn_blocking(|| LOG_LIST_SOURCE.load())
+            .await
+            .unwrap()
+        {
+            Ok(new_list) => {
+                fetcher.set_log_list(&new_list);
+                *LOG_LIST.write().unwrap() = Arc::new(new_list);
+            }
+            Err(err) => warn!("Failed to reload log list, keeping old list: {:?}", err),
+        }
+    }
 }
 
 fn cert_response(cert: &Vec<u8>, leaf_hash: &str, in_logs: Vec<(u32, usize)>) -> Response {
     // first try decoding as precert, then try normal cert
-    let (cert, domains, full_cert) =
+    let (cert, domains, tagged_names, full_cert, embedded_scts) =
         match Constructed::decode(cert.as_ref(), bcder::Mode::Der, |cons| {
             x509_certificate::rfc5280::TbsCertificate::take_from(cons)
         }) {
             Ok(tbs_cert) => (
                 tbs_cert.render(),
                 belvi_cert::get_cert_domains(&tbs_cert),
+                belvi_cert::get_cert_tagged_names(&tbs_cert),
                 false,
+                Vec::new(),
             ),
             Err(_) => {
                 let cert = Constructed::decode(cert.as_ref(), bcder::Mode::Der, |cons| {
                     x509_certificate::rfc5280::Certificate::take_from(cons)
                 })
                 .expect("invalid cert in log");
+                let scts =
+                    belvi_cert::sct::get_cert_scts(&cert.tbs_certificate).unwrap_or_else(|err| {
+                        warn!("Invalid SCTs in cert: {:?}", err);
+                        Vec::new()
+                    });
                 (
                     cert.render(),
                     belvi_cert::get_cert_domains(&cert.tbs_certificate),
+                    belvi_cert::get_cert_tagged_names(&cert.tbs_certificate),
                     true,
+                    scts,
                 )
             }
         };
@@ -165,21 +219,64 @@ fn cert_response(cert: &Vec<u8>, leaf_hash: &str, in_logs: Vec<(u32, usize)>) ->
         "precertificate"
     };
 
-    let log_iter = LOG_LIST.logs();
+    let chain = {
+        // the hash was checked when finding the certificate
+        let leaf_hash = hex::decode(leaf_hash).unwrap();
+        // TODO: don't block executor
+        DB_CONN.with(|db| {
+            Ok::<_, rusqlite::Error>((
+                belvi_db::chains::get(db, &leaf_hash)?,
+                belvi_db::chains::get_precert(db, &leaf_hash)?,
+                if full_cert {
+                    belvi_db::precert_links::precerts(db, &leaf_hash)?
+                } else {
+                    belvi_db::precert_links::final_certs(db, &leaf_hash)?
+                },
+                belvi_db::scts::logs_with_entry(db, &leaf_hash)?,
+            ))
+        })
+    };
+    let log_list = log_list();
+    let (chain, issuance, scts) = match chain {
+        Ok((chain, precert_hash, linked, seen_in)) => (
+            chain::render(&chain, precert_hash.as_deref()),
+            precert::render(full_cert, &linked),
+            if full_cert {
+                scts::render(&embedded_scts, &seen_in, &log_list)
+            } else {
+                "<p>Precertificates don't have embedded SCTs.</p>".to_string()
+            },
+        ),
+        Err(err) => {
+            warn!("Failed to get chain: {:?}", err);
+            (
+                "<p>The chain couldn't be fetched.</p>".to_string(),
+                String::new(),
+                String::new(),
+            )
+        }
+    };
+
+    let log_iter = log_list.logs();
     let log_info = in_logs
         .into_iter()
         .map(|(log_id, idx)| {
             let log = log_iter
                 .clone()
                 .find(|list_log| LogId(list_log.log_id.clone()).num() == log_id);
-            let log_name = log
-                .map(|log| log.description.html_escape())
-                .unwrap_or_else(|| "unknown".to_string());
+            let log_name = match log {
+                Some(log) if log.is_test() => {
+                    format!("{} (test log)", log.description.html_escape())
+                }
+                Some(log) => log.description.html_escape(),
+                None => "unknown".to_string(),
+            };
             format!(
                 r#"<li><a href="/logs/{}">{}</a> at {}</li>"#,
                 log_id,
                 log_name,
-                if let Some(log) = log {
+                // data tiles aren't useful to link to, since they are binary
+                if let Some(log) = log.filter(|log| !log.is_tiled()) {
                     format!(
                         r#"<a href="{}">#{}</a>"#,
                         log.get_entries_url(idx as u64, idx as u64),
@@ -206,6 +303,10 @@ fn cert_response(cert: &Vec<u8>, leaf_hash: &str, in_logs: Vec<(u32, usize)>) ->
                 id = leaf_hash,
                 typ = typ,
                 logs = log_info,
+                chain = chain,
+                issuance = issuance,
+                scts = scts,
+                alt_names = alt_names::render(&tagged_names),
             ),
             heading_classes = "bvfront-domain-heading",
             css = concat!(
@@ -224,16 +325,8 @@ struct FoundCert {
     in_logs: Vec<(u32, usize)>,
 }
 
-async fn find_cert(state: Arc<Mutex<CacheState>>, leaf_hash: &str) -> Result<FoundCert, Response> {
-    if leaf_hash.len() != 32 {
-        return Err(res::error(Some(
-            "Cert ID is not 32 characters long".to_string(),
-        )));
-    }
-    let leaf_hash = match hex::decode(leaf_hash) {
-        Ok(val) => val,
-        Err(_) => return Err(res::error(Some("Cert ID must be hex".to_string()))),
-    };
+async fn find_cert(state: Arc<CacheState>, leaf_hash: &str) -> Result<FoundCert, Response> {
+    let leaf_hash = decode_hash(leaf_hash, "Cert")?;
     let in_logs = DB_CONN.with(|db| {
         // TODO: don't block executor
         let mut query = db
@@ -255,13 +348,23 @@ async fn find_cert(state: Arc<Mutex<CacheState>>, leaf_hash: &str) -> Result<Fou
         return Err(res::not_found("Certificate"));
     }
 
-    let maybe_cert = { state.lock().await.cache_conn.get_cert(&leaf_hash).await };
+    let maybe_cert = match state.cert_store.get_cert(&leaf_hash).await {
+        Ok(cert) => cert,
+        Err(err) => {
+            // the certificate can still be fetched from a log
+            warn!("Failed to get cert from store: {:?}", err);
+            None
+        }
+    };
     match maybe_cert {
-        Some(cert) => Ok(FoundCert { cert, in_logs }),
+        Some(cert) => {
+            STATS.hit();
+            Ok(FoundCert { cert, in_logs })
+        }
         None => {
-            let mut state = state.lock().await;
-            let mut matching_logs = state
-                .log_list
+            STATS.miss();
+            let log_list = log_list();
+            let mut matching_logs = log_list
                 .logs()
                 .filter(|list_log| list_log.readable())
                 .filter_map(|list_log| {
@@ -279,10 +382,23 @@ async fn find_cert(state: Arc<Mutex<CacheState>>, leaf_hash: &str) -> Result<Fou
                     )))
                 }
             };
-            let entries = state
-                .fetcher
-                .fetch_entries(log, idx as u64, idx as u64)
-                .await;
+            let entries = if log.is_tiled() {
+                // the size of the tree is needed to know which data tile has the entry
+                match state.fetcher.fetch_sth(log).await {
+                    Ok(sth) => {
+                        state
+                            .fetcher
+                            .fetch_tiled_entries(log, idx as u64, idx as u64, sth.tree_size)
+                            .await
+                    }
+                    Err(err) => Err(err),
+                }
+            } else {
+                state
+                    .fetcher
+                    .fetch_entries(log, idx as u64, idx as u64)
+                    .await
+            };
             let entries = match entries {
                 Ok(val) => val,
                 Err(err) => {
@@ -307,7 +423,21 @@ async fn find_cert(state: Arc<Mutex<CacheState>>, leaf_hash: &str) -> Result<Fou
                 .log_entry
                 .inner_cert();
             drop(matching_logs);
-            state.cache_conn.new_cert(&belvi_hash::db(cert), cert);
+            STATS.refetch();
+            let not_after: i64 = DB_CONN.with(|db| {
+                db.prepare_cached("SELECT not_after FROM certs WHERE leaf_hash = ?")
+                    .unwrap()
+                    .query_row([&leaf_hash], |row| row.get(0))
+                    .unwrap()
+            });
+            let new_cert = NewCert {
+                id: &leaf_hash,
+                content: cert,
+                not_after,
+            };
+            if let Err(err) = state.cert_store.new_cert(new_cert) {
+                warn!("Failed to store cert: {:?}", err);
+            }
             Ok(FoundCert {
                 cert: cert.clone(),
                 in_logs,
@@ -316,69 +446,346 @@ async fn find_cert(state: Arc<Mutex<CacheState>>, leaf_hash: &str) -> Result<Fou
     }
 }
 
-async fn get_cert(
-    Path(leaf_hash): Path<String>,
-    Extension(state): Extension<Arc<Mutex<CacheState>>>,
-) -> impl IntoResponse {
-    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
-    enum OutputMode {
-        Der,
-        Html,
-        Pem,
-    }
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum OutputMode {
+    Der,
+    Html,
+    Pem,
+}
 
-    let mut parts = leaf_hash.split('.');
-    let leaf_hash = match parts.next() {
+/// Splits the extension off an ID in a path, such as `/cert/:leaf_hash`. Returns `Err` with a
+/// response for extensions that aren't used.
+#[allow(clippy::result_large_err)]
+fn output_mode<'a>(path: &'a str, base: &str) -> Result<(&'a str, OutputMode), Response> {
+    let mut parts = path.split('.');
+    let id = match parts.next() {
         Some(val) => val,
-        None => return res::error(Some("No leaf hash".to_string())),
+        None => return Err(res::error(Some("No hash".to_string()))),
     };
-    let ext = match parts.next() {
+    let mode = match parts.next() {
         None => OutputMode::Html,
         Some("der") => OutputMode::Der,
         Some("pem") => OutputMode::Pem,
-        Some("ber" | "cer") => return res::redirect(&format!("/cert/{}.der", leaf_hash)),
-        Some("html") => return res::redirect(&format!("/cert/{}", leaf_hash)),
-        _ => return res::error(Some("Unknown extension".to_string())),
+        Some("ber" | "cer") => return Err(res::redirect(&format!("{}/{}.der", base, id))),
+        Some("html") => return Err(res::redirect(&format!("{}/{}", base, id))),
+        _ => return Err(res::error(Some("Unknown extension".to_string()))),
     };
+    Ok((id, mode))
+}
 
+/// Responds with a certificate to download.
+fn download_response(cert: Vec<u8>, mode: OutputMode) -> Response {
+    match mode {
+        OutputMode::Pem => (
+            StatusCode::OK,
+            {
+                let mut headers = HeaderMap::new();
+                // according to https://pki-tutorial.readthedocs.io/en/latest/mime.html
+                headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/x-pem-file"),
+                );
+                headers
+            },
+            // TODO: CERTIFICATE should be different for precerts?
+            format!(
+                "-----BEGIN CERTIFICATE-----\r\n{}\r\n-----END CERTIFICATE-----\r\n",
+                base64::encode(cert)
+            ),
+        )
+            .into_response(),
+        _ => (
+            StatusCode::OK,
+            {
+                let mut headers = HeaderMap::new();
+                // according to https://pki-tutorial.readthedocs.io/en/latest/mime.html
+                headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/x-x509-ca-cert"),
+                );
+                headers
+            },
+            cert,
+        )
+            .into_response(),
+    }
+}
+
+/// Decodes a hash from a path, which is 32 hex characters.
+#[allow(clippy::result_large_err)]
+fn decode_hash(hash: &str, thing: &str) -> Result<Vec<u8>, Response> {
+    if hash.len() != 32 {
+        return Err(res::error(Some(format!(
+            "{} ID is not 32 characters long",
+            thing
+        ))));
+    }
+    hex::decode(hash).map_err(|_| res::error(Some(format!("{} ID must be hex", thing))))
+}
+
+async fn get_cert(
+    Path(leaf_hash): Path<String>,
+    Extension(state): Extension<Arc<CacheState>>,
+) -> impl IntoResponse {
+    let (leaf_hash, mode) = match output_mode(&leaf_hash, "/cert") {
+        Ok(val) => val,
+        Err(res) => return res,
+    };
     match find_cert(state, leaf_hash).await {
-        Ok(FoundCert { cert, in_logs }) => match ext {
+        Ok(FoundCert { cert, in_logs }) => match mode {
             OutputMode::Html => cert_response(&cert, leaf_hash, in_logs),
-            OutputMode::Der => (
+            mode => download_response(cert, mode),
+        },
+        Err(res) => res,
+    }
+}
+
+async fn get_issuer(Path(issuer_hash): Path<String>) -> impl IntoResponse {
+    let (issuer_hash, mode) = match output_mode(&issuer_hash, "/issuer") {
+        Ok(val) => val,
+        Err(res) => return res,
+    };
+    let hash = match decode_hash(issuer_hash, "Issuer") {
+        Ok(val) => val,
+        Err(res) => return res,
+    };
+    let issuer =
+        task::spawn_blocking(move || DB_CONN.with(|db| belvi_db::chains::get_issuer(db, &hash)))
+            .await
+            .unwrap();
+    let issuer = match issuer {
+        Ok(Some(issuer)) => issuer,
+        Ok(None) => return res::not_found("Issuer"),
+        Err(err) => return res::error(Some(format!("Error fetching issuer: {:#?}", err))),
+    };
+    if mode != OutputMode::Html {
+        return download_response(issuer.cert, mode);
+    }
+    let rendered = match x509_certificate::X509Certificate::from_der(&issuer.cert) {
+        Ok(cert) => cert.render(),
+        Err(err) => return res::error(Some(format!("Invalid issuer certificate: {}", err))),
+    };
+    let name = chain::subject_name(&issuer.cert).html_escape();
+    (
+        StatusCode::OK,
+        res::html_headers(),
+        format!(
+            include_str!("tmpl/base.html"),
+            title = format_args!("{} - {}", name, PRODUCT_NAME),
+            product_name = PRODUCT_NAME,
+            heading = name,
+            heading_classes = "",
+            content = format_args!(
+                include_str!("tmpl/issuer_info.html"),
+                cert = rendered,
+                id = issuer_hash,
+            ),
+            css = concat!(
+                include_str!("tmpl/base.css"),
+                include_str!("../../belvi_render/bvcert.css")
+            ),
+            script = include_str!("tmpl/dates.js"),
+        ),
+    )
+        .into_response()
+}
+
+/// Downloads the precertificate that was submitted for a precert, from the cert store.
+async fn get_precert(
+    Path(precert_hash): Path<String>,
+    Extension(state): Extension<Arc<CacheState>>,
+) -> impl IntoResponse {
+    let (precert_hash, mode) = match output_mode(&precert_hash, "/precert") {
+        Ok((hash, OutputMode::Html)) => (hash, OutputMode::Der),
+        Ok(val) => val,
+        Err(res) => return res,
+    };
+    let hash = match decode_hash(precert_hash, "Precertificate") {
+        Ok(val) => val,
+        Err(res) => return res,
+    };
+    match state.cert_store.get_cert(&hash).await {
+        Ok(Some(cert)) => {
+            STATS.hit();
+            download_response(cert, mode)
+        }
+        Ok(None) => {
+            STATS.miss();
+            res::not_found("Precertificate")
+        }
+        Err(err) => res::error(Some(format!("Error fetching precertificate: {:?}", err))),
+    }
+}
+
+async fn get_log(Path(log_id): Path<String>) -> impl IntoResponse {
+    let log_num: u32 = match log_id.parse() {
+        Ok(val) => val,
+        Err(_) => return res::error(Some("Log ID must be a number".to_string())),
+    };
+    let (operator, log) = match log_list().operators.iter().find_map(|op| {
+        op.all_logs()
+            .find(|log| LogId(log.log_id.clone()).num() == log_num)
+            .map(|log| (op.name.clone(), log.clone()))
+    }) {
+        Some(val) => val,
+        None => return res::not_found("Log"),
+    };
+    task::spawn_blocking(move || {
+        DB_CONN.with(|db| {
+            let info = match log_info::LogInfo::query(db, log_num) {
+                Ok(info) => info,
+                Err(err) => {
+                    return res::error(Some(format!("Error fetching log info: {:#?}", err)))
+                }
+            };
+            (
                 StatusCode::OK,
-                {
-                    let mut headers = HeaderMap::new();
-                    // according to https://pki-tutorial.readthedocs.io/en/latest/mime.html
-                    headers.insert(
-                        header::CONTENT_TYPE,
-                        HeaderValue::from_static("application/x-x509-ca-cert"),
-                    );
-                    headers
-                },
-                cert,
+                res::html_headers(),
+                format!(
+                    include_str!("tmpl/base.html"),
+                    title = format_args!("{} - {}", log.description.html_escape(), PRODUCT_NAME),
+                    product_name = PRODUCT_NAME,
+                    heading = log.description.html_escape(),
+                    heading_classes = "",
+                    content = info.render(&log, &operator),
+                    css = include_str!("tmpl/base.css"),
+                    script = include_str!("tmpl/dates.js"),
+                ),
             )
-                .into_response(),
-            OutputMode::Pem => (
+                .into_response()
+        })
+    })
+    .await
+    .unwrap()
+}
+
+async fn get_split_views(Path(log_id): Path<String>) -> impl IntoResponse {
+    let log_num: u32 = match log_id.parse() {
+        Ok(val) => val,
+        Err(_) => return res::error(Some("Log ID must be a number".to_string())),
+    };
+    let log = match log_list()
+        .logs()
+        .find(|log| LogId(log.log_id.clone()).num() == log_num)
+    {
+        Some(log) => log.clone(),
+        None => return res::not_found("Log"),
+    };
+    let views = task::spawn_blocking(move || {
+        DB_CONN.with(|db| belvi_db::sth_history::split_views(db, Some(log_num)))
+    })
+    .await
+    .unwrap();
+    match views {
+        Ok(views) => (
+            StatusCode::OK,
+            {
+                let mut headers = HeaderMap::new();
+                headers.insert(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/json"),
+                );
+                headers
+            },
+            serde_json::to_string_pretty(&serde_json::json!({
+                "log_id": log.log_id,
+                "log_url": log.monitoring_url.as_ref().unwrap_or(&log.url),
+                "split_views": views,
+            }))
+            .unwrap(),
+        )
+            .into_response(),
+        Err(err) => res::error(Some(format!("Error fetching split views: {:#?}", err))),
+    }
+}
+
+async fn get_quarantine() -> impl IntoResponse {
+    task::spawn_blocking(move || {
+        DB_CONN.with(|db| {
+            let quarantine = match quarantine::Quarantine::query(db) {
+                Ok(quarantine) => quarantine,
+                Err(err) => {
+                    return res::error(Some(format!(
+                        "Error fetching quarantined entries: {:#?}",
+                        err
+                    )))
+                }
+            };
+            let content = quarantine.render(|log_num| {
+                log_list()
+                    .logs()
+                    .find(|log| LogId(log.log_id.clone()).num() == log_num)
+                    .map(|log| log.description.clone())
+            });
+            (
                 StatusCode::OK,
-                {
-                    let mut headers = HeaderMap::new();
-                    // according to https://pki-tutorial.readthedocs.io/en/latest/mime.html
-                    headers.insert(
-                        header::CONTENT_TYPE,
-                        HeaderValue::from_static("application/x-pem-file"),
-                    );
-                    headers
-                },
-                // TODO: CERTIFICATE should be different for precerts?
+                res::html_headers(),
                 format!(
-                    "-----BEGIN CERTIFICATE-----\r\n{}\r\n-----END CERTIFICATE-----\r\n",
-                    base64::encode(cert)
+                    include_str!("tmpl/base.html"),
+                    title = format_args!("Quarantined entries - {}", PRODUCT_NAME),
+                    product_name = PRODUCT_NAME,
+                    heading = "Quarantined entries",
+                    heading_classes = "",
+                    content = content,
+                    css = include_str!("tmpl/base.css"),
+                    script = include_str!("tmpl/dates.js"),
                 ),
             )
-                .into_response(),
-        },
-        Err(res) => res,
-    }
+                .into_response()
+        })
+    })
+    .await
+    .unwrap()
+}
+
+/// Number of SCTs shown on the page of missing entries.
+const MISSING_SCTS_LIMIT: u32 = 500;
+
+async fn get_missing_scts() -> impl IntoResponse {
+    let log_list = log_list();
+    // logs might not be fetched until their maximum merge delay has passed
+    let mmd = log_list.logs().map(|log| log.mmd).max().unwrap_or(0) as i64 * 1000;
+    let missing = task::spawn_blocking(move || {
+        DB_CONN.with(|db| belvi_db::scts::missing_entries(db, mmd, MISSING_SCTS_LIMIT))
+    })
+    .await
+    .unwrap();
+    let missing = match missing {
+        Ok(missing) => missing,
+        Err(err) => return res::error(Some(format!("Error fetching SCTs: {:#?}", err))),
+    };
+    (
+        StatusCode::OK,
+        res::html_headers(),
+        format!(
+            include_str!("tmpl/base.html"),
+            title = format_args!("Unincorporated SCTs - {}", PRODUCT_NAME),
+            product_name = PRODUCT_NAME,
+            heading = "Unincorporated SCTs",
+            heading_classes = "",
+            content = format_args!(
+                include_str!("tmpl/missing_scts.html"),
+                entries = scts::render_missing(&missing, &log_list),
+            ),
+            css = include_str!("tmpl/base.css"),
+            script = include_str!("tmpl/dates.js"),
+        ),
+    )
+        .into_response()
+}
+
+async fn get_cache_stats() -> impl IntoResponse {
+    let stats = STATS.snapshot();
+    (
+        [(header::CONTENT_TYPE, "application/json")],
+        serde_json::to_string_pretty(&serde_json::json!({
+            "hits": stats.hits,
+            "misses": stats.misses,
+            "refetches": stats.refetches,
+            "evictions": stats.evictions,
+        }))
+        .unwrap(),
+    )
 }
 
 macro_rules! pages {
@@ -474,17 +881,33 @@ async fn handle_422_middleware<B>(req: Request<B>, next: Next<B>) -> Response {
 #[tokio::main(flavor = "multi_thread", worker_threads = 4)]
 async fn main() {
     env_logger::init();
+    let config = belvi_config::get();
 
-    let cache_state = Arc::new(Mutex::new(CacheState {
-        cache_conn: belvi_cache::Connection::new().await,
-        log_list: LogList::google(),
-        fetcher: Fetcher::new(),
-    }));
+    lazy_static::initialize(&LOG_LIST);
+    let fetcher = Fetcher::with_contact(
+        &config.contact,
+        RateLimits::load_or_default(config.rate_limits.as_deref()).expect("invalid rate limits"),
+    );
+    fetcher.set_log_list(&log_list());
+    tokio::spawn(reload_log_list(fetcher.clone()));
+    let cache_state = Arc::new(CacheState {
+        cert_store: belvi_cache::open(config)
+            .await
+            .expect("failed to open certificate store"),
+        fetcher,
+    });
 
     let app = Router::new()
         .route("/", get(get_root))
         .route("/cert/:leaf_hash", get(get_cert))
+        .route("/issuer/:issuer_hash", get(get_issuer))
+        .route("/precert/:precert_hash", get(get_precert))
+        .route("/logs/:log_id", get(get_log))
+        .route("/logs/:log_id/split_views.json", get(get_split_views))
         .route("/docs/:page", get(get_page))
+        .route("/quarantine", get(get_quarantine))
+        .route("/scts/missing", get(get_missing_scts))
+        .route("/stats/cache.json", get(get_cache_stats))
         .fallback(global_404.into_service())
         .layer(middleware::from_fn(log_middleware))
         .layer(middleware::from_fn(handle_422_middleware))
@@ -494,7 +917,7 @@ async fn main() {
             HeaderValue::from_static("belvi/0.1"),
         ));
 
-    axum::Server::bind(&"0.0.0.0:47371".parse().unwrap())
+    axum::Server::bind(&config.bind_addr)
         .serve(app.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .unwrap();
@@ -0,0 +1,47 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Links between precerts and final certificates, shown on the cert page.
+
+fn render_links(leaf_hashes: &[Vec<u8>]) -> String {
+    leaf_hashes
+        .iter()
+        .map(|leaf_hash| {
+            let leaf_hash = hex::encode(leaf_hash);
+            format!(r#"<a href="/cert/{0}"><code>{0}</code></a>"#, leaf_hash)
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Renders whether a precert was issued as a final certificate, or which precert a final
+/// certificate was issued for. `linked` has the leaf hashes of the other certificates.
+pub fn render(full_cert: bool, linked: &[Vec<u8>]) -> String {
+    match (full_cert, linked.is_empty()) {
+        (false, true) => {
+            "<p>This precertificate hasn't been seen issued as a final certificate.</p>".to_string()
+        }
+        (false, false) => format!(
+            "<p>This precertificate was issued as a final certificate: {}.</p>",
+            render_links(linked)
+        ),
+        (true, true) => {
+            "<p>The precertificate for this certificate hasn't been seen.</p>".to_string()
+        }
+        (true, false) => format!(
+            "<p>This certificate was issued for the precertificate {}.</p>",
+            render_links(linked)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_links() {
+        assert!(render(false, &[]).contains("hasn't been seen issued"));
+        let html = render(false, &[vec![0xab, 0xcd]]);
+        assert!(html.contains(r#"<a href="/cert/abcd"><code>abcd</code></a>"#));
+        assert!(render(true, &[vec![0x12]]).contains(r#"<a href="/cert/12">"#));
+    }
+}
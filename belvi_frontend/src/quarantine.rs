@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Log entries that the scanner quarantined, shown on the quarantine page.
+use crate::log_info::render_time;
+use belvi_db::quarantine::{self, QuarantinedEntry};
+use belvi_render::html_escape::HtmlEscapable;
+use rusqlite::Connection;
+
+/// Number of entries shown on the page.
+const LIMIT: u32 = 500;
+
+#[derive(Debug, Clone)]
+pub struct Quarantine {
+    /// Most recently quarantined entries, newest first.
+    pub entries: Vec<QuarantinedEntry>,
+    pub total: u64,
+}
+
+impl Quarantine {
+    pub fn query(db: &Connection) -> rusqlite::Result<Self> {
+        Ok(Self {
+            entries: quarantine::recent(db, LIMIT)?,
+            total: quarantine::count(db)?,
+        })
+    }
+
+    /// `log_name` gets the description of a log from its ID.
+    pub fn render(&self, log_name: impl Fn(u32) -> Option<String>) -> String {
+        let shown = if self.total > self.entries.len() as u64 {
+            format!(
+                "The {} most recent of {} entries are shown.",
+                self.entries.len(),
+                self.total
+            )
+        } else {
+            String::new()
+        };
+        let entries = if self.entries.is_empty() {
+            "<p>No entries have been quarantined.</p>".to_string()
+        } else {
+            let rows = self
+                .entries
+                .iter()
+                .map(|entry| {
+                    format!(
+                        r#"<tr><td>{}</td><td><a href="/logs/{}">{}</a></td><td>{}</td><td>{}</td><td><code>{}</code></td><td><code>{}</code></td></tr>"#,
+                        render_time(entry.ts),
+                        entry.log_id,
+                        log_name(entry.log_id)
+                            .unwrap_or_else(|| entry.log_id.to_string())
+                            .html_escape(),
+                        entry.idx,
+                        if entry.cert_type == 2 {
+                            "Precert"
+                        } else {
+                            "Cert"
+                        },
+                        hex::encode(&entry.leaf_hash),
+                        entry.reason.html_escape(),
+                    )
+                })
+                .fold(String::new(), |a, b| a + &b);
+            format!(
+                r#"<table class="bvfront-quarantine"><tr><th>Quarantined</th><th>Log</th><th>Index</th><th>Type</th><th>Leaf hash</th><th>Reason</th></tr>{}</table>"#,
+                rows
+            )
+        };
+        format!(
+            include_str!("tmpl/quarantine.html"),
+            shown = shown,
+            entries = entries,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render() {
+        let empty = Quarantine {
+            entries: Vec::new(),
+            total: 0,
+        };
+        assert!(empty.render(|_| None).contains("No entries"));
+
+        let entry = QuarantinedEntry {
+            log_id: 7,
+            idx: 42,
+            leaf_hash: vec![0xab, 0xcd],
+            cert_type: 1,
+            reason: "domain is \"&\"".to_string(),
+            ts: 0,
+        };
+        let quarantine = Quarantine {
+            entries: vec![entry],
+            total: 2,
+        };
+        let html = quarantine.render(|id| Some(format!("Log <{}>", id)));
+        assert!(html.contains(&format!(
+            r#"<a href="/logs/7">{}</a>"#,
+            "Log <7>".html_escape()
+        )));
+        assert!(html.contains("<code>abcd</code>"));
+        assert!(!html.contains("\"&\""));
+        assert!(html.contains("The 1 most recent of 2 entries"));
+    }
+}
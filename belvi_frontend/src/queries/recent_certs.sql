@@ -3,4 +3,6 @@ SELECT log_entries.leaf_hash, log_entries.log_id, log_entries.ts, domains.domain
 FROM log_entries
 LEFT JOIN domains ON log_entries.leaf_hash = domains.leaf_hash
 LEFT JOIN certs ON log_entries.leaf_hash = certs.leaf_hash
+-- precerts are shown as their final certificate, if it was logged
+WHERE NOT EXISTS (SELECT 1 FROM precert_links WHERE precert_links.precert_hash = log_entries.leaf_hash)
 ORDER BY log_entries.ts DESC
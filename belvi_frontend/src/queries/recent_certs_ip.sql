@@ -0,0 +1,7 @@
+-- SPDX-License-Identifier: Apache-2.0
+SELECT log_entries.leaf_hash, log_entries.log_id, log_entries.ts, ips.address, certs.extra_hash, certs.not_before, certs.not_after, ips.rowid
+FROM ips
+LEFT JOIN log_entries ON log_entries.leaf_hash = ips.leaf_hash
+LEFT JOIN certs ON log_entries.leaf_hash = certs.leaf_hash
+WHERE (ips.ip, ips.rowid) > (?, ?) AND ips.ip <= ? AND NOT EXISTS (SELECT 1 FROM precert_links WHERE precert_links.precert_hash = log_entries.leaf_hash)
+ORDER BY ips.ip, ips.rowid
@@ -3,5 +3,5 @@ SELECT log_entries.leaf_hash, log_entries.log_id, log_entries.ts, domains.domain
 FROM domains
 LEFT JOIN log_entries ON log_entries.leaf_hash = domains.leaf_hash
 LEFT JOIN certs ON log_entries.leaf_hash = certs.leaf_hash
-WHERE domrev(lower(domains.domain)) >= ? AND domrev(lower(domains.domain)) < ?
+WHERE domrev(lower(domains.domain)) >= ? AND domrev(lower(domains.domain)) < ? AND NOT EXISTS (SELECT 1 FROM precert_links WHERE precert_links.precert_hash = log_entries.leaf_hash)
 ORDER BY domrev(lower(domains.domain))
@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Signed certificate timestamps, shown on the cert page and on the page of entries that logs
+//! never incorporated.
+use crate::log_info::render_time;
+use belvi_cert::sct::Sct;
+use belvi_db::scts::MissingEntry;
+use belvi_log_list::{Log, LogId, LogList};
+use belvi_render::html_escape::HtmlEscapable;
+
+fn find_log(log_list: &LogList, log_key: &[u8]) -> Option<Log> {
+    let log_id = base64::encode(log_key);
+    log_list.logs().find(|log| log.log_id == log_id).cloned()
+}
+
+fn render_log(log: Option<&Log>, fallback: &str) -> String {
+    match log {
+        Some(log) => format!(
+            r#"<a href="/logs/{}">{}</a>"#,
+            LogId(log.log_id.clone()).num(),
+            log.description.html_escape()
+        ),
+        None => format!("Unknown log <code>{}</code>", fallback.html_escape()),
+    }
+}
+
+/// Renders the SCTs of a certificate. `seen_in` has the IDs of logs that have an entry for the
+/// certificate or its precert.
+pub fn render(scts: &[Sct], seen_in: &[u32], log_list: &LogList) -> String {
+    if scts.is_empty() {
+        return "<p>This certificate has no embedded SCTs.</p>".to_string();
+    }
+    let rows = scts
+        .iter()
+        .map(|sct| {
+            let log = find_log(log_list, &sct.log_id);
+            let seen = if seen_in.contains(&belvi_db::scts::log_num(&sct.log_id)) {
+                "Yes"
+            } else if log.is_some() {
+                "Not seen"
+            } else {
+                "Unknown"
+            };
+            format!(
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+                render_log(log.as_ref(), &base64::encode(sct.log_id)),
+                render_time(sct.timestamp as i64),
+                sct.algorithm_name().html_escape(),
+                seen,
+            )
+        })
+        .fold(String::new(), |a, b| a + &b);
+    format!(
+        r#"<table class="bvfront-scts"><tr><th>Log</th><th>Timestamp</th><th>Signature</th><th>Entry in log</th></tr>{}</table>"#,
+        rows
+    )
+}
+
+/// Renders SCTs from logs that didn't incorporate the entry.
+pub fn render_missing(entries: &[MissingEntry], log_list: &LogList) -> String {
+    if entries.is_empty() {
+        return "<p>Every SCT has a matching entry in its log.</p>".to_string();
+    }
+    let rows = entries
+        .iter()
+        .map(|entry| {
+            let log = log_list
+                .logs()
+                .find(|log| LogId(log.log_id.clone()).num() == entry.log_id);
+            let leaf_hash = hex::encode(&entry.leaf_hash);
+            format!(
+                r#"<tr><td>{}</td><td>{}</td><td><a href="/cert/{2}"><code>{2}</code></a></td></tr>"#,
+                render_time(entry.ts),
+                render_log(log, &entry.log_id.to_string()),
+                leaf_hash,
+            )
+        })
+        .fold(String::new(), |a, b| a + &b);
+    format!(
+        r#"<table class="bvfront-scts"><tr><th>SCT timestamp</th><th>Log</th><th>Certificate</th></tr>{}</table>"#,
+        rows
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn log_list() -> LogList {
+        serde_json::from_str(include_str!(
+            "../../belvi_log_list/test_data/log_list_v3.json"
+        ))
+        .unwrap()
+    }
+
+    #[test]
+    fn render_scts() {
+        let log_list = log_list();
+        let log = log_list.logs().next().unwrap().clone();
+        let log_key: [u8; 32] = base64::decode(&log.log_id).unwrap().try_into().unwrap();
+        let sct = |log_id| Sct {
+            version: 0,
+            log_id,
+            timestamp: 0,
+            extensions: Vec::new(),
+            hash_algorithm: 4,
+            signature_algorithm: 3,
+            signature: Vec::new(),
+        };
+        let html = render(&[sct(log_key), sct([0; 32])], &[], &log_list);
+        assert!(html.contains(&log.description.html_escape()));
+        assert!(html.contains("Not seen"));
+        assert!(html.contains(&base64::encode([0; 32]).html_escape()));
+        let html = render(
+            &[sct(log_key)],
+            &[LogId(log.log_id.clone()).num()],
+            &log_list,
+        );
+        assert!(html.contains("<td>Yes</td>"));
+    }
+}
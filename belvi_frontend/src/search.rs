@@ -17,7 +17,7 @@ fn render_domain(s: &str) -> String {
     )
 }
 
-fn format_date(date: DateTime<Utc>) -> String {
+pub fn format_date(date: DateTime<Utc>) -> String {
     date.format("%k:%M, %e %b %Y").html_escape()
 }
 
@@ -26,6 +26,10 @@ fn format_date(date: DateTime<Utc>) -> String {
 pub enum QueryMode {
     Regex,
     Subdomain,
+    /// An IP address or CIDR range
+    Ip,
+    /// A regex for subjectAltNames that aren't domains or IP addresses
+    AltName,
     Recent,
 }
 
@@ -88,6 +92,7 @@ impl Query {
         }
     }
 
+    #[allow(clippy::result_large_err)]
     pub fn search_sync(&self, db: &Connection, limit: u32) -> Result<SearchResults, Response> {
         let mut certs_stmt = db
             .prepare_cached(include_str!("queries/recent_certs.sql"))
@@ -98,6 +103,12 @@ impl Query {
         let mut cert_sub_stmt = db
             .prepare_cached(include_str!("queries/recent_certs_sub.sql"))
             .unwrap();
+        let mut certs_ip_stmt = db
+            .prepare_cached(include_str!("queries/recent_certs_ip.sql"))
+            .unwrap();
+        let mut certs_alt_name_stmt = db
+            .prepare_cached(include_str!("queries/recent_certs_alt_name.sql"))
+            .unwrap();
         let mut certs_count_stmt = db.prepare_cached("SELECT COUNT(*) FROM certs").unwrap();
         let mode = self.mode.unwrap_or(QueryMode::Recent);
         let after = self.after.clone().and_then(|after| {
@@ -136,6 +147,34 @@ impl Query {
                     .unwrap(),
                 None,
             ),
+            (Some(query), QueryMode::AltName) => {
+                (certs_alt_name_stmt.query([query]).unwrap(), None)
+            }
+            (Some(query), QueryMode::Ip) => {
+                let range = match belvi_db::ips::IpRange::parse(query) {
+                    Some(range) => range,
+                    None => {
+                        return Err(res::error(Some(
+                            "Invalid IP address or CIDR range".to_string(),
+                        )))
+                    }
+                };
+                // rows are ordered by (ip, rowid), so a page starts right after the last row of
+                // the previous page
+                let (start, start_rowid) = match &after {
+                    Some((rowid, ip)) => match ip.parse() {
+                        Ok(ip) => (belvi_db::ips::key(ip), *rowid as i64),
+                        Err(_) => return Err(res::error(None)),
+                    },
+                    None => (range.start, i64::MIN),
+                };
+                (
+                    certs_ip_stmt
+                        .query(rusqlite::params![&start[..], start_rowid, &range.end[..]])
+                        .unwrap(),
+                    None,
+                )
+            }
             (None, QueryMode::Recent) => (
                 certs_stmt.query([]).unwrap(),
                 Some(
@@ -156,6 +195,7 @@ impl Query {
 
         let mut certs = Vec::new();
         let mut next = None;
+        let mut last_ip_row = None;
         loop {
             let val = match certs_rows.next() {
                 Ok(Some(val)) => val,
@@ -192,13 +232,15 @@ impl Query {
                     Ordering::Less => {}
                     // stop requesting rows once we get enough
                     Ordering::Equal => {
-                        if mode == QueryMode::Subdomain {
-                            next = Some(format!(
+                        next = match mode {
+                            QueryMode::Subdomain => Some(format!(
                                 "{}:{}",
                                 val.get::<_, usize>(7).unwrap(),
                                 domain.unwrap_or_else(String::new),
-                            ));
-                        }
+                            )),
+                            QueryMode::Ip => last_ip_row,
+                            _ => None,
+                        };
                         break;
                     }
                     Ordering::Greater => unreachable!(),
@@ -213,6 +255,13 @@ impl Query {
                     not_after: val.get(6).unwrap(),
                 });
             }
+            if mode == QueryMode::Ip {
+                last_ip_row = Some(format!(
+                    "{}:{}",
+                    val.get::<_, usize>(7).unwrap(),
+                    domain.unwrap_or_else(String::new),
+                ));
+            }
         }
         for cert in &mut certs {
             // so when displayed they are longest to shortest
@@ -221,3 +270,59 @@ impl Query {
         Ok(SearchResults { certs, count, next })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ip_pages() {
+        let db = belvi_db::memory();
+        let certs: [(u8, &[&str]); 4] = [
+            (1, &["10.0.0.1"]),
+            (2, &["10.0.0.1"]),
+            (3, &["10.0.0.2", "10.0.0.3"]),
+            (4, &["10.0.1.1"]),
+        ];
+        for (leaf_hash, ips) in certs {
+            db.execute(
+                "INSERT INTO certs (leaf_hash, extra_hash, not_before, not_after, cert_type) VALUES (?, x'00', 0, 0, 0)",
+                [[leaf_hash]],
+            )
+            .unwrap();
+            db.execute(
+                "INSERT INTO log_entries (leaf_hash, log_id, ts, idx) VALUES (?, 0, 0, 0)",
+                [[leaf_hash]],
+            )
+            .unwrap();
+            let ips: Vec<_> = ips.iter().map(|ip| ip.parse().unwrap()).collect();
+            belvi_db::ips::add(&db, &[leaf_hash], &ips).unwrap();
+        }
+
+        let mut query = Query {
+            query: Some("10.0.0.0/24".to_string()),
+            after: None,
+            mode: Some(QueryMode::Ip),
+            limit: None,
+        };
+        let mut pages = Vec::new();
+        loop {
+            let results = query.search_sync(&db, 1).ok().unwrap();
+            pages.push(
+                results
+                    .certs
+                    .iter()
+                    .map(|cert| (cert.leaf_hash.clone(), cert.domain.len()))
+                    .collect::<Vec<_>>(),
+            );
+            match results.next {
+                Some(next) => query.after = Some(next),
+                None => break,
+            }
+        }
+        assert_eq!(
+            pages,
+            vec![vec![(vec![1], 1)], vec![(vec![2], 1)], vec![(vec![3], 2)],]
+        );
+    }
+}
@@ -11,9 +11,12 @@ fn main() {
             "{:30} {:10} {}",
             log.description,
             match log.state {
+                LogState::Pending { .. } => "pending",
+                LogState::Qualified { .. } => "qualified",
                 LogState::Usable { .. } => "usable",
                 LogState::Retired { .. } => "retired",
                 LogState::ReadOnly { .. } => "read-only",
+                LogState::Rejected { .. } => "rejected",
             },
             log.has_active_certs(now)
         );
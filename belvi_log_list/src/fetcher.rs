@@ -1,14 +1,39 @@
 // SPDX-License-Identifier: Apache-2.0
 use super::{
-    log_data::{GetEntriesItem, LogSth},
+    log_data::{
+        CTParseError, GetEntriesItem, GetEntryAndProofResponse, GetProofByHashResponse,
+        GetSthConsistencyResponse, LogSth,
+    },
+    merkle,
+    rate_limit::{RateLimiter, RateLimits},
+    signature::SignatureError,
+    tiles::{self, Checkpoint, TILE_HEIGHT, TILE_WIDTH},
     Log,
 };
 use log::{trace, warn};
-use reqwest::StatusCode;
+use reqwest::{header::RETRY_AFTER, StatusCode};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+const DEFAULT_CONTACT: &str = "belvi@smitop.com";
+/// Number of times a request is made before giving up.
+const MAX_ATTEMPTS: u32 = 4;
+/// Wait after the first failed request. It doubles after each failure, and up to the same amount
+/// of jitter is added so that requests to a log don't all retry at once.
+const BASE_RETRY_WAIT: Duration = Duration::from_secs(1);
+/// If a log asks us to wait longer than this, we give up instead of waiting.
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(5 * 60);
 
 #[derive(Debug, Clone)]
 pub struct Fetcher {
     client: reqwest::Client,
+    /// Issuer certificates of tiled logs, by SHA-256 hash. Logs share the same issuers, so this is
+    /// shared between all logs.
+    issuers: Arc<Mutex<HashMap<merkle::Hash, Vec<u8>>>>,
+    rate_limiter: Arc<RateLimiter>,
 }
 
 #[derive(Debug)]
@@ -16,37 +41,157 @@ pub struct Fetcher {
 pub enum FetchError {
     Reqwest(reqwest::Error),
     BadStatus,
+    /// The log kept responding with 429 Too Many Requests, or asked us to wait too long.
+    RateLimited,
+    BadSignature(SignatureError),
+    /// The log isn't a tiled log.
+    NotTiled,
+    /// A local file (for a `file://` monitoring URL) couldn't be read.
+    Io(std::io::Error),
+    Parse(CTParseError),
     DeserializeError {
         serde_error: serde_json::Error,
         input: bytes::Bytes,
     },
 }
 
+impl Default for Fetcher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses a `Retry-After` header, which is either a number of seconds or an HTTP date.
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    if let Ok(secs) = value.trim().parse() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = httpdate::parse_http_date(value.trim()).ok()?;
+    Some(date.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+/// How long to wait before retrying after `attempt` requests failed.
+fn retry_wait(attempt: u32) -> Duration {
+    let wait = BASE_RETRY_WAIT * 2u32.pow(attempt.saturating_sub(1));
+    wait + wait.mul_f64(fastrand::f64())
+}
+
 impl Fetcher {
     pub fn new() -> Self {
+        Self::with_contact(DEFAULT_CONTACT, RateLimits::default())
+    }
+    /// Creates a fetcher that sends `contact` to logs, so that log operators can reach us.
+    pub fn with_contact(contact: &str, limits: RateLimits) -> Self {
         let mut headers = reqwest::header::HeaderMap::new();
         headers.insert(
             "From",
-            reqwest::header::HeaderValue::from_static("belvi@smitop.com"),
+            reqwest::header::HeaderValue::from_str(contact).expect("invalid contact"),
         );
         Self {
             client: reqwest::Client::builder()
-                .user_agent("belvi/0.1 (belvi@smitop.com)")
+                .user_agent(format!("belvi/0.1 ({})", contact))
                 .default_headers(headers)
                 .brotli(true)
                 .gzip(true)
                 .https_only(true)
                 .build()
                 .unwrap(),
+            issuers: Arc::default(),
+            rate_limiter: Arc::new(RateLimiter::new(limits)),
         }
     }
-    pub async fn fetch_sth(&self, log: &Log) -> Result<LogSth, FetchError> {
-        let res = self
-            .client
-            .get(log.get_sth_url())
-            .send()
-            .await
-            .map_err(FetchError::Reqwest)?;
+    /// Updates which operator runs each log, which is needed for operator rate limits.
+    pub fn set_log_list(&self, log_list: &super::LogList) {
+        self.rate_limiter.set_log_list(log_list);
+    }
+    /// Makes a GET request to a log, waiting for the rate limit of the log. Requests that fail
+    /// because of network errors, server errors, or rate limiting are retried.
+    async fn get(&self, log: &Log, url: &str) -> Result<reqwest::Response, FetchError> {
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            self.rate_limiter.acquire(log).await;
+            let res = self.client.get(url).send().await;
+            let wait = match &res {
+                Ok(res)
+                    if res.status() == StatusCode::TOO_MANY_REQUESTS
+                        || res.status() == StatusCode::SERVICE_UNAVAILABLE =>
+                {
+                    let wait = res
+                        .headers()
+                        .get(RETRY_AFTER)
+                        .and_then(|value| value.to_str().ok())
+                        .and_then(|value| parse_retry_after(value, SystemTime::now()))
+                        .unwrap_or_else(|| retry_wait(attempt));
+                    warn!(
+                        "\"{}\" responded with {}, waiting {:?}",
+                        log.description,
+                        res.status().as_str(),
+                        wait
+                    );
+                    // slow down all requests to the log, not just this one
+                    self.rate_limiter.pause(log, wait);
+                    if wait > MAX_RETRY_WAIT || attempt >= MAX_ATTEMPTS {
+                        return Err(FetchError::RateLimited);
+                    }
+                    wait
+                }
+                Ok(res) if res.status().is_server_error() => retry_wait(attempt),
+                Ok(_) => return res.map_err(FetchError::Reqwest),
+                Err(err) if err.is_timeout() || err.is_connect() || err.is_request() => {
+                    retry_wait(attempt)
+                }
+                Err(_) => return res.map_err(FetchError::Reqwest),
+            };
+            if attempt >= MAX_ATTEMPTS {
+                return res.map_err(FetchError::Reqwest);
+            }
+            trace!("retrying {} in {:?}", url, wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+    /// Fetches a file from the monitoring prefix of a tiled log. `file://` URLs are read from the
+    /// local filesystem, which is useful for mirrors and tests.
+    async fn fetch_monitoring(
+        &self,
+        log: &Log,
+        url: Option<String>,
+    ) -> Result<bytes::Bytes, FetchError> {
+        let url = url.ok_or(FetchError::NotTiled)?;
+        if let Some(path) = url.strip_prefix("file://") {
+            return match tokio::fs::read(path).await {
+                Ok(data) => Ok(data.into()),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    Err(FetchError::BadStatus)
+                }
+                Err(err) => Err(FetchError::Io(err)),
+            };
+        }
+        let res = self.get(log, &url).await?;
+        if res.status() != StatusCode::OK {
+            warn!(
+                "bad resp status {} from {}",
+                res.status().as_str(),
+                res.url()
+            );
+            return Err(FetchError::BadStatus);
+        }
+        res.bytes().await.map_err(FetchError::Reqwest)
+    }
+    async fn fetch_json<T: serde::de::DeserializeOwned>(
+        &self,
+        log: &Log,
+        url: String,
+    ) -> Result<T, FetchError> {
+        let res = self.get(log, &url).await?;
+        if res.status() != StatusCode::OK {
+            warn!(
+                "bad resp status {} from {}",
+                res.status().as_str(),
+                res.url()
+            );
+            return Err(FetchError::BadStatus);
+        }
         let bytes = res.bytes().await.map_err(FetchError::Reqwest)?;
         match serde_json::from_slice(&bytes) {
             Ok(v) => Ok(v),
@@ -56,6 +201,48 @@ impl Fetcher {
             }),
         }
     }
+    /// Fetches the latest STH of the log, and checks that it was signed by the log.
+    pub async fn fetch_sth(&self, log: &Log) -> Result<LogSth, FetchError> {
+        let sth: LogSth = if log.is_tiled() {
+            self.fetch_checkpoint(log).await?
+        } else {
+            self.fetch_json(log, log.get_sth_url()).await?
+        };
+        log.verify_sth(&sth).map_err(FetchError::BadSignature)?;
+        Ok(sth)
+    }
+    pub async fn fetch_sth_consistency(
+        &self,
+        log: &Log,
+        first: u64,
+        second: u64,
+    ) -> Result<GetSthConsistencyResponse, FetchError> {
+        self.fetch_json(log, log.get_sth_consistency_url(first, second))
+            .await
+    }
+    pub async fn fetch_proof_by_hash(
+        &self,
+        log: &Log,
+        leaf_hash: &merkle::Hash,
+        tree_size: u64,
+    ) -> Result<GetProofByHashResponse, FetchError> {
+        // base64 can contain characters that are special in URLs
+        let hash = base64::encode(leaf_hash)
+            .replace('+', "%2B")
+            .replace('/', "%2F")
+            .replace('=', "%3D");
+        self.fetch_json(log, log.get_proof_by_hash_url(hash, tree_size))
+            .await
+    }
+    pub async fn fetch_entry_and_proof(
+        &self,
+        log: &Log,
+        leaf_index: u64,
+        tree_size: u64,
+    ) -> Result<GetEntryAndProofResponse, FetchError> {
+        self.fetch_json(log, log.get_entry_and_proof_url(leaf_index, tree_size))
+            .await
+    }
     pub async fn fetch_entries(
         &self,
         log: &Log,
@@ -63,14 +250,8 @@ impl Fetcher {
         end: u64,
     ) -> Result<Vec<GetEntriesItem>, FetchError> {
         trace!("fetching {}-{} from \"{}\"", start, end, log.description);
-        let resp = self
-            .client
-            .get(log.get_entries_url(start, end))
-            .send()
-            .await
-            .map_err(FetchError::Reqwest)?;
+        let resp = self.get(log, &log.get_entries_url(start, end)).await?;
         if resp.status() != StatusCode::OK {
-            // TODO: proper backoff after 429
             warn!(
                 "bad resp status {} while fetching {}-{} from \"{}\": {}",
                 resp.status().as_str(),
@@ -81,7 +262,231 @@ impl Fetcher {
             );
             Err(FetchError::BadStatus)
         } else {
-            Ok(GetEntriesItem::parse(&resp.text().await.map_err(FetchError::Reqwest)?).unwrap())
+            GetEntriesItem::parse(&resp.text().await.map_err(FetchError::Reqwest)?)
+                .map_err(FetchError::Parse)
+        }
+    }
+    /// Fetches the checkpoint of a tiled log, as an STH. The signature isn't checked.
+    async fn fetch_checkpoint(&self, log: &Log) -> Result<LogSth, FetchError> {
+        let checkpoint = self.fetch_monitoring(log, log.checkpoint_url()).await?;
+        let checkpoint = std::str::from_utf8(&checkpoint)
+            .map_err(|_| FetchError::Parse(CTParseError::CheckpointMalformed))?;
+        Checkpoint::parse(checkpoint)
+            .and_then(|checkpoint| checkpoint.to_sth())
+            .map_err(FetchError::Parse)
+    }
+    /// Fetches a tile that has `width` entries in a tree. Partial tiles can be deleted once the
+    /// full tile exists, so the full tile is used if the partial tile can't be found.
+    async fn fetch_tile(
+        &self,
+        log: &Log,
+        level: Option<u32>,
+        index: u64,
+        width: u64,
+    ) -> Result<bytes::Bytes, FetchError> {
+        match self
+            .fetch_monitoring(log, log.tile_url(level, index, width))
+            .await
+        {
+            Err(FetchError::BadStatus) if width != TILE_WIDTH => {
+                self.fetch_monitoring(log, log.tile_url(level, index, TILE_WIDTH))
+                    .await
+            }
+            res => res,
+        }
+    }
+    async fn fetch_issuer(&self, log: &Log, fingerprint: &merkle::Hash) -> Result<(), FetchError> {
+        if self.issuers.lock().unwrap().contains_key(fingerprint) {
+            return Ok(());
+        }
+        let cert = self
+            .fetch_monitoring(log, log.issuer_url(fingerprint))
+            .await?;
+        if merkle::sha256(&[&cert]) != *fingerprint {
+            warn!(
+                "issuer {} from \"{}\" has the wrong hash",
+                base64::encode(fingerprint),
+                log.description
+            );
+            return Err(FetchError::BadStatus);
+        }
+        self.issuers
+            .lock()
+            .unwrap()
+            .insert(*fingerprint, cert.to_vec());
+        Ok(())
+    }
+    /// Fetches the inclusive range `start..=end` of entries from a tiled log, where the tree has
+    /// `tree_size` entries. Only the entries from one data tile are returned, so fewer entries than
+    /// requested may be returned.
+    pub async fn fetch_tiled_entries(
+        &self,
+        log: &Log,
+        start: u64,
+        end: u64,
+        tree_size: u64,
+    ) -> Result<Vec<GetEntriesItem>, FetchError> {
+        trace!(
+            "fetching tiles {}-{} from \"{}\"",
+            start,
+            end,
+            log.description
+        );
+        let index = start / TILE_WIDTH;
+        let width = tiles::tile_width(index, tree_size);
+        let tile = self.fetch_tile(log, None, index, width).await?;
+        let leaves = tiles::parse_data_tile(&tile).map_err(FetchError::Parse)?;
+        let offset = start % TILE_WIDTH;
+        let leaves = leaves
+            .get(offset as usize..width.min(leaves.len() as u64) as usize)
+            .unwrap_or_default();
+        let leaves = &leaves[..leaves.len().min((end - start + 1) as usize)];
+        for leaf in leaves {
+            for fingerprint in &leaf.chain {
+                self.fetch_issuer(log, fingerprint).await?;
+            }
+        }
+        let issuers = self.issuers.lock().unwrap();
+        leaves
+            .iter()
+            .map(|leaf| leaf.to_entry(&issuers))
+            .collect::<Result<_, _>>()
+            .map_err(FetchError::Parse)
+    }
+    /// Builds the part of the audit path that is above the complete subtree of height `height`
+    /// starting at `first_leaf` from the hash tiles of a tiled log. The result can be checked with
+    /// [`merkle::verify_subtree_inclusion`].
+    pub async fn fetch_tiled_subtree_proof(
+        &self,
+        log: &Log,
+        first_leaf: u64,
+        height: u32,
+        tree_size: u64,
+    ) -> Result<Vec<merkle::Hash>, FetchError> {
+        let mut reader = TileReader::new(self, log, tree_size);
+        // walk down from the root to the subtree, recording the sibling at each level
+        let mut siblings = Vec::new();
+        let (mut lo, mut hi) = (0, tree_size);
+        while hi - lo > 1 << height {
+            // largest power of two smaller than the size of the range
+            let k = 1 << (63 - (hi - lo - 1).leading_zeros());
+            if first_leaf < lo + k {
+                siblings.push((lo + k, hi));
+                hi = lo + k;
+            } else {
+                siblings.push((lo, lo + k));
+                lo += k;
+            }
+        }
+        let mut proof = Vec::with_capacity(siblings.len());
+        for (start, end) in siblings.into_iter().rev() {
+            proof.push(reader.range_hash(start, end).await?);
+        }
+        Ok(proof)
+    }
+    /// Computes the root hash of the first `size` entries of a tiled log from the hash tiles of
+    /// the tree with `tree_size` entries. When the tree has grown, this can be compared with the
+    /// root hash of an older STH to check that the log is append-only.
+    pub async fn fetch_tiled_root(
+        &self,
+        log: &Log,
+        size: u64,
+        tree_size: u64,
+    ) -> Result<merkle::Hash, FetchError> {
+        if size == 0 {
+            return Ok(merkle::sha256(&[]));
+        }
+        TileReader::new(self, log, tree_size)
+            .range_hash(0, size)
+            .await
+    }
+}
+
+/// Reads nodes of the Merkle tree of a tiled log from its hash tiles.
+struct TileReader<'a> {
+    fetcher: &'a Fetcher,
+    log: &'a Log,
+    tree_size: u64,
+    tiles: HashMap<(u32, u64), Vec<merkle::Hash>>,
+}
+
+impl<'a> TileReader<'a> {
+    fn new(fetcher: &'a Fetcher, log: &'a Log, tree_size: u64) -> Self {
+        Self {
+            fetcher,
+            log,
+            tree_size,
+            tiles: HashMap::new(),
+        }
+    }
+    /// Hash of the complete subtree of height `height` starting at `first_leaf`.
+    async fn node(&mut self, first_leaf: u64, height: u32) -> Result<merkle::Hash, FetchError> {
+        // each level of tiles stores the nodes at a multiple of 8 levels, and the nodes in
+        // between are computed from them
+        let level = height / TILE_HEIGHT;
+        let tile_level_height = level * TILE_HEIGHT;
+        let count = 1 << (height - tile_level_height);
+        let first = first_leaf >> tile_level_height;
+        let index = first / TILE_WIDTH;
+        if !self.tiles.contains_key(&(level, index)) {
+            let width = tiles::tile_width(index, self.tree_size >> tile_level_height);
+            let tile = self
+                .fetcher
+                .fetch_tile(self.log, Some(level), index, width)
+                .await?;
+            let hashes = tiles::parse_hash_tile(&tile).map_err(FetchError::Parse)?;
+            self.tiles.insert((level, index), hashes);
+        }
+        let offset = (first % TILE_WIDTH) as usize;
+        let hashes = self.tiles[&(level, index)]
+            .get(offset..offset + count)
+            .ok_or(FetchError::Parse(CTParseError::HashWrongLength))?;
+        Ok(merkle::perfect_subtree_root(hashes))
+    }
+    /// Merkle tree hash of the leaves in `start..end`, where the range is a node of the tree or
+    /// starts at 0.
+    async fn range_hash(&mut self, start: u64, end: u64) -> Result<merkle::Hash, FetchError> {
+        let mut nodes = Vec::new();
+        for (first_leaf, height) in merkle::aligned_subtrees(start, end - 1) {
+            nodes.push(self.node(first_leaf, height).await?);
+        }
+        let last = nodes.pop().expect("range is never empty");
+        Ok(nodes
+            .iter()
+            .rev()
+            .fold(last, |acc, node| merkle::node_hash(node, &acc)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn retry_after() {
+        let now = httpdate::parse_http_date("Sun, 18 Oct 2026 12:00:00 GMT").unwrap();
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 18 Oct 2026 12:01:30 GMT", now),
+            Some(Duration::from_secs(90))
+        );
+        // dates in the past mean there's no need to wait
+        assert_eq!(
+            parse_retry_after("Sun, 18 Oct 2026 11:00:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+
+    #[test]
+    fn retry_wait_grows() {
+        for attempt in 1..MAX_ATTEMPTS {
+            let wait = retry_wait(attempt);
+            let base = BASE_RETRY_WAIT * 2u32.pow(attempt - 1);
+            assert!(wait >= base && wait <= base * 2);
         }
     }
 }
@@ -6,6 +6,15 @@ pub mod fetcher;
 pub mod log_data;
 #[cfg(test)]
 mod log_test;
+pub mod merkle;
+pub mod rate_limit;
+pub mod signature;
+pub mod source;
+#[cfg(test)]
+mod test_keys;
+pub mod tiles;
+#[cfg(test)]
+mod tiles_test;
 
 #[cfg(test)]
 mod log_list_test;
@@ -36,6 +45,16 @@ pub struct LogListOperator {
     pub name: String,
     pub email: Vec<String>,
     pub logs: Vec<Log>,
+    /// Logs that use the [static-ct-api](https://c2sp.org/static-ct-api).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tiled_logs: Vec<Log>,
+}
+
+impl LogListOperator {
+    /// Returns an iterator of the RFC 6962 and tiled logs of the operator.
+    pub fn all_logs(&self) -> impl Iterator<Item = &Log> + Clone {
+        self.logs.iter().chain(self.tiled_logs.iter())
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -43,14 +62,49 @@ pub struct Log {
     pub description: String,
     pub log_id: String,
     pub key: String,
+    /// Base URL of the RFC 6962 API. Empty for tiled logs.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub url: String,
+    /// Prefix that checkpoints and tiles are fetched from, for tiled logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monitoring_url: Option<String>,
+    /// Prefix that certificates are submitted to, for tiled logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub submission_url: Option<String>,
     pub mmd: u32,
     pub state: LogState,
     pub temporal_interval: Option<TemporalInterval>,
+    /// Missing in older log lists, where all logs are production logs.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_type: Option<LogType>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub previous_operators: Vec<PreviousOperator>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LogType {
+    #[serde(rename = "prod")]
+    Prod,
+
+    #[serde(rename = "test")]
+    Test,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PreviousOperator {
+    pub name: String,
+    /// When the log stopped being run by this operator.
+    pub end_time: String,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum LogState {
+    #[serde(rename = "pending")]
+    Pending { timestamp: String },
+
+    #[serde(rename = "qualified")]
+    Qualified { timestamp: String },
+
     #[serde(rename = "usable")]
     Usable { timestamp: String },
 
@@ -62,6 +116,9 @@ pub enum LogState {
         timestamp: String,
         final_tree_head: TreeHead,
     },
+
+    #[serde(rename = "rejected")]
+    Rejected { timestamp: String },
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -111,13 +168,36 @@ impl Log {
         )
     }
     #[must_use]
-    pub fn get_entry_and_proof_url(&self, leaf_index: u32, tree_size: TreeSize) -> String {
+    pub fn get_entry_and_proof_url(&self, leaf_index: TreeSize, tree_size: TreeSize) -> String {
         format!(
             "{}ct/v1/get-entry-and-proof?leaf_index={}&tree_size={}",
             self.url, leaf_index, tree_size
         )
     }
 
+    /// Does this log use the static-ct-api instead of the RFC 6962 API?
+    #[must_use]
+    pub fn is_tiled(&self) -> bool {
+        self.monitoring_url.is_some()
+    }
+    #[must_use]
+    pub fn checkpoint_url(&self) -> Option<String> {
+        Some(format!("{}checkpoint", self.monitoring_url.as_ref()?))
+    }
+    #[must_use]
+    pub fn tile_url(&self, level: Option<u32>, index: TreeSize, width: u64) -> Option<String> {
+        Some(format!(
+            "{}{}",
+            self.monitoring_url.as_ref()?,
+            tiles::tile_path(level, index, width)
+        ))
+    }
+    #[must_use]
+    pub fn issuer_url(&self, fingerprint: &merkle::Hash) -> Option<String> {
+        let hex: String = fingerprint.iter().map(|b| format!("{:02x}", b)).collect();
+        Some(format!("{}issuer/{}", self.monitoring_url.as_ref()?, hex))
+    }
+
     /// Is it possible that this log has unexpired certs that can be fetched?
     #[must_use]
     pub fn has_active_certs(&self, now: DateTime<Utc>) -> bool {
@@ -142,7 +222,10 @@ impl Log {
             end_exclusive,
         }) = &self.temporal_interval
         {
-            if matches!(self.state, LogState::Retired { .. }) {
+            if matches!(
+                self.state,
+                LogState::Retired { .. } | LogState::Rejected { .. }
+            ) {
                 false
             } else {
                 let end_exclusive =
@@ -152,9 +235,11 @@ impl Log {
         } else {
             match self.state {
                 // log isn't up anymore
-                LogState::Retired { .. } => false,
-                // timestamp is time when log started
-                LogState::Usable { .. } => true,
+                LogState::Retired { .. } | LogState::Rejected { .. } => false,
+                // timestamp is time when log started, or when it started to be trusted
+                LogState::Pending { .. } | LogState::Qualified { .. } | LogState::Usable { .. } => {
+                    true
+                }
                 // timestamp is point when certs stop being accepted
                 LogState::ReadOnly { ref timestamp, .. } => {
                     let timestamp =
@@ -169,9 +254,18 @@ impl Log {
     pub fn readable(&self) -> bool {
         matches!(
             self.state,
-            LogState::ReadOnly { .. } | LogState::Usable { .. }
+            LogState::Pending { .. }
+                | LogState::Qualified { .. }
+                | LogState::ReadOnly { .. }
+                | LogState::Usable { .. }
         )
     }
+
+    /// Is this a test log, whose certificates aren't trusted by browsers?
+    #[must_use]
+    pub fn is_test(&self) -> bool {
+        self.log_type == Some(LogType::Test)
+    }
 }
 
 impl LogList {
@@ -180,8 +274,29 @@ impl LogList {
         serde_json::from_str(include_str!("../log_list.json")).unwrap()
     }
 
+    /// Adds the logs from another list. Logs that are already in this list are skipped, and logs
+    /// from operators with the same name are added to the existing operator.
+    pub fn merge(&mut self, other: LogList) {
+        for mut op in other.operators {
+            let known = |log: &Log| self.logs().any(|known| known.log_id == log.log_id);
+            op.logs.retain(|log| !known(log));
+            op.tiled_logs.retain(|log| !known(log));
+            match self
+                .operators
+                .iter_mut()
+                .find(|existing| existing.name == op.name)
+            {
+                Some(existing) => {
+                    existing.logs.append(&mut op.logs);
+                    existing.tiled_logs.append(&mut op.tiled_logs);
+                }
+                None => self.operators.push(op),
+            }
+        }
+    }
+
     /// Returns an iterator of all logs run by all log operators.
     pub fn logs(&self) -> impl Iterator<Item = &Log> + Clone {
-        self.operators.iter().flat_map(|op| op.logs.iter())
+        self.operators.iter().flat_map(LogListOperator::all_logs)
     }
 }
@@ -1,4 +1,5 @@
 // SPDX-License-Identifier: Apache-2.0
+use crate::merkle;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::cmp;
@@ -14,9 +15,15 @@ pub struct LogSth {
     pub tree_head_signature: String,
 }
 
+impl LogSth {
+    pub fn root_hash(&self) -> Result<merkle::Hash, CTParseError> {
+        parse_hash(&self.sha256_root_hash)
+    }
+}
+
 impl PartialOrd for LogSth {
     fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
-        self.tree_size.partial_cmp(&other.tree_size)
+        Some(self.cmp(other))
     }
 }
 
@@ -36,7 +43,14 @@ pub enum CTParseError {
     MerkleTreeLeafTooShort,
     MerkleTreeLeafUnknownLeafType,
     TimestampedEntryTooShort,
+    TimestampedEntryTrailingData,
+    ExtraDataTooShort,
+    ExtraDataTrailingData,
     LogEntryUnknownEntryType,
+    HashWrongLength,
+    CheckpointMalformed,
+    CheckpointNoSignature,
+    MissingIssuer(merkle::Hash),
     Base64Error(base64::DecodeError),
     JsonError(serde_json::Error),
 }
@@ -45,6 +59,8 @@ pub enum CTParseError {
 pub struct GetEntriesItem {
     pub leaf_input: MerkleTreeLeaf,
     pub extra_data: Vec<u8>,
+    /// RFC 6962 Merkle tree hash of `leaf_input`.
+    pub leaf_hash: merkle::Hash,
 }
 
 impl GetEntriesItem {
@@ -66,10 +82,12 @@ impl GetEntriesItem {
             return Err(CTParseError::GetEntriesEntryNoLeafInput);
         };
         let leaf_input = base64::decode(leaf_input).map_err(CTParseError::Base64Error)?;
+        let leaf_hash = merkle::leaf_hash(&leaf_input);
         let leaf_input = MerkleTreeLeaf::parse(&leaf_input)?;
         Ok(Self {
             extra_data,
             leaf_input,
+            leaf_hash,
         })
     }
     pub fn parse(entries: &str) -> Result<Vec<Self>, CTParseError> {
@@ -90,6 +108,100 @@ impl GetEntriesItem {
         }
         Ok(parsed_entries)
     }
+
+    /// Parses the certificates that were submitted with the entry from `extra_data`.
+    pub fn chain(&self) -> Result<EntryChain<'_>, CTParseError> {
+        fn take_cert<'a>(v: &'a [u8], pos: &mut usize) -> Result<&'a [u8], CTParseError> {
+            let (cert, len) =
+                take_length_prefixed(&v[*pos..], 3).map_err(|_| CTParseError::ExtraDataTooShort)?;
+            *pos += len;
+            Ok(cert)
+        }
+        let v = &self.extra_data[..];
+        let mut pos = 0;
+        let pre_certificate = match self.leaf_input.timestamped_entry.log_entry {
+            LogEntry::X509(_) => None,
+            LogEntry::Precert { .. } => Some(take_cert(v, &mut pos)?),
+        };
+        let chain_bytes = take_cert(v, &mut pos)?;
+        if pos != v.len() {
+            return Err(CTParseError::ExtraDataTrailingData);
+        }
+        let mut chain = Vec::new();
+        let mut chain_pos = 0;
+        while chain_pos < chain_bytes.len() {
+            chain.push(take_cert(chain_bytes, &mut chain_pos)?);
+        }
+        Ok(EntryChain {
+            pre_certificate,
+            chain,
+        })
+    }
+}
+
+/// Certificates submitted with an entry, as described in
+/// [RFC 6962 section 4.6](https://www.rfc-editor.org/rfc/rfc6962#section-4.6).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryChain<'a> {
+    /// The precertificate that was submitted, if this is a precert entry.
+    pub pre_certificate: Option<&'a [u8]>,
+    /// DER certificates, starting with the issuer of the entry and ending with (or just below)
+    /// a root.
+    pub chain: Vec<&'a [u8]>,
+}
+
+fn parse_hash(hash: &str) -> Result<merkle::Hash, CTParseError> {
+    base64::decode(hash)
+        .map_err(CTParseError::Base64Error)?
+        .try_into()
+        .map_err(|_| CTParseError::HashWrongLength)
+}
+
+fn parse_audit_path(path: &[String]) -> Result<Vec<merkle::Hash>, CTParseError> {
+    path.iter().map(|hash| parse_hash(hash)).collect()
+}
+
+/// Response from the `get-proof-by-hash` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetProofByHashResponse {
+    pub leaf_index: u64,
+    pub audit_path: Vec<String>,
+}
+
+impl GetProofByHashResponse {
+    pub fn audit_path(&self) -> Result<Vec<merkle::Hash>, CTParseError> {
+        parse_audit_path(&self.audit_path)
+    }
+}
+
+/// Response from the `get-sth-consistency` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetSthConsistencyResponse {
+    pub consistency: Vec<String>,
+}
+
+impl GetSthConsistencyResponse {
+    pub fn consistency(&self) -> Result<Vec<merkle::Hash>, CTParseError> {
+        parse_audit_path(&self.consistency)
+    }
+}
+
+/// Response from the `get-entry-and-proof` endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GetEntryAndProofResponse {
+    pub leaf_input: String,
+    pub extra_data: String,
+    pub audit_path: Vec<String>,
+}
+
+impl GetEntryAndProofResponse {
+    pub fn leaf_hash(&self) -> Result<merkle::Hash, CTParseError> {
+        let leaf_input = base64::decode(&self.leaf_input).map_err(CTParseError::Base64Error)?;
+        Ok(merkle::leaf_hash(&leaf_input))
+    }
+    pub fn audit_path(&self) -> Result<Vec<merkle::Hash>, CTParseError> {
+        parse_audit_path(&self.audit_path)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -99,8 +211,36 @@ pub struct TimestampedEntry {
     pub extensions: CtExtensions,
 }
 
+/// Reads a big-endian length of `len_bytes` bytes, followed by that many bytes of data. Returns
+/// the data and the total number of bytes read.
+pub(crate) fn take_length_prefixed(
+    v: &[u8],
+    len_bytes: usize,
+) -> Result<(&[u8], usize), CTParseError> {
+    if v.len() < len_bytes {
+        return Err(CTParseError::TimestampedEntryTooShort);
+    }
+    let len = v[..len_bytes]
+        .iter()
+        .fold(0, |acc, byte| (acc << 8) | *byte as usize);
+    let end = len_bytes + len;
+    if v.len() < end {
+        return Err(CTParseError::TimestampedEntryTooShort);
+    }
+    Ok((&v[len_bytes..end], end))
+}
+
 impl TimestampedEntry {
     pub fn parse(v: &[u8]) -> Result<Self, CTParseError> {
+        let (entry, len) = Self::parse_prefix(v)?;
+        if len != v.len() {
+            return Err(CTParseError::TimestampedEntryTrailingData);
+        }
+        Ok(entry)
+    }
+
+    /// Parses a `TimestampedEntry` at the start of `v`, returning the entry and its length.
+    pub fn parse_prefix(v: &[u8]) -> Result<(Self, usize), CTParseError> {
         if v.len() <= 11 {
             return Err(CTParseError::TimestampedEntryTooShort);
         };
@@ -108,28 +248,40 @@ impl TimestampedEntry {
             u64::from_be_bytes(v[0..=7].try_into().expect("slice is always right length"));
         let entry_type =
             u16::from_be_bytes(v[8..=9].try_into().expect("slice is always right length"));
-        let log_entry = match entry_type {
-            // just skip the next 3 bytes?
-            0 => LogEntry::X509(v[13..].to_vec()),
+        let (signed_entry, pos) = match entry_type {
+            0 => {
+                let (cert, len) = take_length_prefixed(&v[10..], 3)?;
+                (cert, 10 + len)
+            }
             1 => {
                 if v.len() <= 43 {
                     return Err(CTParseError::TimestampedEntryTooShort);
                 };
-                assert!(v[v.len() - 1] == 0, "TODO: extensions");
-                assert!(v[v.len() - 2] == 0, "TODO: extensions");
-                LogEntry::Precert {
-                    issuer_key_hash: v[10..=41].try_into().expect("slice is always right length"),
-                    // just skip the next 4 bytes?
-                    tbs_certificate: v[45..].to_vec(),
-                }
+                let (tbs, len) = take_length_prefixed(&v[42..], 3)?;
+                (tbs, 42 + len)
             }
             _ => return Err(CTParseError::LogEntryUnknownEntryType),
         };
-        Ok(Self {
-            timestamp,
-            log_entry,
-            extensions: CtExtensions(vec![]), // TODO: extensions
-        })
+        let (extensions, ext_len) = take_length_prefixed(&v[pos..], 2)?;
+        // Belvi has always stored entries followed by an empty extensions field, and uses the
+        // hash of those bytes as the ID of the entry. Extensions (such as the leaf index added by
+        // static-ct-api logs) aren't part of the certificate, so they are always left out here.
+        let entry_bytes = [signed_entry, &[0, 0]].concat();
+        let log_entry = match entry_type {
+            0 => LogEntry::X509(entry_bytes),
+            _ => LogEntry::Precert {
+                issuer_key_hash: v[10..=41].try_into().expect("slice is always right length"),
+                tbs_certificate: entry_bytes,
+            },
+        };
+        Ok((
+            Self {
+                timestamp,
+                log_entry,
+                extensions: CtExtensions(extensions.to_vec()),
+            },
+            pos + ext_len,
+        ))
     }
 }
 
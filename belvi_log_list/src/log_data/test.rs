@@ -6,3 +6,58 @@ fn argon2021() {
     let data = include_str!("../../test_data/argon2021-get-entries?start=0&end=1.json");
     GetEntriesItem::parse(data).unwrap();
 }
+
+/// The bytes of each entry are used to identify it in the database, so they must not change.
+#[test]
+fn argon2021_entry_bytes() {
+    let data = include_str!("../../test_data/argon2021-get-entries?start=0&end=1.json");
+    let json: Value = serde_json::from_str(data).unwrap();
+    let entries = GetEntriesItem::parse(data).unwrap();
+    for (entry, raw) in entries.iter().zip(json["entries"].as_array().unwrap()) {
+        let leaf_input = base64::decode(raw["leaf_input"].as_str().unwrap()).unwrap();
+        let entry_type = leaf_input[11];
+        let inner_start = if entry_type == 0 { 15 } else { 47 };
+        assert_eq!(
+            entry.leaf_input.timestamped_entry.log_entry.inner_cert(),
+            &leaf_input[inner_start..]
+        );
+        assert_eq!(entry.leaf_hash, crate::merkle::leaf_hash(&leaf_input));
+    }
+}
+
+#[test]
+fn argon2021_chain() {
+    let data = include_str!("../../test_data/argon2021-get-entries?start=0&end=1.json");
+    for entry in GetEntriesItem::parse(data).unwrap() {
+        let chain = entry.chain().unwrap();
+        assert_eq!(
+            chain.pre_certificate.is_some(),
+            matches!(
+                entry.leaf_input.timestamped_entry.log_entry,
+                LogEntry::Precert { .. }
+            )
+        );
+        assert!(!chain.chain.is_empty());
+        for cert in chain.chain.iter().chain(&chain.pre_certificate) {
+            // DER SEQUENCE
+            assert_eq!(cert[0], 0x30);
+        }
+    }
+}
+
+#[test]
+fn bad_chain() {
+    let data = include_str!("../../test_data/argon2021-get-entries?start=0&end=1.json");
+    let mut entry = GetEntriesItem::parse(data).unwrap().remove(0);
+    let extra_data = entry.extra_data.clone();
+    entry.extra_data.push(0);
+    assert!(matches!(
+        entry.chain(),
+        Err(CTParseError::ExtraDataTrailingData)
+    ));
+    entry.extra_data = extra_data[..extra_data.len() - 1].to_vec();
+    assert!(matches!(
+        entry.chain(),
+        Err(CTParseError::ExtraDataTooShort)
+    ));
+}
@@ -6,3 +6,52 @@ fn parse_list() {
     let log_list = serde_json::from_str::<LogList>(include_str!("../log_list.json")).unwrap();
     assert_eq!(log_list.operators[0].name, "Google".to_string());
 }
+
+#[test]
+fn parse_v3_list() {
+    let log_list =
+        serde_json::from_str::<LogList>(include_str!("../test_data/log_list_v3.json")).unwrap();
+    let op = &log_list.operators[0];
+    assert_eq!(op.logs.len(), 3);
+    assert_eq!(op.tiled_logs.len(), 1);
+    assert_eq!(log_list.logs().count(), 4);
+
+    let oak = &op.logs[0];
+    assert_eq!(oak.log_type, Some(LogType::Prod));
+    assert_eq!(
+        oak.previous_operators,
+        vec![PreviousOperator {
+            name: "Old Example CA".to_string(),
+            end_time: "2025-06-01T00:00:00Z".to_string(),
+        }]
+    );
+    assert!(!oak.is_tiled());
+
+    let testtube = &op.logs[1];
+    assert!(testtube.is_test());
+    assert!(matches!(testtube.state, LogState::Qualified { .. }));
+    assert!(testtube.readable());
+
+    let birch = &op.logs[2];
+    assert!(!birch.is_test());
+    assert!(!birch.readable());
+    assert!(!birch.has_active_certs(chrono::Utc::now()));
+
+    let willow = &op.tiled_logs[0];
+    assert!(willow.is_tiled());
+    assert!(willow.url.is_empty());
+    assert_eq!(
+        willow.checkpoint_url().unwrap(),
+        "https://willow2026h1.mon.ct.example.com/checkpoint"
+    );
+    assert_eq!(
+        willow.submission_url.as_deref(),
+        Some("https://willow2026h1.ct.example.com/")
+    );
+
+    // serializing doesn't add fields that aren't in the schema
+    let reserialized: LogList =
+        serde_json::from_str(&serde_json::to_string(&log_list).unwrap()).unwrap();
+    assert_eq!(reserialized, log_list);
+    assert!(!serde_json::to_string(willow).unwrap().contains("\"url\""));
+}
@@ -49,6 +49,8 @@ fn argon2021() {
         log_id: "9lyUL9F3MCIUVBgIMJRWjuNNExkzv98MLyALzE7xZOM=".to_string(),
         key: "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAETeBmZOrzZKo4xYktx9gI2chEce3cw/tbr5xkoQlmhB18aKfsxD+MnILgGNl0FOm0eYGilFVi85wLRIOhK8lxKw==".to_string(),
         url: "https://ct.googleapis.com/logs/argon2021/".to_string(),
+        monitoring_url: None,
+        submission_url: None,
         mmd: 86400,
         state: LogState::Usable {
             timestamp: "2018-06-15T02:30:13Z".to_string(),
@@ -56,7 +58,9 @@ fn argon2021() {
         temporal_interval: Some(TemporalInterval {
             start_inclusive: "2021-01-01T00:00:00Z".to_string(),
             end_exclusive: "2022-01-01T00:00:00Z".to_string(),
-        })
+        }),
+        log_type: None,
+        previous_operators: Vec::new(),
     });
     assert_eq!(
         log.get_sth_url(),
@@ -99,6 +103,8 @@ fn aviator() {
         log_id: "aPaY+B9kgr46jO65KB1M/HFRXWeT1ETRCmesu09P+8Q=".to_string(),
         key: "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAE1/TMabLkDpCjiupacAlP7xNi0I1JYP8bQFAHDG1xhtolSY1l4QgNRzRrvSe8liE+NPWHdjGxfx3JhTsN9x8/6Q==".to_string(),
         url: "https://ct.googleapis.com/aviator/".to_string(),
+        monitoring_url: None,
+        submission_url: None,
         mmd: 86400,
         state: LogState::ReadOnly {
             timestamp: "2016-11-30T13:24:18Z".to_string(),
@@ -108,6 +114,8 @@ fn aviator() {
             }
         },
         temporal_interval: None,
+        log_type: None,
+        previous_operators: Vec::new(),
     });
     assert_eq!(
         validities(&log),
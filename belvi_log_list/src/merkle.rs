@@ -0,0 +1,333 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Merkle tree hashing and proof verification, as described in
+//! [RFC 6962 section 2.1](https://datatracker.ietf.org/doc/html/rfc6962#section-2.1).
+use ring::digest;
+
+pub type Hash = [u8; 32];
+
+/// SHA-256 hash of the concatenation of `parts`.
+#[must_use]
+pub fn sha256(parts: &[&[u8]]) -> Hash {
+    let mut ctx = digest::Context::new(&digest::SHA256);
+    for part in parts {
+        ctx.update(part);
+    }
+    ctx.finish()
+        .as_ref()
+        .try_into()
+        .expect("SHA-256 is 32 bytes")
+}
+
+/// Hash of a leaf in the tree, given the leaf's `MerkleTreeLeaf` encoding.
+#[must_use]
+pub fn leaf_hash(leaf_input: &[u8]) -> Hash {
+    sha256(&[&[0], leaf_input])
+}
+
+/// Hash of an interior node in the tree.
+#[must_use]
+pub fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    sha256(&[&[1], left, right])
+}
+
+/// Root hash of a complete subtree. The number of leaves must be a power of two.
+#[must_use]
+pub fn perfect_subtree_root(leaves: &[Hash]) -> Hash {
+    assert!(
+        leaves.len().is_power_of_two(),
+        "subtree with {} leaves isn't perfect",
+        leaves.len()
+    );
+    let mut level = leaves.to_vec();
+    while level.len() > 1 {
+        level = level
+            .chunks_exact(2)
+            .map(|pair| node_hash(&pair[0], &pair[1]))
+            .collect();
+    }
+    level[0]
+}
+
+/// Splits the inclusive range `start..=end` into the largest complete subtrees that cover it.
+/// Each item is the index of the first leaf of the subtree and the height of the subtree.
+#[must_use]
+pub fn aligned_subtrees(start: u64, end: u64) -> Vec<(u64, u32)> {
+    assert!(start <= end);
+    let mut subtrees = Vec::new();
+    let mut cur = start;
+    while cur <= end {
+        let remaining = end - cur + 1;
+        let mut height = if cur == 0 { 63 } else { cur.trailing_zeros() };
+        while (1 << height) > remaining {
+            height -= 1;
+        }
+        subtrees.push((cur, height));
+        cur += 1 << height;
+    }
+    subtrees
+}
+
+/// Verifies that the complete subtree of height `height` starting at leaf `first_leaf` is
+/// included in a tree of size `tree_size` with root hash `root`. `proof` is the part of the
+/// audit path that is above the subtree: for an audit path returned by `get-proof-by-hash` for
+/// the first leaf of the subtree, the first `height` hashes are skipped. With a height of 0,
+/// this is the inclusion proof verification algorithm from RFC 9162 section 2.1.3.2.
+#[must_use]
+pub fn verify_subtree_inclusion(
+    first_leaf: u64,
+    height: u32,
+    subtree_root: &Hash,
+    tree_size: u64,
+    proof: &[Hash],
+    root: &Hash,
+) -> bool {
+    if first_leaf >= tree_size || first_leaf & ((1 << height) - 1) != 0 {
+        return false;
+    }
+    if first_leaf + (1 << height) > tree_size {
+        // subtree isn't complete
+        return false;
+    }
+    let mut f = first_leaf >> height;
+    let mut s = (tree_size - 1) >> height;
+    let mut r = *subtree_root;
+    for p in proof {
+        if s == 0 {
+            return false;
+        }
+        if f & 1 == 1 || f == s {
+            r = node_hash(p, &r);
+            if f & 1 == 0 {
+                while f & 1 == 0 && f != 0 {
+                    f >>= 1;
+                    s >>= 1;
+                }
+            }
+        } else {
+            r = node_hash(&r, p);
+        }
+        f >>= 1;
+        s >>= 1;
+    }
+    s == 0 && r == *root
+}
+
+/// Verifies that a leaf is included in the tree.
+#[must_use]
+pub fn verify_inclusion(
+    leaf_index: u64,
+    leaf_hash: &Hash,
+    tree_size: u64,
+    proof: &[Hash],
+    root: &Hash,
+) -> bool {
+    verify_subtree_inclusion(leaf_index, 0, leaf_hash, tree_size, proof, root)
+}
+
+/// Verifies that the tree of size `first_size` with root `first_root` is a prefix of the tree of
+/// size `second_size` with root `second_root`, using the algorithm from RFC 9162 section
+/// 2.1.4.2. `proof` is the consistency proof returned by `get-sth-consistency`.
+#[must_use]
+pub fn verify_consistency(
+    first_size: u64,
+    first_root: &Hash,
+    second_size: u64,
+    second_root: &Hash,
+    proof: &[Hash],
+) -> bool {
+    if first_size > second_size {
+        return false;
+    }
+    if first_size == second_size {
+        return proof.is_empty() && first_root == second_root;
+    }
+    if first_size == 0 {
+        // the empty tree is a prefix of every tree
+        return proof.is_empty();
+    }
+    if proof.is_empty() {
+        return false;
+    }
+    let mut proof = proof.to_vec();
+    if first_size.is_power_of_two() {
+        proof.insert(0, *first_root);
+    }
+    let mut f = first_size - 1;
+    let mut s = second_size - 1;
+    while f & 1 == 1 {
+        f >>= 1;
+        s >>= 1;
+    }
+    let mut fr = proof[0];
+    let mut sr = proof[0];
+    for c in &proof[1..] {
+        if s == 0 {
+            return false;
+        }
+        if f & 1 == 1 || f == s {
+            fr = node_hash(c, &fr);
+            sr = node_hash(c, &sr);
+            if f & 1 == 0 {
+                while f & 1 == 0 && f != 0 {
+                    f >>= 1;
+                    s >>= 1;
+                }
+            }
+        } else {
+            sr = node_hash(&sr, c);
+        }
+        f >>= 1;
+        s >>= 1;
+    }
+    fr == *first_root && sr == *second_root && s == 0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn leaves(n: u64) -> Vec<Hash> {
+        (0..n).map(|i| leaf_hash(&i.to_be_bytes())).collect()
+    }
+
+    /// MTH from RFC 6962 section 2.1.
+    fn mth(leaves: &[Hash]) -> Hash {
+        match leaves.len() {
+            0 => sha256(&[]),
+            1 => leaves[0],
+            n => {
+                let k = (n as u64).next_power_of_two() as usize / 2;
+                node_hash(&mth(&leaves[..k]), &mth(&leaves[k..]))
+            }
+        }
+    }
+
+    /// PATH from RFC 6962 section 2.1.1.
+    fn path(m: usize, leaves: &[Hash]) -> Vec<Hash> {
+        let n = leaves.len();
+        if n <= 1 {
+            return Vec::new();
+        }
+        let k = (n as u64).next_power_of_two() as usize / 2;
+        if m < k {
+            let mut p = path(m, &leaves[..k]);
+            p.push(mth(&leaves[k..]));
+            p
+        } else {
+            let mut p = path(m - k, &leaves[k..]);
+            p.push(mth(&leaves[..k]));
+            p
+        }
+    }
+
+    /// SUBPROOF from RFC 6962 section 2.1.2.
+    fn subproof(m: usize, leaves: &[Hash], complete: bool) -> Vec<Hash> {
+        let n = leaves.len();
+        if m == n {
+            return if complete {
+                Vec::new()
+            } else {
+                vec![mth(leaves)]
+            };
+        }
+        let k = (n as u64).next_power_of_two() as usize / 2;
+        if m <= k {
+            let mut p = subproof(m, &leaves[..k], complete);
+            p.push(mth(&leaves[k..]));
+            p
+        } else {
+            let mut p = subproof(m - k, &leaves[k..], false);
+            p.push(mth(&leaves[..k]));
+            p
+        }
+    }
+
+    #[test]
+    fn known_hashes() {
+        assert_eq!(
+            hex::encode(leaf_hash(b"")),
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d"
+        );
+        assert_eq!(
+            hex::encode(mth(&[])),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn inclusion() {
+        for n in 1..40 {
+            let leaves = leaves(n);
+            let root = mth(&leaves);
+            for m in 0..n {
+                let proof = path(m as usize, &leaves);
+                assert!(verify_inclusion(m, &leaves[m as usize], n, &proof, &root));
+                // wrong index
+                assert!(
+                    !verify_inclusion((m + 1) % n, &leaves[m as usize], n, &proof, &root) || n == 1
+                );
+                // wrong leaf
+                assert!(!verify_inclusion(m, &leaf_hash(b"x"), n, &proof, &root));
+            }
+        }
+    }
+
+    #[test]
+    fn subtrees() {
+        assert_eq!(aligned_subtrees(0, 0), vec![(0, 0)]);
+        assert_eq!(aligned_subtrees(0, 6), vec![(0, 2), (4, 1), (6, 0)]);
+        assert_eq!(aligned_subtrees(3, 9), vec![(3, 0), (4, 2), (8, 1)]);
+        for n in 1..40 {
+            let leaves = leaves(n);
+            let root = mth(&leaves);
+            for start in 0..n {
+                for end in start..n {
+                    for (first, height) in aligned_subtrees(start, end) {
+                        let size = 1 << height;
+                        let sub = &leaves[first as usize..(first + size) as usize];
+                        let proof = &path(first as usize, &leaves)[height as usize..];
+                        let sub_root = perfect_subtree_root(sub);
+                        assert!(verify_subtree_inclusion(
+                            first, height, &sub_root, n, proof, &root
+                        ));
+                        assert!(!verify_subtree_inclusion(
+                            first,
+                            height,
+                            &leaf_hash(b"x"),
+                            n,
+                            proof,
+                            &root
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn consistency() {
+        for n in 1..40 {
+            let leaves = leaves(n);
+            let root = mth(&leaves);
+            for m in 1..=n {
+                let first_root = mth(&leaves[..m as usize]);
+                let proof = subproof(m as usize, &leaves, true);
+                assert!(verify_consistency(m, &first_root, n, &root, &proof));
+                if m != n {
+                    // wrong old root
+                    assert!(!verify_consistency(m, &leaf_hash(b"x"), n, &root, &proof));
+                    // wrong new root
+                    assert!(!verify_consistency(
+                        m,
+                        &first_root,
+                        n,
+                        &leaf_hash(b"x"),
+                        &proof
+                    ));
+                    // shrinking tree
+                    assert!(!verify_consistency(n, &root, m, &first_root, &proof));
+                }
+            }
+        }
+    }
+}
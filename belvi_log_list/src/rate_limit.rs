@@ -0,0 +1,348 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Rate limits for requests to logs, so that we stay within the limits published by operators.
+//!
+//! Each log has a token bucket. Limits can be set for a single log, or for all logs of an
+//! operator, in which case the logs of the operator share one bucket. When a log asks us to slow
+//! down, its bucket is paused, so every request to the log waits.
+//!
+//! The limits are read from a JSON file set by the `rate_limits` setting of `belvi_config`, for
+//! example:
+//! ```json
+//! {
+//!     "default": { "requests_per_second": 4, "burst": 8 },
+//!     "operators": { "Google": { "requests_per_second": 10, "burst": 20 } },
+//!     "logs": { "<log ID>": { "requests_per_second": 1, "burst": 1 } }
+//! }
+//! ```
+use crate::{source::LoadError, Log, LogList};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RateLimit {
+    /// Sustained rate of requests.
+    pub requests_per_second: f64,
+    /// Number of requests that can be made at once after being idle.
+    pub burst: u32,
+}
+
+impl RateLimit {
+    /// Checks that requests can be made under the limit.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        if !self.requests_per_second.is_finite() || self.requests_per_second <= 0.0 {
+            Err("requests_per_second must be a positive number")
+        } else if self.burst == 0 {
+            Err("burst must be at least 1")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Default for RateLimit {
+    fn default() -> Self {
+        Self {
+            requests_per_second: 4.0,
+            burst: 8,
+        }
+    }
+}
+
+/// Rate limit configuration. Limits for a log take precedence over limits for its operator.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RateLimits {
+    #[serde(default)]
+    pub default: RateLimit,
+    /// Limits shared by all logs of an operator, by operator name.
+    #[serde(default)]
+    pub operators: HashMap<String, RateLimit>,
+    /// Limits for single logs, by log ID.
+    #[serde(default)]
+    pub logs: HashMap<String, RateLimit>,
+}
+
+impl RateLimits {
+    /// Reads the limits from the file if there is one, or uses the default limits.
+    pub fn load_or_default(path: Option<&Path>) -> Result<Self, LoadError> {
+        match path {
+            Some(path) => Self::load(path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Reads the limits from a JSON file, and checks that they are usable.
+    pub fn load(path: &Path) -> Result<Self, LoadError> {
+        let data = std::fs::read(path).map_err(|err| LoadError::Io {
+            path: path.to_owned(),
+            err,
+        })?;
+        let limits: Self = serde_json::from_slice(&data).map_err(|err| LoadError::Json {
+            path: path.to_owned(),
+            err,
+        })?;
+        limits
+            .validate()
+            .map_err(|reason| LoadError::BadRateLimit {
+                path: path.to_owned(),
+                reason,
+            })?;
+        Ok(limits)
+    }
+
+    /// Checks every limit, returning which one is wrong.
+    pub fn validate(&self) -> Result<(), String> {
+        let named = std::iter::once(("default".to_string(), &self.default))
+            .chain(
+                self.operators
+                    .iter()
+                    .map(|(name, limit)| (format!("operator \"{}\"", name), limit)),
+            )
+            .chain(
+                self.logs
+                    .iter()
+                    .map(|(id, limit)| (format!("log {}", id), limit)),
+            );
+        for (name, limit) in named {
+            limit
+                .validate()
+                .map_err(|err| format!("limit for {}: {}", name, err))?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum BucketKey {
+    Log(String),
+    Operator(String),
+}
+
+#[derive(Debug)]
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+    /// Set when the log asked us to back off.
+    paused_until: Option<Instant>,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            updated: now,
+            paused_until: None,
+        }
+    }
+
+    /// Takes a token, or returns how long to wait before trying again.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        if let Some(until) = self.paused_until {
+            if now < until {
+                return Err(until - now);
+            }
+            self.paused_until = None;
+        }
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens =
+            (self.tokens + elapsed * self.limit.requests_per_second).min(self.limit.burst as f64);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64(
+                (1.0 - self.tokens) / self.limit.requests_per_second,
+            ))
+        }
+    }
+
+    fn pause(&mut self, until: Instant) {
+        self.paused_until = Some(self.paused_until.map_or(until, |cur| cur.max(until)));
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RateLimiter {
+    limits: RateLimits,
+    /// Operator name of each log, by log ID.
+    operators: Mutex<HashMap<String, String>>,
+    buckets: Mutex<HashMap<BucketKey, Bucket>>,
+}
+
+impl RateLimiter {
+    #[must_use]
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    /// Sets which operator runs each log, so that operator limits can be applied.
+    pub fn set_log_list(&self, log_list: &LogList) {
+        let operators = log_list
+            .operators
+            .iter()
+            .flat_map(|op| {
+                op.all_logs()
+                    .map(move |log| (log.log_id.clone(), op.name.clone()))
+            })
+            .collect();
+        *self.operators.lock().unwrap() = operators;
+    }
+
+    fn bucket_key(&self, log: &Log) -> (BucketKey, RateLimit) {
+        if let Some(limit) = self.limits.logs.get(&log.log_id) {
+            return (BucketKey::Log(log.log_id.clone()), *limit);
+        }
+        if let Some(operator) = self.operators.lock().unwrap().get(&log.log_id) {
+            if let Some(limit) = self.limits.operators.get(operator) {
+                return (BucketKey::Operator(operator.clone()), *limit);
+            }
+        }
+        (BucketKey::Log(log.log_id.clone()), self.limits.default)
+    }
+
+    fn try_acquire(&self, log: &Log, now: Instant) -> Result<(), Duration> {
+        let (key, limit) = self.bucket_key(log);
+        self.buckets
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Bucket::new(limit, now))
+            .take(now)
+    }
+
+    /// Waits until a request can be made to the log.
+    pub async fn acquire(&self, log: &Log) {
+        while let Err(wait) = self.try_acquire(log, Instant::now()) {
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Stops requests to the log (or its operator, if they share limits) for a while.
+    pub fn pause(&self, log: &Log, duration: Duration) {
+        let now = Instant::now();
+        let (key, limit) = self.bucket_key(log);
+        self.buckets
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Bucket::new(limit, now))
+            .pause(now + duration);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn test_log(log_id: &str) -> Log {
+        serde_json::from_value(serde_json::json!({
+            "description": "Test log",
+            "log_id": log_id,
+            "key": "",
+            "url": "https://ct.example.com/",
+            "mmd": 86400,
+            "state": { "usable": { "timestamp": "2022-01-01T00:00:00Z" } },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn token_bucket() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(
+            RateLimit {
+                requests_per_second: 2.0,
+                burst: 3,
+            },
+            now,
+        );
+        for _ in 0..3 {
+            bucket.take(now).unwrap();
+        }
+        assert_eq!(bucket.take(now), Err(Duration::from_millis(500)));
+        bucket.take(now + Duration::from_millis(500)).unwrap();
+        // tokens don't build up past the burst size
+        let later = now + Duration::from_secs(60);
+        for _ in 0..3 {
+            bucket.take(later).unwrap();
+        }
+        assert!(bucket.take(later).is_err());
+
+        bucket.pause(later + Duration::from_secs(30));
+        assert_eq!(
+            bucket.take(later + Duration::from_secs(10)),
+            Err(Duration::from_secs(20))
+        );
+        bucket.take(later + Duration::from_secs(30)).unwrap();
+    }
+
+    #[test]
+    fn invalid_limits() {
+        let limits = |json| {
+            serde_json::from_value::<RateLimits>(json)
+                .unwrap()
+                .validate()
+        };
+        limits(serde_json::json!({ "default": { "requests_per_second": 0.5, "burst": 1 } }))
+            .unwrap();
+        limits(serde_json::json!({ "default": { "requests_per_second": 0, "burst": 1 } }))
+            .unwrap_err();
+        limits(serde_json::json!({
+            "operators": { "Google": { "requests_per_second": -1, "burst": 1 } }
+        }))
+        .unwrap_err();
+        limits(serde_json::json!({
+            "logs": { "<log ID>": { "requests_per_second": 1, "burst": 0 } }
+        }))
+        .unwrap_err();
+        let infinite = RateLimit {
+            requests_per_second: f64::INFINITY,
+            burst: 1,
+        };
+        infinite.validate().unwrap_err();
+        RateLimit {
+            requests_per_second: f64::NAN,
+            ..infinite
+        }
+        .validate()
+        .unwrap_err();
+    }
+
+    #[test]
+    fn operator_limits() {
+        let limit = RateLimit {
+            requests_per_second: 1.0,
+            burst: 1,
+        };
+        let limiter = RateLimiter::new(RateLimits {
+            operators: [("Example CA".to_string(), limit)].into_iter().collect(),
+            ..RateLimits::default()
+        });
+        let log_list: LogList =
+            serde_json::from_str(include_str!("../test_data/log_list_v3.json")).unwrap();
+        let now = Instant::now();
+        let oak = log_list.operators[0].logs[0].clone();
+        let willow = log_list.operators[0].tiled_logs[0].clone();
+        // the operator of the logs isn't known yet
+        assert_eq!(limiter.bucket_key(&oak).1, RateLimit::default());
+
+        limiter.set_log_list(&log_list);
+        // logs of the operator share a bucket
+        limiter.try_acquire(&oak, now).unwrap();
+        assert!(limiter.try_acquire(&willow, now).is_err());
+        // other logs use the default limit
+        let other = test_log("AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=");
+        limiter.try_acquire(&other, now).unwrap();
+        limiter.try_acquire(&other, now).unwrap();
+    }
+}
@@ -0,0 +1,247 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Verification of signatures made by logs, as described in
+//! [RFC 6962 section 3.5](https://datatracker.ietf.org/doc/html/rfc6962#section-3.5).
+use crate::{log_data::LogSth, Log};
+use bcder::decode::Constructed;
+use ring::signature;
+use x509_certificate::rfc5280::SubjectPublicKeyInfo;
+
+/// OID 1.2.840.10045.2.1
+const OID_EC_PUBLIC_KEY: &[u8] = &[42, 134, 72, 206, 61, 2, 1];
+/// OID 1.2.840.10045.3.1.7, the `namedCurve` for P-256
+const OID_PRIME256V1: &[u8] = &[42, 134, 72, 206, 61, 3, 1, 7];
+/// OID 1.2.840.113549.1.1.1
+const OID_RSA_ENCRYPTION: &[u8] = &[42, 134, 72, 134, 247, 13, 1, 1, 1];
+
+/// `HashAlgorithm.sha256` from RFC 5246.
+const HASH_SHA256: u8 = 4;
+/// `SignatureAlgorithm.rsa` from RFC 5246.
+const SIG_RSA: u8 = 1;
+/// `SignatureAlgorithm.ecdsa` from RFC 5246.
+const SIG_ECDSA: u8 = 3;
+
+#[derive(Debug)]
+pub enum SignatureError {
+    /// The log's key isn't a valid base64-encoded SubjectPublicKeyInfo.
+    BadKey,
+    /// The log's key isn't a P-256 ECDSA or RSA key.
+    UnsupportedKeyType,
+    /// The signature isn't a valid `DigitallySigned` struct.
+    Malformed,
+    /// The signature uses a different algorithm than the log's key, or isn't SHA-256.
+    WrongAlgorithm { hash: u8, signature: u8 },
+    /// The root hash of the STH isn't valid base64.
+    BadRootHash,
+    /// The signature doesn't match.
+    Invalid,
+}
+
+/// A public key used by a log to sign tree heads and SCTs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogKey {
+    /// Uncompressed P-256 point.
+    EcdsaP256(Vec<u8>),
+    /// DER-encoded `RSAPublicKey`.
+    Rsa(Vec<u8>),
+}
+
+impl LogKey {
+    pub fn from_spki(der: &[u8]) -> Result<Self, SignatureError> {
+        let spki = Constructed::decode(der, bcder::Mode::Der, SubjectPublicKeyInfo::take_from)
+            .map_err(|_| SignatureError::BadKey)?;
+        let key = spki.subject_public_key.octet_bytes().to_vec();
+        match spki.algorithm.algorithm.as_ref() {
+            OID_EC_PUBLIC_KEY => {
+                let curve = spki
+                    .algorithm
+                    .parameters
+                    .as_ref()
+                    .ok_or(SignatureError::BadKey)?
+                    .decode_oid()
+                    .map_err(|_| SignatureError::BadKey)?;
+                if curve.as_ref() == OID_PRIME256V1 {
+                    Ok(Self::EcdsaP256(key))
+                } else {
+                    Err(SignatureError::UnsupportedKeyType)
+                }
+            }
+            OID_RSA_ENCRYPTION => Ok(Self::Rsa(key)),
+            _ => Err(SignatureError::UnsupportedKeyType),
+        }
+    }
+
+    /// Verifies a plain SHA-256 signature of `data`, such as the detached signature of a log list.
+    pub fn verify_sha256(&self, data: &[u8], sig: &[u8]) -> Result<(), SignatureError> {
+        let (algorithm, key): (&dyn signature::VerificationAlgorithm, _) = match self {
+            Self::EcdsaP256(key) => (&signature::ECDSA_P256_SHA256_ASN1, key),
+            Self::Rsa(key) => (&signature::RSA_PKCS1_2048_8192_SHA256, key),
+        };
+        signature::UnparsedPublicKey::new(algorithm, key)
+            .verify(data, sig)
+            .map_err(|_| SignatureError::Invalid)
+    }
+
+    /// Verifies a TLS `DigitallySigned` struct that signs `data`.
+    pub fn verify(&self, data: &[u8], digitally_signed: &[u8]) -> Result<(), SignatureError> {
+        if digitally_signed.len() < 4 {
+            return Err(SignatureError::Malformed);
+        }
+        let hash = digitally_signed[0];
+        let sig_alg = digitally_signed[1];
+        let len = u16::from_be_bytes([digitally_signed[2], digitally_signed[3]]) as usize;
+        let sig = &digitally_signed[4..];
+        if sig.len() != len {
+            return Err(SignatureError::Malformed);
+        }
+        let (algorithm, key): (&dyn signature::VerificationAlgorithm, _) = match (self, sig_alg) {
+            (Self::EcdsaP256(key), SIG_ECDSA) if hash == HASH_SHA256 => {
+                (&signature::ECDSA_P256_SHA256_ASN1, key)
+            }
+            (Self::Rsa(key), SIG_RSA) if hash == HASH_SHA256 => {
+                (&signature::RSA_PKCS1_2048_8192_SHA256, key)
+            }
+            _ => {
+                return Err(SignatureError::WrongAlgorithm {
+                    hash,
+                    signature: sig_alg,
+                })
+            }
+        };
+        signature::UnparsedPublicKey::new(algorithm, key)
+            .verify(data, sig)
+            .map_err(|_| SignatureError::Invalid)
+    }
+}
+
+/// The `TreeHeadSignature` struct that an STH signature covers.
+pub fn sth_signed_data(sth: &LogSth) -> Result<Vec<u8>, SignatureError> {
+    let root_hash = sth.root_hash().map_err(|_| SignatureError::BadRootHash)?;
+    let mut data = Vec::with_capacity(2 + 8 + 8 + 32);
+    data.push(0); // version: v1
+    data.push(1); // signature_type: tree_hash
+    data.extend_from_slice(&sth.timestamp.to_be_bytes());
+    data.extend_from_slice(&sth.tree_size.to_be_bytes());
+    data.extend_from_slice(&root_hash);
+    Ok(data)
+}
+
+impl Log {
+    pub fn public_key(&self) -> Result<LogKey, SignatureError> {
+        let der = base64::decode(&self.key).map_err(|_| SignatureError::BadKey)?;
+        LogKey::from_spki(&der)
+    }
+
+    /// Checks that the STH was signed by this log.
+    pub fn verify_sth(&self, sth: &LogSth) -> Result<(), SignatureError> {
+        let signature =
+            base64::decode(&sth.tree_head_signature).map_err(|_| SignatureError::Malformed)?;
+        self.public_key()?
+            .verify(&sth_signed_data(sth)?, &signature)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_keys::{key_pair, spki};
+    use ring::rand::SystemRandom;
+
+    /// RSA-2048 key, with an STH of size 1000 that it signed.
+    const RSA_SPKI: &str = "MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ8AMIIBCgKCAQEA3wFTXd/u4v4xTrWVDfO5J1heD9E5ceX3CiQGztjUaiTGbxE0rRV5/QZ9d87ZWDqLqtcZpUwc+P4tIIHjF6e4Q0Ge0E4Fp1+0DEeud2oeQJAXkP+LDNw6OUkVPSzDJghIJkmeOc+NazEpALKC/xF6pZ3fRcUW8pzvpjndEZa21/9ZtvevlenU0RFm3Zu3l28Ythq/6UhK6P4aRsocN+Ih0wroM+mM1A6NOPRh6OmSTsJE2ugOtrWE+hVzHz59udS2vyAJHb9xFE/ELxTKBTeTH1qxVGBGjg0ZzR+A17lTWL/lnMRxOB+pWR0yFOoiVeVxOqHUvvStbittXZ4TJJhGyQIDAQAB";
+    const RSA_STH_SIGNATURE: &str = "BAEBAHAT7RekYnYgGVYUmMWroj3k2mFL3Fm13ideF135G/HUPYepR5fC1hDz+ANQygcO9Bcc1BSU8TMba9w0+B3mCX2f5Hqclqjg+OZC7oPt0aZrvPV54p28begPAVwZU7GSlG7qcUb64R2fg+He3rkBXgzrRnn/CMYktDQpOwPJmbxYs6GjteO31QNmdpEGetxz5bRQs4W+8dfO99oEWxmee1ZD2wSS+4MdSvayvJMC1dRQJaCW3rqW8GB7a9veOswLm+2KqUPRn88hnwt5EBSIR+k+3Um4VNKkHkRVn6hZbuGct6/mpcjsd+CAU70Kdr0b4R/IdXUccFHr+cmxGV1cTyE=";
+    /// P-384 key, which CT logs aren't allowed to use.
+    const P384_SPKI: &str = "MHYwEAYHKoZIzj0CAQYFK4EEACIDYgAEdTFP2py1y/K3t8CWHDC2BG3fVmV8E3R5i+An9mnOpuujvTZUC8bNglL/+v4hv4rAHH6S089hy/ObHVS/YZy4oRSGrvIuRSmKWrIcRV0u720/hIDiWLM7Hov2l5kxFEHI";
+
+    fn test_log(key: &signature::EcdsaKeyPair) -> Log {
+        test_log_with_key(&base64::encode(spki(key)))
+    }
+
+    fn test_log_with_key(spki: &str) -> Log {
+        serde_json::from_value(serde_json::json!({
+            "description": "Test log",
+            "log_id": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+            "key": spki,
+            "url": "https://ct.example.com/",
+            "mmd": 86400,
+            "state": { "usable": { "timestamp": "2022-01-01T00:00:00Z" } },
+        }))
+        .unwrap()
+    }
+
+    fn signed_sth(key: &signature::EcdsaKeyPair, tree_size: u64) -> LogSth {
+        let mut sth = LogSth {
+            tree_size,
+            timestamp: 1656633600000,
+            sha256_root_hash: base64::encode([7; 32]),
+            tree_head_signature: String::new(),
+        };
+        let sig = key
+            .sign(&SystemRandom::new(), &sth_signed_data(&sth).unwrap())
+            .unwrap();
+        let sig = sig.as_ref();
+        sth.tree_head_signature = base64::encode(
+            [
+                &[HASH_SHA256, SIG_ECDSA][..],
+                &(sig.len() as u16).to_be_bytes(),
+                sig,
+            ]
+            .concat(),
+        );
+        sth
+    }
+
+    #[test]
+    fn valid_sth() {
+        let key = key_pair();
+        let log = test_log(&key);
+        log.verify_sth(&signed_sth(&key, 1000)).unwrap();
+    }
+
+    #[test]
+    fn modified_sth() {
+        let key = key_pair();
+        let log = test_log(&key);
+        let mut sth = signed_sth(&key, 1000);
+        sth.tree_size += 1;
+        assert!(matches!(log.verify_sth(&sth), Err(SignatureError::Invalid)));
+    }
+
+    #[test]
+    fn wrong_key() {
+        let log = test_log(&key_pair());
+        let sth = signed_sth(&key_pair(), 1000);
+        assert!(matches!(log.verify_sth(&sth), Err(SignatureError::Invalid)));
+    }
+
+    #[test]
+    fn rsa_sth() {
+        let log = test_log_with_key(RSA_SPKI);
+        assert!(matches!(log.public_key(), Ok(LogKey::Rsa(_))));
+        let mut sth = LogSth {
+            tree_size: 1000,
+            timestamp: 1656633600000,
+            sha256_root_hash: base64::encode([7; 32]),
+            tree_head_signature: RSA_STH_SIGNATURE.to_string(),
+        };
+        log.verify_sth(&sth).unwrap();
+        sth.tree_size += 1;
+        assert!(matches!(log.verify_sth(&sth), Err(SignatureError::Invalid)));
+    }
+
+    #[test]
+    fn other_curve() {
+        let der = base64::decode(P384_SPKI).unwrap();
+        assert!(matches!(
+            LogKey::from_spki(&der),
+            Err(SignatureError::UnsupportedKeyType)
+        ));
+    }
+
+    #[test]
+    fn real_log_keys() {
+        // every log in the bundled list should have a key we can use
+        for log in crate::LogList::google().logs() {
+            log.public_key().unwrap();
+        }
+    }
+}
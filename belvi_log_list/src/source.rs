@@ -0,0 +1,217 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Loading the log list from files, so that logs can be added or removed without rebuilding.
+//!
+//! The list can be signed, in which case it is rejected unless its signature is valid. More
+//! lists, which aren't signed, can be added for private or test logs that aren't in the main
+//! list. The files are set by the `log_list` settings of `belvi_config`.
+use crate::{signature::LogKey, signature::SignatureError, LogList};
+use log::warn;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+const DEFAULT_RELOAD_INTERVAL: u64 = 60 * 60;
+
+#[derive(Debug)]
+#[allow(dead_code)] // Debug trait is ignored for dead code analysis, but some fields are only here for better messages
+pub enum LoadError {
+    Io {
+        path: PathBuf,
+        err: std::io::Error,
+    },
+    Json {
+        path: PathBuf,
+        err: serde_json::Error,
+    },
+    /// The key for the log list isn't a PEM or DER SubjectPublicKeyInfo.
+    BadKey(SignatureError),
+    /// The signature of the log list doesn't match.
+    BadSignature(SignatureError),
+    /// A rate limit can't be used, such as one that allows no requests.
+    BadRateLimit {
+        path: PathBuf,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct LogListSource {
+    /// `None` means the bundled list.
+    pub path: Option<PathBuf>,
+    pub signature_path: Option<PathBuf>,
+    pub key: Option<LogKey>,
+    pub extra_paths: Vec<PathBuf>,
+    pub reload_interval: Duration,
+}
+
+impl Default for LogListSource {
+    /// The bundled list, without any extra lists.
+    fn default() -> Self {
+        Self {
+            path: None,
+            signature_path: None,
+            key: None,
+            extra_paths: Vec::new(),
+            reload_interval: Duration::from_secs(DEFAULT_RELOAD_INTERVAL),
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<u8>, LoadError> {
+    fs::read(path).map_err(|err| LoadError::Io {
+        path: path.to_path_buf(),
+        err,
+    })
+}
+
+fn parse(path: &Path, data: &[u8]) -> Result<LogList, LoadError> {
+    serde_json::from_slice(data).map_err(|err| LoadError::Json {
+        path: path.to_path_buf(),
+        err,
+    })
+}
+
+/// Parses a public key that is either PEM or DER encoded.
+pub fn parse_key(data: &[u8]) -> Result<LogKey, LoadError> {
+    let der = match std::str::from_utf8(data) {
+        Ok(text) if text.contains("-----BEGIN") => {
+            let base64: String = text
+                .lines()
+                .filter(|line| !line.starts_with("-----"))
+                .collect();
+            base64::decode(base64.trim()).map_err(|_| LoadError::BadKey(SignatureError::BadKey))?
+        }
+        _ => data.to_vec(),
+    };
+    LogKey::from_spki(&der).map_err(LoadError::BadKey)
+}
+
+impl LogListSource {
+    /// A source for the list at `path` (or the bundled list), reading the key it is signed with
+    /// from `key_path`. The signature defaults to `path` with a `.sig` extension.
+    pub fn new(
+        path: Option<&Path>,
+        signature_path: Option<&Path>,
+        key_path: Option<&Path>,
+        extra_paths: &[PathBuf],
+        reload_interval: Duration,
+    ) -> Result<Self, LoadError> {
+        let key = match key_path {
+            Some(key_path) => Some(parse_key(&read(key_path)?)?),
+            None => None,
+        };
+        Ok(Self {
+            path: path.map(Path::to_path_buf),
+            signature_path: path.map(|path| match signature_path {
+                Some(signature_path) => signature_path.to_path_buf(),
+                None => path.with_extension("sig"),
+            }),
+            key,
+            extra_paths: extra_paths.to_vec(),
+            reload_interval,
+        })
+    }
+
+    /// Reads the log list, checking its signature if there is a key.
+    pub fn load(&self) -> Result<LogList, LoadError> {
+        let mut list = match &self.path {
+            Some(path) => {
+                let data = read(path)?;
+                match (&self.key, &self.signature_path) {
+                    (Some(key), Some(sig_path)) => key
+                        .verify_sha256(&data, &read(sig_path)?)
+                        .map_err(LoadError::BadSignature)?,
+                    (Some(_), None) => {
+                        return Err(LoadError::BadSignature(SignatureError::Malformed))
+                    }
+                    (None, _) => warn!("Signature of log list {:?} isn't being checked", path),
+                }
+                parse(path, &data)?
+            }
+            None => LogList::google(),
+        };
+        for path in &self.extra_paths {
+            list.merge(parse(path, &read(path)?)?);
+        }
+        Ok(list)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_keys;
+    use ring::{rand::SystemRandom, signature::EcdsaKeyPair};
+
+    /// A directory for the files of one test.
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("belvi-log-list-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn key_pair() -> (EcdsaKeyPair, String) {
+        let key = test_keys::key_pair();
+        let pem = format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----\n",
+            base64::encode(test_keys::spki(&key))
+        );
+        (key, pem)
+    }
+
+    fn signed_source(name: &str, list: &[u8]) -> LogListSource {
+        let dir = test_dir(name);
+        let (key, pem) = key_pair();
+        let sig = key.sign(&SystemRandom::new(), list).unwrap();
+        fs::write(dir.join("log_list.json"), list).unwrap();
+        fs::write(dir.join("log_list.sig"), sig.as_ref()).unwrap();
+        LogListSource {
+            path: Some(dir.join("log_list.json")),
+            signature_path: Some(dir.join("log_list.sig")),
+            key: Some(parse_key(pem.as_bytes()).unwrap()),
+            ..LogListSource::default()
+        }
+    }
+
+    #[test]
+    fn signed_list() {
+        let list = include_bytes!("../test_data/log_list_v3.json");
+        let source = signed_source("signed", list);
+        assert_eq!(source.load().unwrap().logs().count(), 4);
+
+        // the list changed after it was signed
+        let mut modified = list.to_vec();
+        modified[10] ^= 1;
+        fs::write(source.path.as_ref().unwrap(), modified).unwrap();
+        assert!(matches!(source.load(), Err(LoadError::BadSignature(_))));
+
+        // the signature is missing
+        fs::remove_file(source.signature_path.as_ref().unwrap()).unwrap();
+        assert!(matches!(source.load(), Err(LoadError::Io { .. })));
+    }
+
+    #[test]
+    fn extra_lists() {
+        let dir = test_dir("extra");
+        fs::write(
+            dir.join("private.json"),
+            include_bytes!("../test_data/log_list_v3.json"),
+        )
+        .unwrap();
+        let source = LogListSource {
+            // the bundled list is merged with it twice, but logs are only added once
+            extra_paths: vec![dir.join("private.json"), dir.join("private.json")],
+            ..LogListSource::default()
+        };
+        let list = source.load().unwrap();
+        let bundled = LogList::google();
+        assert_eq!(list.logs().count(), bundled.logs().count() + 4);
+        assert_eq!(list.operators.len(), bundled.operators.len() + 1);
+        assert!(list
+            .logs()
+            .any(|log| log.description == "Example 'Willow2026h1'"));
+    }
+}
@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Keys for tests that sign things the way a log or log list operator would.
+use ring::{
+    rand::SystemRandom,
+    signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING},
+};
+
+/// DER encoding of a P-256 SubjectPublicKeyInfo, up to the point itself.
+const P256_SPKI_PREFIX: &str = "3059301306072a8648ce3d020106082a8648ce3d030107034200";
+
+/// Generates a new P-256 key pair.
+pub fn key_pair() -> EcdsaKeyPair {
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &SystemRandom::new())
+        .unwrap();
+    EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref()).unwrap()
+}
+
+/// The DER-encoded SubjectPublicKeyInfo of `key`.
+pub fn spki(key: &EcdsaKeyPair) -> Vec<u8> {
+    [
+        hex::decode(P256_SPKI_PREFIX).unwrap(),
+        key.public_key().as_ref().to_vec(),
+    ]
+    .concat()
+}
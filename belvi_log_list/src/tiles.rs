@@ -0,0 +1,190 @@
+// SPDX-License-Identifier: Apache-2.0
+//! Support for logs that use the [static-ct-api](https://c2sp.org/static-ct-api), which
+//! publishes a signed checkpoint and tiles of entries and hashes instead of the RFC 6962 JSON
+//! API.
+use crate::{
+    log_data::{
+        take_length_prefixed, CTParseError, GetEntriesItem, LogSth, MerkleTreeLeaf,
+        TimestampedEntry,
+    },
+    merkle,
+};
+use std::collections::HashMap;
+
+/// Number of entries in a full tile.
+pub const TILE_WIDTH: u64 = 256;
+/// Number of Merkle tree levels covered by one level of hash tiles.
+pub const TILE_HEIGHT: u32 = 8;
+
+/// Encodes a tile index as a path, as described in the
+/// [tlog-tiles spec](https://c2sp.org/tlog-tiles): `1234067` becomes `x001/x234/067`.
+fn encode_index(index: u64) -> String {
+    let digits = index.to_string();
+    let padded = format!("{}{}", "0".repeat((3 - digits.len() % 3) % 3), digits);
+    let groups: Vec<&str> = padded
+        .as_bytes()
+        .chunks(3)
+        .map(|group| std::str::from_utf8(group).unwrap())
+        .collect();
+    let (last, rest) = groups.split_last().unwrap();
+    rest.iter()
+        .map(|group| format!("x{}/", group))
+        .chain(std::iter::once(last.to_string()))
+        .collect()
+}
+
+/// Path of a tile, relative to the monitoring prefix of the log. `level` is `None` for data tiles.
+#[must_use]
+pub fn tile_path(level: Option<u32>, index: u64, width: u64) -> String {
+    let level = match level {
+        Some(level) => level.to_string(),
+        None => "data".to_string(),
+    };
+    if width == TILE_WIDTH {
+        format!("tile/{}/{}", level, encode_index(index))
+    } else {
+        format!("tile/{}/{}.p/{}", level, encode_index(index), width)
+    }
+}
+
+/// Width of the tile with the given index, at a level of the tree that has `count` entries.
+#[must_use]
+pub fn tile_width(index: u64, count: u64) -> u64 {
+    count.saturating_sub(index * TILE_WIDTH).min(TILE_WIDTH)
+}
+
+/// An entry in a data tile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TileLeaf {
+    /// `MerkleTreeLeaf` encoding of the entry.
+    pub leaf_input: Vec<u8>,
+    /// The precertificate, if this is a precert entry.
+    pub pre_certificate: Option<Vec<u8>>,
+    /// SHA-256 hashes of each certificate in the chain.
+    pub chain: Vec<merkle::Hash>,
+}
+
+impl TileLeaf {
+    /// Converts the leaf to the form returned by `get-entries`, given the certificates in the chain.
+    pub fn to_entry(
+        &self,
+        issuers: &HashMap<merkle::Hash, Vec<u8>>,
+    ) -> Result<GetEntriesItem, CTParseError> {
+        fn push_cert(v: &mut Vec<u8>, cert: &[u8]) {
+            v.extend_from_slice(&(cert.len() as u32).to_be_bytes()[1..]);
+            v.extend_from_slice(cert);
+        }
+        let mut chain = Vec::new();
+        for fingerprint in &self.chain {
+            let issuer = issuers
+                .get(fingerprint)
+                .ok_or(CTParseError::MissingIssuer(*fingerprint))?;
+            push_cert(&mut chain, issuer);
+        }
+        let mut extra_data = Vec::new();
+        if let Some(pre_certificate) = &self.pre_certificate {
+            push_cert(&mut extra_data, pre_certificate);
+        }
+        push_cert(&mut extra_data, &chain);
+        Ok(GetEntriesItem {
+            leaf_input: MerkleTreeLeaf::parse(&self.leaf_input)?,
+            extra_data,
+            leaf_hash: merkle::leaf_hash(&self.leaf_input),
+        })
+    }
+}
+
+/// Parses the entries in a data tile.
+pub fn parse_data_tile(mut v: &[u8]) -> Result<Vec<TileLeaf>, CTParseError> {
+    let mut leaves = Vec::with_capacity(TILE_WIDTH as usize);
+    while !v.is_empty() {
+        let (entry, mut pos) = TimestampedEntry::parse_prefix(v)?;
+        let leaf_input = [&[0, 0], &v[..pos]].concat(); // version v1, timestamped_entry
+        let pre_certificate = match entry.log_entry {
+            crate::log_data::LogEntry::X509(_) => None,
+            crate::log_data::LogEntry::Precert { .. } => {
+                let (cert, len) = take_length_prefixed(&v[pos..], 3)?;
+                pos += len;
+                Some(cert.to_vec())
+            }
+        };
+        let (fingerprints, len) = take_length_prefixed(&v[pos..], 2)?;
+        pos += len;
+        leaves.push(TileLeaf {
+            leaf_input,
+            pre_certificate,
+            chain: parse_hash_tile(fingerprints)?,
+        });
+        v = &v[pos..];
+    }
+    Ok(leaves)
+}
+
+/// Parses the hashes in a hash tile, or a list of issuer fingerprints.
+pub fn parse_hash_tile(v: &[u8]) -> Result<Vec<merkle::Hash>, CTParseError> {
+    let hashes = v.chunks_exact(32);
+    if !hashes.remainder().is_empty() {
+        return Err(CTParseError::HashWrongLength);
+    }
+    Ok(hashes.map(|hash| hash.try_into().unwrap()).collect())
+}
+
+/// A checkpoint, in the [signed note](https://c2sp.org/signed-note) format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Checkpoint {
+    pub origin: String,
+    pub tree_size: u64,
+    /// Base64-encoded root hash.
+    pub root_hash: String,
+    /// Key names and signatures.
+    pub signatures: Vec<(String, Vec<u8>)>,
+}
+
+impl Checkpoint {
+    pub fn parse(text: &str) -> Result<Self, CTParseError> {
+        let (body, sigs) = text
+            .split_once("\n\n")
+            .ok_or(CTParseError::CheckpointMalformed)?;
+        let mut lines = body.lines();
+        let mut next_line = || lines.next().ok_or(CTParseError::CheckpointMalformed);
+        let origin = next_line()?.to_string();
+        let tree_size = next_line()?
+            .parse()
+            .map_err(|_| CTParseError::CheckpointMalformed)?;
+        let root_hash = next_line()?.to_string();
+        let mut signatures = Vec::new();
+        for line in sigs.lines() {
+            let (name, sig) = line
+                .strip_prefix("\u{2014} ")
+                .and_then(|line| line.split_once(' '))
+                .ok_or(CTParseError::CheckpointMalformed)?;
+            let sig = base64::decode(sig).map_err(CTParseError::Base64Error)?;
+            signatures.push((name.to_string(), sig));
+        }
+        Ok(Self {
+            origin,
+            tree_size,
+            root_hash,
+            signatures,
+        })
+    }
+
+    /// Converts the checkpoint to an STH, using the log's signature. The signature in the
+    /// returned STH can be checked with [`crate::Log::verify_sth`].
+    pub fn to_sth(&self) -> Result<LogSth, CTParseError> {
+        // the log signs with its origin as the key name, and the signature is a key ID, the
+        // timestamp, and a TLS DigitallySigned struct
+        let sig = self
+            .signatures
+            .iter()
+            .find(|(name, sig)| *name == self.origin && sig.len() > 12)
+            .map(|(_, sig)| sig)
+            .ok_or(CTParseError::CheckpointNoSignature)?;
+        Ok(LogSth {
+            tree_size: self.tree_size,
+            timestamp: u64::from_be_bytes(sig[4..12].try_into().unwrap()),
+            sha256_root_hash: self.root_hash.clone(),
+            tree_head_signature: base64::encode(&sig[12..]),
+        })
+    }
+}
@@ -0,0 +1,177 @@
+// SPDX-License-Identifier: Apache-2.0
+use super::{
+    fetcher::Fetcher,
+    log_data::{LogEntry, LogSth},
+    merkle,
+    tiles::{tile_path, tile_width},
+    Log,
+};
+
+const TREE_SIZE: u64 = 260;
+
+/// A log served from the fixture tiles in `test_data/tiles`, made by `generate.py`.
+fn fixture_log() -> Log {
+    let mut log: Log = serde_json::from_str(include_str!("../test_data/tiles/log.json")).unwrap();
+    log.monitoring_url = Some(format!(
+        "file://{}/test_data/tiles/",
+        env!("CARGO_MANIFEST_DIR")
+    ));
+    log
+}
+
+#[test]
+fn paths() {
+    assert_eq!(tile_path(None, 0, 256), "tile/data/000");
+    assert_eq!(tile_path(Some(0), 1, 5), "tile/0/001.p/5");
+    assert_eq!(tile_path(Some(2), 1234067, 256), "tile/2/x001/x234/067");
+    assert_eq!(tile_path(None, 1000, 256), "tile/data/x001/000");
+    assert_eq!(tile_width(0, 300), 256);
+    assert_eq!(tile_width(1, 300), 44);
+    assert_eq!(tile_width(1, 512), 256);
+}
+
+#[tokio::test]
+async fn checkpoint() {
+    let log = fixture_log();
+    assert!(log.is_tiled());
+    let sth = Fetcher::new().fetch_sth(&log).await.unwrap();
+    assert_eq!(sth.tree_size, TREE_SIZE);
+    assert_eq!(sth.timestamp, 1656633600000 + TREE_SIZE);
+
+    let mut other_log = log.clone();
+    // Argon2021's key
+    other_log.key = "MFkwEwYHKoZIzj0CAQYIKoZIzj0DAQcDQgAETeBmZOrzZKo4xYktx9gI2chEce3cw/tbr5xkoQlmhB18aKfsxD+MnILgGNl0FOm0eYGilFVi85wLRIOhK8lxKw==".to_string();
+    assert!(Fetcher::new().fetch_sth(&other_log).await.is_err());
+}
+
+/// Splits data into the items of a list of 24-bit length-prefixed items.
+fn split_u24(mut v: &[u8]) -> Vec<&[u8]> {
+    let mut items = Vec::new();
+    while !v.is_empty() {
+        let len = u32::from_be_bytes([0, v[0], v[1], v[2]]) as usize;
+        items.push(&v[3..3 + len]);
+        v = &v[3 + len..];
+    }
+    items
+}
+
+#[tokio::test]
+async fn entries() {
+    let log = fixture_log();
+    let fetcher = Fetcher::new();
+    // only entries from one tile are returned at a time
+    let entries = fetcher
+        .fetch_tiled_entries(&log, 250, 259, TREE_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 6);
+    let entries = fetcher
+        .fetch_tiled_entries(&log, 256, 300, TREE_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 4);
+
+    let entries = fetcher
+        .fetch_tiled_entries(&log, 0, 9, TREE_SIZE)
+        .await
+        .unwrap();
+    assert_eq!(entries.len(), 10);
+    for (idx, entry) in (0u64..).zip(&entries) {
+        let timestamped_entry = &entry.leaf_input.timestamped_entry;
+        assert_eq!(timestamped_entry.timestamp, 1656633600000 + idx);
+        // leaf_index extension
+        assert_eq!(timestamped_entry.extensions.0[3..], idx.to_be_bytes()[3..]);
+        // the entry bytes are the certificate followed by empty extensions, like get-entries
+        assert!(timestamped_entry.log_entry.inner_cert().ends_with(&[0, 0]));
+        let extra_data = split_u24(&entry.extra_data);
+        let chain = if idx % 5 == 0 {
+            assert!(matches!(
+                timestamped_entry.log_entry,
+                LogEntry::Precert { .. }
+            ));
+            // precertificate, then the chain
+            assert_eq!(extra_data.len(), 2);
+            extra_data[1]
+        } else {
+            assert!(matches!(timestamped_entry.log_entry, LogEntry::X509(_)));
+            assert_eq!(extra_data.len(), 1);
+            extra_data[0]
+        };
+        // intermediate and root
+        assert_eq!(split_u24(chain).len(), 2);
+    }
+}
+
+#[tokio::test]
+async fn inclusion() {
+    let log = fixture_log();
+    let fetcher = Fetcher::new();
+    let sth: LogSth = fetcher.fetch_sth(&log).await.unwrap();
+    let root = sth.root_hash().unwrap();
+    let mut leaves = fetcher
+        .fetch_tiled_entries(&log, 0, 255, TREE_SIZE)
+        .await
+        .unwrap();
+    leaves.extend(
+        fetcher
+            .fetch_tiled_entries(&log, 256, 259, TREE_SIZE)
+            .await
+            .unwrap(),
+    );
+    let leaves: Vec<merkle::Hash> = leaves.iter().map(|entry| entry.leaf_hash).collect();
+    for (start, end) in [(0, 255), (256, 259), (3, 9), (100, 258), (259, 259)] {
+        for (first_leaf, height) in merkle::aligned_subtrees(start, end) {
+            let subtree_root = merkle::perfect_subtree_root(
+                &leaves[first_leaf as usize..(first_leaf + (1 << height)) as usize],
+            );
+            let proof = fetcher
+                .fetch_tiled_subtree_proof(&log, first_leaf, height, TREE_SIZE)
+                .await
+                .unwrap();
+            assert!(merkle::verify_subtree_inclusion(
+                first_leaf,
+                height,
+                &subtree_root,
+                TREE_SIZE,
+                &proof,
+                &root
+            ));
+            assert!(!merkle::verify_subtree_inclusion(
+                first_leaf,
+                height,
+                &merkle::leaf_hash(b"x"),
+                TREE_SIZE,
+                &proof,
+                &root
+            ));
+        }
+    }
+    assert_eq!(
+        fetcher
+            .fetch_tiled_root(&log, TREE_SIZE, TREE_SIZE)
+            .await
+            .unwrap(),
+        root
+    );
+    // the root of a smaller tree is the root of its complete subtrees, from right to left
+    let mut subtree_roots: Vec<merkle::Hash> = merkle::aligned_subtrees(0, 99)
+        .into_iter()
+        .map(|(first_leaf, height)| {
+            merkle::perfect_subtree_root(
+                &leaves[first_leaf as usize..(first_leaf + (1 << height)) as usize],
+            )
+        })
+        .collect();
+    let last = subtree_roots.pop().unwrap();
+    let old_root = subtree_roots
+        .iter()
+        .rev()
+        .fold(last, |acc, node| merkle::node_hash(node, &acc));
+    assert_eq!(
+        fetcher
+            .fetch_tiled_root(&log, 100, TREE_SIZE)
+            .await
+            .unwrap(),
+        old_root
+    );
+}
@@ -0,0 +1,170 @@
+#!/usr/bin/env python3
+# SPDX-License-Identifier: Apache-2.0
+"""Generates a small static-ct-api log in this directory, for tests.
+
+The log has 260 entries, so it has one full data tile and one partial data tile. Every fifth
+entry is a precertificate. Requires the `cryptography` package."""
+import base64
+import datetime
+import hashlib
+import json
+import os
+import shutil
+import struct
+
+from cryptography import x509
+from cryptography.hazmat.primitives import hashes, serialization
+from cryptography.hazmat.primitives.asymmetric import ec
+from cryptography.x509.oid import NameOID
+
+TREE_SIZE = 260
+ORIGIN = "ct.example.com/tiles"
+TIMESTAMP = 1656633600000
+DIR = os.path.dirname(os.path.abspath(__file__))
+NOT_BEFORE = datetime.datetime(2022, 7, 1)
+NOT_AFTER = datetime.datetime(2022, 10, 1)
+POISON = x509.ObjectIdentifier("1.3.6.1.4.1.11129.2.4.3")
+
+
+def sha256(data):
+    return hashlib.sha256(data).digest()
+
+
+def name(cn):
+    return x509.Name([x509.NameAttribute(NameOID.COMMON_NAME, cn)])
+
+
+def cert(subject, issuer, key, issuer_key, serial, extensions=()):
+    builder = (
+        x509.CertificateBuilder()
+        .subject_name(name(subject))
+        .issuer_name(name(issuer))
+        .public_key(key.public_key())
+        .serial_number(serial)
+        .not_valid_before(NOT_BEFORE)
+        .not_valid_after(NOT_AFTER)
+    )
+    for ext, critical in extensions:
+        builder = builder.add_extension(ext, critical)
+    return builder.sign(issuer_key, hashes.SHA256())
+
+
+def der(c):
+    return c.public_bytes(serialization.Encoding.DER)
+
+
+def u16(data):
+    return struct.pack(">H", len(data)) + data
+
+
+def u24(data):
+    return struct.pack(">I", len(data))[1:] + data
+
+
+def mth(hashes_):
+    if len(hashes_) == 1:
+        return hashes_[0]
+    k = 1
+    while k * 2 < len(hashes_):
+        k *= 2
+    return sha256(b"\x01" + mth(hashes_[:k]) + mth(hashes_[k:]))
+
+
+def tile_path(level, index, width):
+    digits = str(index).zfill((len(str(index)) + 2) // 3 * 3)
+    groups = [digits[i : i + 3] for i in range(0, len(digits), 3)]
+    path = "/".join(["x" + g for g in groups[:-1]] + [groups[-1]])
+    suffix = "" if width == 256 else ".p/%d" % width
+    return os.path.join(DIR, "tile", level, path + suffix)
+
+
+def write(path, data):
+    os.makedirs(os.path.dirname(path), exist_ok=True)
+    with open(path, "wb") as f:
+        f.write(data)
+
+
+def main():
+    shutil.rmtree(os.path.join(DIR, "tile"), ignore_errors=True)
+    shutil.rmtree(os.path.join(DIR, "issuer"), ignore_errors=True)
+    root_key = ec.generate_private_key(ec.SECP256R1())
+    int_key = ec.generate_private_key(ec.SECP256R1())
+    leaf_key = ec.generate_private_key(ec.SECP256R1())
+    root = der(cert("Belvi Test Root", "Belvi Test Root", root_key, root_key, 1))
+    intermediate = der(cert("Belvi Test Intermediate", "Belvi Test Root", int_key, root_key, 2))
+    chain = [intermediate, root]
+    for issuer in chain:
+        write(os.path.join(DIR, "issuer", sha256(issuer).hex()), issuer)
+    int_key_hash = sha256(
+        int_key.public_key().public_bytes(
+            serialization.Encoding.DER, serialization.PublicFormat.SubjectPublicKeyInfo
+        )
+    )
+
+    leaf_hashes = []
+    data = b""
+    for idx in range(TREE_SIZE):
+        domain = "e%d.example.com" % idx
+        san = (x509.SubjectAlternativeName([x509.DNSName(domain)]), False)
+        extensions = b"\x00" + u16(idx.to_bytes(5, "big"))  # leaf_index
+        if idx % 5 == 0:
+            poison = (x509.UnrecognizedExtension(POISON, b"\x05\x00"), True)
+            precert = cert(domain, "Belvi Test Intermediate", leaf_key, int_key, 1000 + idx, [san, poison])
+            tbs = cert(domain, "Belvi Test Intermediate", leaf_key, int_key, 1000 + idx, [san])
+            entry = (
+                struct.pack(">QH", TIMESTAMP + idx, 1)
+                + int_key_hash
+                + u24(tbs.tbs_certificate_bytes)
+                + u16(extensions)
+            )
+            tile_entry = entry + u24(der(precert))
+        else:
+            leaf = der(cert(domain, "Belvi Test Intermediate", leaf_key, int_key, 1000 + idx, [san]))
+            entry = struct.pack(">QH", TIMESTAMP + idx, 0) + u24(leaf) + u16(extensions)
+            tile_entry = entry
+        data += tile_entry + u16(b"".join(sha256(c) for c in chain))
+        leaf_hashes.append(sha256(b"\x00\x00\x00" + entry))
+        if (idx + 1) % 256 == 0 or idx + 1 == TREE_SIZE:
+            write(tile_path("data", idx // 256, (idx % 256) + 1), data)
+            data = b""
+
+    level = 0
+    while TREE_SIZE >> (8 * level):
+        count = TREE_SIZE >> (8 * level)
+        nodes = [
+            mth(leaf_hashes[i << (8 * level) : (i + 1) << (8 * level)]) for i in range(count)
+        ]
+        for index in range(0, count, 256):
+            tile = nodes[index : index + 256]
+            write(tile_path(str(level), index // 256, len(tile)), b"".join(tile))
+        level += 1
+
+    log_key = ec.generate_private_key(ec.SECP256R1())
+    spki = log_key.public_key().public_bytes(
+        serialization.Encoding.DER, serialization.PublicFormat.SubjectPublicKeyInfo
+    )
+    root_hash = mth(leaf_hashes)
+    signed = struct.pack(">BBQQ", 0, 1, TIMESTAMP + TREE_SIZE, TREE_SIZE) + root_hash
+    sig = log_key.sign(signed, ec.ECDSA(hashes.SHA256()))
+    key_id = sha256(ORIGIN.encode() + b"\n\x05" + sha256(spki))[:4]
+    note_sig = key_id + struct.pack(">Q", TIMESTAMP + TREE_SIZE) + b"\x04\x03" + u16(sig)
+    checkpoint = "%s\n%d\n%s\n\n— %s %s\n" % (
+        ORIGIN,
+        TREE_SIZE,
+        base64.b64encode(root_hash).decode(),
+        ORIGIN,
+        base64.b64encode(note_sig).decode(),
+    )
+    write(os.path.join(DIR, "checkpoint"), checkpoint.encode())
+    log = {
+        "description": "Belvi test tiled log",
+        "log_id": base64.b64encode(sha256(spki)).decode(),
+        "key": base64.b64encode(spki).decode(),
+        "mmd": 60,
+        "state": {"usable": {"timestamp": "2022-07-01T00:00:00Z"}},
+    }
+    write(os.path.join(DIR, "log.json"), (json.dumps(log, indent=4) + "\n").encode())
+
+
+if __name__ == "__main__":
+    main()
@@ -25,21 +25,30 @@ fn check(cert: Vec<u8>) {
 async fn main() {
     env_logger::init();
 
-    let mut conn = belvi_cache::Connection::new().await;
-    let keys = conn.cached_cert_key_list().await;
+    // listing every certificate is only possible with Redis
+    let config = belvi_config::get();
+    let redis = belvi_cache::RedisStore::connect(&config.redis_addr, Default::default())
+        .await
+        .unwrap();
+    let store = belvi_cache::open(config).await.unwrap();
 
-    let total = keys.len();
-    for (idx, key) in keys.into_iter().enumerate() {
-        let cert = conn.get_cert(&key[2..]).await.unwrap();
-        if let Err(_) = catch_unwind(|| check(cert)) {
-            panic!("Failed with cert {}", hex::encode(&key[2..]));
-        };
-        if idx % 1000 == 0 {
-            println!(
-                "Checked {:.2}% ({})",
-                ((idx as f64) / (total as f64)) * 100.0,
-                idx
-            );
+    let mut scan = redis.scan_cert_ids();
+    let mut checked = 0;
+    while let Some(ids) = scan.next_batch().await.unwrap() {
+        for id in ids {
+            // the certificate might have been removed since it was scanned
+            let cert = match store.get_cert(&id).await.unwrap() {
+                Some(cert) => cert,
+                None => continue,
+            };
+            if catch_unwind(|| check(cert)).is_err() {
+                panic!("Failed with cert {}", hex::encode(&id));
+            };
+            checked += 1;
+            if checked % 1000 == 0 {
+                println!("Checked {}", checked);
+            }
         }
     }
+    println!("Checked all {} certs", checked);
 }
@@ -1,6 +1,8 @@
 // SPDX-License-Identifier: Apache-2.0
-use super::{ber::render_ber, render_kv_table, Render};
+use super::{ber::render_ber, render_array, render_kv_table, Render};
 
+use belvi_cert::sct::{self, Sct};
+use chrono::TimeZone;
 use x509_certificate::rfc5280::{Extension, Extensions};
 
 impl Render for Extensions {
@@ -29,6 +31,51 @@ impl Render for Extensions {
 impl Render for Extension {
     fn render(&self) -> String {
         // TODO: recognize common extensions
+        if self.id.as_ref() == belvi_cert::SCT_LIST_OID {
+            if let Ok(scts) = sct::parse_sct_list(self.value.to_bytes()) {
+                return render_array(scts.iter().map(Render::render));
+            }
+        }
         render_ber(self.value.to_bytes())
     }
 }
+
+impl Render for Sct {
+    fn render(&self) -> String {
+        render_kv_table(
+            [
+                ("Version".to_string(), format!("v{}", self.version + 1)),
+                ("Log ID".to_string(), (&self.log_id[..]).render()),
+                (
+                    "Timestamp".to_string(),
+                    chrono::Utc.timestamp_millis(self.timestamp as i64).render(),
+                ),
+                ("Extensions".to_string(), (&self.extensions[..]).render()),
+                ("Signature algorithm".to_string(), self.algorithm_name()),
+                ("Signature".to_string(), (&self.signature[..]).render()),
+            ]
+            .into_iter(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_scts() {
+        let cert = x509_certificate::certificate::X509Certificate::from_der(include_bytes!(
+            "../../test_certs/ttw.der"
+        ))
+        .unwrap();
+        let exts = cert.as_ref().tbs_certificate.extensions.as_ref().unwrap();
+        let sct_list = exts
+            .iter()
+            .find(|ext| ext.id.as_ref() == belvi_cert::SCT_LIST_OID)
+            .unwrap();
+        let html = sct_list.render();
+        assert!(html.contains("ECDSA with SHA-256"));
+        assert!(html.contains("<time"));
+    }
+}